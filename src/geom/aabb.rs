@@ -0,0 +1,91 @@
+use crate::raycasting::ray::Ray;
+use crate::types::Vector3f;
+use nalgebra::{Matrix4, Point3};
+
+/// Axis aligned bounding box
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vector3f,
+    pub max: Vector3f,
+}
+
+impl Aabb {
+
+    pub fn new(min: Vector3f, max: Vector3f) -> Aabb {
+        Aabb { min, max }
+    }
+
+    /// the smallest box containing all the points
+    pub fn from_points(points: &[Vector3f]) -> Aabb {
+        let mut aabb = Aabb::new(points[0], points[0]);
+        for point in &points[1..] {
+            aabb.min = aabb.min.inf(point);
+            aabb.max = aabb.max.sup(point);
+        }
+        aabb
+    }
+
+    pub fn surrounding(&self, other: &Aabb) -> Aabb {
+        Aabb::new(self.min.inf(&other.min), self.max.sup(&other.max))
+    }
+
+    pub fn centroid(&self) -> Vector3f {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn extent(&self) -> Vector3f {
+        self.max - self.min
+    }
+
+    /// index of the axis along which the box is largest
+    pub fn longest_axis(&self) -> usize {
+        self.extent().imax()
+    }
+
+    pub fn corners(&self) -> [Vector3f; 8] {
+        let mut corners = [Vector3f::zeros(); 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            *corner = Vector3f::new(
+                if i & 1 == 0 { self.min.x } else { self.max.x },
+                if i & 2 == 0 { self.min.y } else { self.max.y },
+                if i & 4 == 0 { self.min.z } else { self.max.z },
+            );
+        }
+        corners
+    }
+
+    /// bounding box of this box after an affine transform
+    pub fn transformed(&self, transform: &Matrix4<f32>) -> Aabb {
+        let corners : Vec<Vector3f> = self.corners().iter()
+            .map(|corner| transform.transform_point(&Point3::from(*corner)).coords)
+            .collect();
+        Aabb::from_points(&corners)
+    }
+
+    /// slab test, true if the ray crosses the box for some t in (t_min, t_max)
+    pub fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> bool {
+        self.clip(ray, t_min, t_max).is_some()
+    }
+
+    /// the part of (t_min, t_max) where the ray is inside the box, None if it misses it
+    pub fn clip(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<(f32, f32)> {
+        let mut t_min = t_min;
+        let mut t_max = t_max;
+        for axis in 0..3 {
+            let inverse_direction = 1.0 / ray.direction[axis];
+            let mut t0 = (self.min[axis] - ray.origin[axis]) * inverse_direction;
+            let mut t1 = (self.max[axis] - ray.origin[axis]) * inverse_direction;
+            if inverse_direction < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // written so that NaN (ray parallel to a slab face) keeps the previous bound
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+            if t_max < t_min {
+                return None;
+            }
+        }
+        Some((t_min, t_max))
+    }
+
+}
@@ -0,0 +1,202 @@
+use super::aabb::Aabb;
+use super::hittable::Hittable;
+use crate::raycasting::ray::{Ray, HitPoint};
+
+/// leaves with at most this many primitives are not split further
+const MAX_LEAF_SIZE: usize = 2;
+
+#[derive(Clone, Debug)]
+enum BvhNode {
+    /// children are at node_index + 1 and at right_child
+    Interior { aabb: Aabb, right_child: usize, split_axis: usize },
+    /// primitives[first..first + count]
+    Leaf { aabb: Aabb, first: usize, count: usize },
+}
+
+/// Bounding volume hierarchy over a list of hittables.
+/// It stores indices into the list, so the hits report which hittable was hit.
+/// Hittables without a bounding box are tested on every ray
+#[derive(Clone, Debug, Default)]
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    primitives: Vec<usize>,
+    unbounded: Vec<usize>,
+}
+
+impl Bvh {
+
+    pub fn new(hittables: &[Box<dyn Hittable>]) -> Bvh {
+        let mut bvh = Bvh::default();
+        let mut bounded = Vec::new();
+        for (index, hittable) in hittables.iter().enumerate() {
+            match hittable.bounding_box() {
+                Some(aabb) => bounded.push((index, aabb)),
+                None => bvh.unbounded.push(index),
+            }
+        }
+        if !bounded.is_empty() {
+            bvh.build(&mut bounded);
+        }
+        bvh
+    }
+
+    /// split the primitives at the median centroid along the axis where centroids spread most
+    fn build(&mut self, primitives: &mut [(usize, Aabb)]) -> usize {
+        let aabb = primitives[1..].iter()
+            .fold(primitives[0].1, |aabb, (_, primitive_aabb)| aabb.surrounding(primitive_aabb));
+        let node_index = self.nodes.len();
+
+        if primitives.len() <= MAX_LEAF_SIZE {
+            self.nodes.push(BvhNode::Leaf { aabb, first: self.primitives.len(), count: primitives.len() });
+            self.primitives.extend(primitives.iter().map(|(index, _)| *index));
+            return node_index;
+        }
+
+        let centroids : Vec<_> = primitives.iter().map(|(_, aabb)| aabb.centroid()).collect();
+        let split_axis = Aabb::from_points(&centroids).longest_axis();
+        primitives.sort_by(|(_, a), (_, b)| {
+            a.centroid()[split_axis].partial_cmp(&b.centroid()[split_axis]).unwrap()
+        });
+        let middle = primitives.len() / 2;
+
+        self.nodes.push(BvhNode::Interior { aabb, right_child: 0, split_axis });
+        let (left, right) = primitives.split_at_mut(middle);
+        self.build(left);
+        let right_index = self.build(right);
+        if let BvhNode::Interior { right_child, .. } = &mut self.nodes[node_index] {
+            *right_child = right_index;
+        }
+        node_index
+    }
+
+    /// closest hit along the ray, with the index of the hittable, skipping the hits cut out.
+    /// intersection_tests counts both box and hittable tests
+    pub fn closest_hit(&self, hittables: &[Box<dyn Hittable>], ray: &Ray, t_min: f32, t_max: f32,
+            intersection_tests: &mut u64) -> Option<(usize, HitPoint)> {
+        let mut closest_hit = None;
+        let mut t_max = t_max;
+        let mut test_hittable = |index: usize, t_max: &mut f32, closest_hit: &mut Option<(usize, HitPoint)>| {
+            *intersection_tests += 1;
+            if let Some(hitpoint) = hittables[index].opaque_intersection(ray, t_min, *t_max) {
+                *t_max = hitpoint.t;
+                *closest_hit = Some((index, hitpoint));
+            }
+        };
+
+        for index in &self.unbounded {
+            test_hittable(*index, &mut t_max, &mut closest_hit);
+        }
+
+        if self.nodes.is_empty() {
+            return closest_hit;
+        }
+        let mut box_tests = 0;
+        let mut stack = vec![0];
+        while let Some(node_index) = stack.pop() {
+            box_tests += 1;
+            match &self.nodes[node_index] {
+                BvhNode::Leaf { aabb, first, count } => {
+                    if aabb.hit(ray, t_min, t_max) {
+                        for index in &self.primitives[*first..*first + *count] {
+                            test_hittable(*index, &mut t_max, &mut closest_hit);
+                        }
+                    }
+                },
+                BvhNode::Interior { aabb, right_child, split_axis } => {
+                    if aabb.hit(ray, t_min, t_max) {
+                        // visit first the child closer to the ray origin, so t_max shrinks sooner
+                        let left_child = node_index + 1;
+                        if ray.direction[*split_axis] < 0.0 {
+                            stack.push(left_child);
+                            stack.push(*right_child);
+                        } else {
+                            stack.push(*right_child);
+                            stack.push(left_child);
+                        }
+                    }
+                },
+            }
+        }
+        *intersection_tests += box_tests;
+        closest_hit
+    }
+
+    /// fraction of light crossing all the hittables along the ray between t_min and t_max,
+    /// 0 as soon as an opaque one blocks it
+    pub fn transmittance(&self, hittables: &[Box<dyn Hittable>], ray: &Ray, t_min: f32, t_max: f32,
+            intersection_tests: &mut u64) -> f32 {
+        let mut transmittance = 1.0;
+        for index in &self.unbounded {
+            *intersection_tests += 1;
+            transmittance *= hittables[*index].transmittance(ray, t_min, t_max);
+            if transmittance <= 0.0 {
+                return 0.0;
+            }
+        }
+
+        if self.nodes.is_empty() {
+            return transmittance;
+        }
+        let mut stack = vec![0];
+        while let Some(node_index) = stack.pop() {
+            *intersection_tests += 1;
+            match &self.nodes[node_index] {
+                BvhNode::Leaf { aabb, first, count } => {
+                    if aabb.hit(ray, t_min, t_max) {
+                        for index in &self.primitives[*first..*first + *count] {
+                            *intersection_tests += 1;
+                            transmittance *= hittables[*index].transmittance(ray, t_min, t_max);
+                            if transmittance <= 0.0 {
+                                return 0.0;
+                            }
+                        }
+                    }
+                },
+                BvhNode::Interior { aabb, right_child, .. } => {
+                    if aabb.hit(ray, t_min, t_max) {
+                        stack.push(*right_child);
+                        stack.push(node_index + 1);
+                    }
+                },
+            }
+        }
+        transmittance
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geom::sphere::Sphere;
+    use crate::types::Vector3f;
+    use rand::Rng;
+
+    #[test]
+    fn bvh_hits_match_brute_force() {
+        let mut rng = rand::thread_rng();
+        let hittables : Vec<Box<dyn Hittable>> = (0..50).map(|_| {
+            Box::new(Sphere {
+                origin: Vector3f::new(rng.gen_range(-5.0, 5.0), rng.gen_range(-5.0, 5.0), rng.gen_range(-5.0, 5.0)),
+                radius: rng.gen_range(0.1, 1.0),
+                material: None
+            }) as Box<dyn Hittable>
+        }).collect();
+        let bvh = Bvh::new(&hittables);
+
+        for _ in 0..1000 {
+            let ray = Ray {
+                origin: Vector3f::new(rng.gen_range(-8.0, 8.0), rng.gen_range(-8.0, 8.0), 10.0),
+                direction: Vector3f::new(rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0), -1.0),
+                time: 0.0,
+            };
+            let brute_force = hittables.iter().enumerate()
+                .filter_map(|(index, hittable)| hittable.ray_intersaction(&ray, 0.001, f32::INFINITY).map(|hit| (index, hit.t)))
+                .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+            let bvh_hit = bvh.closest_hit(&hittables, &ray, 0.001, f32::INFINITY, &mut 0)
+                .map(|(index, hit)| (index, hit.t));
+            assert_eq!(brute_force, bvh_hit);
+        }
+    }
+}
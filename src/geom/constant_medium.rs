@@ -0,0 +1,176 @@
+use crate::types::{Vector2f, Vector3f};
+use crate::raycasting::ray::{Ray, HitPoint, RayInterval};
+use super::hittable::Hittable;
+use super::aabb::Aabb;
+use super::rand_geom::{free_flight_distance, random_in_unit_sphere};
+use crate::material::material::Material;
+
+/// Volume of constant density filling a closed boundary, as fog or smoke.
+/// Rays cross it or scatter inside, at a free flight distance; the material
+/// is the phase function, usually `Isotropic`
+#[derive(Clone, Debug)]
+pub struct ConstantMedium {
+    boundary: Box<dyn Hittable>,
+    density: f32,
+    pub phase_function: Option<Box<dyn Material>>
+}
+
+impl ConstantMedium {
+
+    pub fn new(boundary: Box<dyn Hittable>, density: f32, phase_function: Option<Box<dyn Material>>) -> ConstantMedium {
+        ConstantMedium { boundary, density, phase_function }
+    }
+
+    /// the parts of the ray inside the boundary. Solids give them all, for other
+    /// hittables they are between the first hit and the next one, so the boundary must be convex
+    fn inside_intervals(&self, ray: &Ray) -> Vec<(f32, f32)> {
+        if let Some(intervals) = self.boundary.ray_intervals(ray) {
+            return intervals.iter().map(|RayInterval { enter, exit }| (enter.t, exit.t)).collect();
+        }
+        let enter = match self.boundary.ray_intersaction(ray, f32::NEG_INFINITY, f32::INFINITY) {
+            Some(enter) => enter.t,
+            None => return Vec::new(),
+        };
+        match self.boundary.ray_intersaction(ray, enter + 0.0001, f32::INFINITY) {
+            Some(exit) => vec![(enter, exit.t)],
+            None => Vec::new(),
+        }
+    }
+
+}
+
+impl Hittable for ConstantMedium {
+
+    /// the point where the ray scatters, with an arbitrary normal
+    fn ray_intersaction(&self, ray: &Ray, t_min:f32, t_max:f32) -> Option<HitPoint> {
+        let direction_norm = ray.direction.norm();
+        let mut distance = free_flight_distance(self.density);
+        for (enter, exit) in self.inside_intervals(ray) {
+            let (enter, exit) = (enter.max(t_min), exit.min(t_max));
+            if enter >= exit {
+                continue;
+            }
+            let length = (exit - enter) * direction_norm;
+            if distance < length {
+                let t = enter + distance / direction_norm;
+                return Some(HitPoint {
+                    t,
+                    position: ray.at(t),
+                    normal: Vector3f::new(1.0, 0.0, 0.0),
+                    front_face: true,
+                    uv: Vector2f::zeros(),
+                    dpdu: Vector3f::new(0.0, 1.0, 0.0),
+                    dpdv: Vector3f::new(0.0, 0.0, 1.0),
+                });
+            }
+            // the distance is memoryless, what is left carries on to the next interval
+            distance -= length;
+        }
+        None
+    }
+
+    fn material(&self) -> &Option<Box<dyn Material>> {
+        &self.phase_function
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.boundary.bounding_box()
+    }
+
+    /// Beer–Lambert law over the length of the ray inside the boundary
+    fn transmittance(&self, ray: &Ray, t_min:f32, t_max:f32) -> f32 {
+        let length : f32 = self.inside_intervals(ray).iter()
+            .map(|(enter, exit)| (exit.min(t_max) - enter.max(t_min)).max(0.0))
+            .sum();
+        (-self.density * length * ray.direction.norm()).exp()
+    }
+
+}
+
+/// Homogeneous fog scattering isotropically. A bounded fog fills its box,
+/// an unbounded one the space between the hittables only
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Fog {
+    pub density: f32,
+    pub albedo: Vector3f,
+    pub bounds: Option<Aabb>,
+}
+
+impl Fog {
+
+    /// the part of (0, t_max) the ray spends in the fog. t_max is infinite for rays
+    /// escaping to the sky, an unbounded fog would let no light in so they skip it
+    fn interval(&self, ray: &Ray, t_max: f32) -> Option<(f32, f32)> {
+        match &self.bounds {
+            Some(bounds) => bounds.clip(ray, 0.0, t_max),
+            None if t_max.is_finite() => Some((0.0, t_max)),
+            None => None,
+        }
+    }
+
+    /// t at which the ray scatters in the fog, if it does before t_max
+    pub fn sample_scattering(&self, ray: &Ray, t_max: f32) -> Option<f32> {
+        let (enter, exit) = self.interval(ray, t_max)?;
+        let t = enter + free_flight_distance(self.density) / ray.direction.norm();
+        if t < exit { Some(t) } else { None }
+    }
+
+    /// fraction of the light crossing the fog along the ray up to t_max
+    pub fn transmittance(&self, ray: &Ray, t_max: f32) -> f32 {
+        match self.interval(ray, t_max) {
+            Some((enter, exit)) => (-self.density * (exit - enter) * ray.direction.norm()).exp(),
+            None => 1.0,
+        }
+    }
+
+    /// attenuation and scattered ray at t
+    pub fn scatter(&self, ray: &Ray, t: f32) -> (Vector3f, Ray) {
+        (self.albedo, Ray { origin: ray.at(t), direction: random_in_unit_sphere(), time: ray.time })
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geom::sphere::Sphere;
+    use crate::geom::quad::Quad;
+
+    #[test]
+    fn medium_transmittance_follows_beer_lambert() {
+        // a unit sphere crossed through its center, 2 units of medium
+        let sphere = Box::new(Sphere { origin: Vector3f::zeros(), radius: 1.0, material: None });
+        let medium = ConstantMedium::new(sphere, 0.5, None);
+        let ray = Ray { origin: Vector3f::new(0.0, 0.0, 5.0), direction: Vector3f::new(0.0, 0.0, -2.0), time: 0.0 };
+        let samples = 20000;
+        let mut crossed = 0;
+        for _ in 0..samples {
+            match medium.ray_intersaction(&ray, 0.001, f32::INFINITY) {
+                Some(hit) => assert!(hit.position.norm() <= 1.0 + 1e-4),
+                None => crossed += 1,
+            }
+        }
+        let transmittance = crossed as f32 / samples as f32;
+        assert!((transmittance - (-1.0f32).exp()).abs() < 0.02);
+        assert!((medium.transmittance(&ray, 0.001, f32::INFINITY) - (-1.0f32).exp()).abs() < 1e-5);
+
+        // an open boundary, without intervals, is crossed between its hits
+        let thin = Box::new(Quad::new(Vector3f::new(-1.0, -1.0, 0.0), Vector3f::new(2.0, 0.0, 0.0), Vector3f::new(0.0, 2.0, 0.0), None));
+        let medium = ConstantMedium::new(thin, 100.0, None);
+        assert!(medium.ray_intersaction(&ray, 0.001, f32::INFINITY).is_none());
+
+        let fog = Fog { density: 1.0, albedo: Vector3f::repeat(0.5), bounds: None };
+        assert!(fog.sample_scattering(&ray, 0.0).is_none());
+        assert!(fog.sample_scattering(&ray, f32::INFINITY).is_none());
+
+        // a bounded fog scatters escaping rays, within its box
+        let bounds = Aabb::new(Vector3f::repeat(-1.0), Vector3f::repeat(1.0));
+        let fog = Fog { density: 100.0, bounds: Some(bounds), ..fog };
+        let t = fog.sample_scattering(&ray, f32::INFINITY).unwrap();
+        assert!((2.0..=3.0).contains(&t));
+        assert!(fog.sample_scattering(&ray, 1.0).is_none());
+        let thin_fog = Fog { density: 0.5, ..fog };
+        assert!((thin_fog.transmittance(&ray, f32::INFINITY) - (-1.0f32).exp()).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,152 @@
+use crate::raycasting::ray::{Ray, HitPoint, RayInterval, SurfaceCrossing};
+use super::hittable::Hittable;
+use super::aabb::Aabb;
+use crate::material::material::Material;
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CsgOperation {
+    Union,
+    Intersection,
+    /// left minus right
+    Difference,
+}
+
+impl CsgOperation {
+
+    fn contains(&self, inside_left: bool, inside_right: bool) -> bool {
+        match self {
+            CsgOperation::Union => inside_left || inside_right,
+            CsgOperation::Intersection => inside_left && inside_right,
+            CsgOperation::Difference => inside_left && !inside_right,
+        }
+    }
+
+}
+
+/// Boolean combination of two solids, hittables that report their `ray_intervals`.
+/// The whole shape has a single material, the ones of the operands are ignored
+#[derive(Clone, Debug)]
+pub struct Csg {
+    pub operation: CsgOperation,
+    pub left: Box<dyn Hittable>,
+    pub right: Box<dyn Hittable>,
+    pub material: Option<Box<dyn Material>>
+}
+
+/// the intervals inside the combination of two lists of sorted, disjoint intervals
+pub fn combine_intervals(operation: CsgOperation, left: &[RayInterval], right: &[RayInterval]) -> Vec<RayInterval> {
+    // (crossing, from the left operand, entering)
+    let mut events : Vec<(&SurfaceCrossing, bool, bool)> = Vec::new();
+    for (intervals, is_left) in [(left, true), (right, false)] {
+        for interval in intervals {
+            events.push((&interval.enter, is_left, true));
+            events.push((&interval.exit, is_left, false));
+        }
+    }
+    events.sort_by(|a, b| a.0.t.partial_cmp(&b.0.t).unwrap());
+
+    let mut combined = Vec::new();
+    let (mut inside_left, mut inside_right, mut inside) = (false, false, false);
+    let mut enter = None;
+    for (crossing, is_left, entering) in events {
+        if is_left {
+            inside_left = entering;
+        } else {
+            inside_right = entering;
+        }
+        let now_inside = operation.contains(inside_left, inside_right);
+        if now_inside == inside {
+            continue;
+        }
+        inside = now_inside;
+        // the surface of the subtracted solid bounds the difference from the other side
+        let crossing = if operation == CsgOperation::Difference && !is_left {
+            crossing.flipped()
+        } else {
+            crossing.clone()
+        };
+        if inside {
+            enter = Some(crossing);
+        } else if let Some(enter) = enter.take() {
+            combined.push(RayInterval { enter, exit: crossing });
+        }
+    }
+    combined
+}
+
+impl Hittable for Csg {
+
+    fn ray_intersaction(&self, ray: &Ray, t_min:f32, t_max:f32) -> Option<HitPoint> {
+        self.ray_intervals(ray)?.iter()
+            .flat_map(|interval| vec![&interval.enter, &interval.exit])
+            .find(|crossing| crossing.t > t_min && crossing.t < t_max)
+            .map(|crossing| crossing.to_hitpoint(ray))
+    }
+
+    fn material(&self) -> &Option<Box<dyn Material>> {
+        &self.material
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        match self.operation {
+            CsgOperation::Union => {
+                Some(self.left.bounding_box()?.surrounding(&self.right.bounding_box()?))
+            },
+            CsgOperation::Intersection => match (self.left.bounding_box(), self.right.bounding_box()) {
+                (Some(left), Some(right)) => Some(Aabb::new(left.min.sup(&right.min), left.max.inf(&right.max))),
+                (left, right) => left.or(right),
+            },
+            CsgOperation::Difference => self.left.bounding_box(),
+        }
+    }
+
+    fn ray_intervals(&self, ray: &Ray) -> Option<Vec<RayInterval>> {
+        let left = self.left.ray_intervals(ray)?;
+        let right = self.right.ray_intervals(ray)?;
+        Some(combine_intervals(self.operation, &left, &right))
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geom::sphere::Sphere;
+    use crate::types::Vector3f;
+
+    fn sphere(x: f32, radius: f32) -> Box<dyn Hittable> {
+        Box::new(Sphere { origin: Vector3f::new(x, 0.0, 0.0), radius, material: None })
+    }
+
+    fn interval_ts(csg: &Csg, ray: &Ray) -> Vec<(f32, f32)> {
+        csg.ray_intervals(ray).unwrap().iter().map(|interval| (interval.enter.t, interval.exit.t)).collect()
+    }
+
+    #[test]
+    fn csg_of_two_spheres() {
+        // along the x axis the spheres cover [-1, 1] and [0.5, 2.5]
+        let ray = Ray { origin: Vector3f::new(-5.0, 0.0, 0.0), direction: Vector3f::new(1.0, 0.0, 0.0), time: 0.0 };
+        let csg = |operation| Csg { operation, left: sphere(0.0, 1.0), right: sphere(1.5, 1.0), material: None };
+
+        assert_eq!(interval_ts(&csg(CsgOperation::Union), &ray), vec![(4.0, 7.5)]);
+        assert_eq!(interval_ts(&csg(CsgOperation::Intersection), &ray), vec![(5.5, 6.0)]);
+        assert_eq!(interval_ts(&csg(CsgOperation::Difference), &ray), vec![(4.0, 5.5)]);
+
+        // the hole left by the right sphere faces the inside of the right sphere
+        let difference = csg(CsgOperation::Difference);
+        let back_ray = Ray { origin: Vector3f::new(5.0, 0.0, 0.0), direction: Vector3f::new(-1.0, 0.0, 0.0), time: 0.0 };
+        let hit = difference.ray_intersaction(&back_ray, 0.001, f32::INFINITY).unwrap();
+        assert!((hit.t - 4.5).abs() < 1e-5);
+        assert!(hit.front_face);
+        assert_eq!(hit.normal, Vector3f::new(1.0, 0.0, 0.0));
+
+        // from inside the union, the first hit is the exit
+        let inside_ray = Ray { origin: Vector3f::new(1.0, 0.0, 0.0), direction: Vector3f::new(1.0, 0.0, 0.0), time: 0.0 };
+        let hit = csg(CsgOperation::Union).ray_intersaction(&inside_ray, 0.001, f32::INFINITY).unwrap();
+        assert!((hit.t - 1.5).abs() < 1e-5);
+        assert!(!hit.front_face);
+    }
+}
@@ -0,0 +1,142 @@
+use crate::types::Vector3f;
+use crate::raycasting::ray::{Ray, HitPoint, RayInterval, SurfaceCrossing};
+use super::hittable::Hittable;
+use super::aabb::Aabb;
+use super::quad::Quad;
+use crate::material::material::Material;
+
+/// Axis aligned box made of six quads facing outwards.
+/// Rotated boxes are obtained wrapping it in a `Transformed`
+#[derive(Clone, Debug)]
+pub struct Cuboid {
+    min: Vector3f,
+    max: Vector3f,
+    sides: Vec<Quad>,
+    pub material: Option<Box<dyn Material>>
+}
+
+impl Cuboid {
+
+    /// box between two opposite corners, in any order
+    pub fn new(a: Vector3f, b: Vector3f, material: Option<Box<dyn Material>>) -> Cuboid {
+        let min = a.inf(&b);
+        let max = a.sup(&b);
+        let dx = Vector3f::new(max.x - min.x, 0.0, 0.0);
+        let dy = Vector3f::new(0.0, max.y - min.y, 0.0);
+        let dz = Vector3f::new(0.0, 0.0, max.z - min.z);
+        let sides = vec![
+            Quad::new(Vector3f::new(min.x, min.y, max.z), dx, dy, None),  // front
+            Quad::new(Vector3f::new(max.x, min.y, max.z), -dz, dy, None), // right
+            Quad::new(Vector3f::new(max.x, min.y, min.z), -dx, dy, None), // back
+            Quad::new(Vector3f::new(min.x, min.y, min.z), dz, dy, None),  // left
+            Quad::new(Vector3f::new(min.x, max.y, max.z), dx, -dz, None), // top
+            Quad::new(Vector3f::new(min.x, min.y, min.z), dx, dz, None),  // bottom
+        ];
+        Cuboid { min, max, sides, material }
+    }
+
+    pub fn sides(&self) -> &[Quad] {
+        &self.sides
+    }
+
+    /// crossing of the side at the min or max of axis, the position being on it
+    fn crossing(&self, t: f32, position: Vector3f, axis: usize, is_max: bool) -> SurfaceCrossing {
+        // order of the sides in new
+        let side = match (axis, is_max) {
+            (2, true) => 0,
+            (0, true) => 1,
+            (2, false) => 2,
+            (0, false) => 3,
+            (1, true) => 4,
+            _ => 5,
+        };
+        let mut outward_normal = Vector3f::zeros();
+        outward_normal[axis] = if is_max { 1.0 } else { -1.0 };
+        let (dpdu, dpdv) = self.sides[side].edges();
+        SurfaceCrossing { t, position, outward_normal, uv: self.sides[side].uv_at(&position), dpdu, dpdv }
+    }
+
+}
+
+impl Hittable for Cuboid {
+
+    fn ray_intersaction(&self, ray: &Ray, t_min:f32, t_max:f32) -> Option<HitPoint> {
+        let mut closest_hit = None;
+        let mut t_max = t_max;
+        for side in &self.sides {
+            if let Some(hitpoint) = side.ray_intersaction(ray, t_min, t_max) {
+                t_max = hitpoint.t;
+                closest_hit = Some(hitpoint);
+            }
+        }
+        closest_hit
+    }
+
+    fn material(&self) -> &Option<Box<dyn Material>> {
+        &self.material
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::new(self.min, self.max))
+    }
+
+    /// slab test over the whole ray line
+    fn ray_intervals(&self, ray: &Ray) -> Option<Vec<RayInterval>> {
+        let mut enter = SurfaceCrossing::at_infinity(f32::NEG_INFINITY);
+        let mut exit = SurfaceCrossing::at_infinity(f32::INFINITY);
+        for axis in 0..3 {
+            if ray.direction[axis] == 0.0 {
+                if ray.origin[axis] < self.min[axis] || ray.origin[axis] > self.max[axis] {
+                    return Some(Vec::new());
+                }
+                continue;
+            }
+            let t_at_min = (self.min[axis] - ray.origin[axis]) / ray.direction[axis];
+            let t_at_max = (self.max[axis] - ray.origin[axis]) / ray.direction[axis];
+            let ((t0, t0_is_max), (t1, t1_is_max)) = if t_at_min < t_at_max {
+                ((t_at_min, false), (t_at_max, true))
+            } else {
+                ((t_at_max, true), (t_at_min, false))
+            };
+            if t0 > enter.t {
+                enter = self.crossing(t0, ray.at(t0), axis, t0_is_max);
+            }
+            if t1 < exit.t {
+                exit = self.crossing(t1, ray.at(t1), axis, t1_is_max);
+            }
+        }
+        if enter.t < exit.t {
+            Some(vec![RayInterval { enter, exit }])
+        } else {
+            Some(Vec::new())
+        }
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cuboid_faces_point_outwards() {
+        let cuboid = Cuboid::new(Vector3f::new(1.0, 1.0, 1.0), Vector3f::new(-1.0, -2.0, -3.0), None);
+        for side in cuboid.sides() {
+            let (u, v) = side.edges();
+            let center = side.corner() + (u + v) * 0.5;
+            let outward = u.cross(&v);
+            let ray = Ray { origin: center + outward, direction: -outward, time: 0.0 };
+            let hit = cuboid.ray_intersaction(&ray, 0.001, f32::INFINITY).unwrap();
+            assert!(hit.front_face);
+            assert!((hit.position - center).norm() < 1e-4);
+            assert!(hit.normal.dot(&outward) > 0.0);
+        }
+
+        // from inside, the far side is hit on its back face
+        let ray = Ray { origin: Vector3f::zeros(), direction: Vector3f::new(0.0, 0.0, 1.0), time: 0.0 };
+        let hit = cuboid.ray_intersaction(&ray, 0.001, f32::INFINITY).unwrap();
+        assert!(!hit.front_face);
+        assert!((hit.t - 1.0).abs() < 1e-5);
+    }
+}
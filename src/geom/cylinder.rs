@@ -0,0 +1,402 @@
+use crate::types::{Vector2f, Vector3f};
+use crate::raycasting::ray::{Ray, HitPoint, RayInterval, SurfaceCrossing};
+use super::hittable::Hittable;
+use super::aabb::Aabb;
+use super::polynomial::solve_quadratic;
+use super::sphere::{around_y_tangent, radial_direction};
+use crate::material::material::Material;
+use std::f32::consts::PI;
+
+/// u of a point around the y axis, starting at -x like the sphere
+fn angle_u(local: &Vector3f) -> f32 {
+    ((-local.z).atan2(local.x) + PI) / (2.0 * PI)
+}
+
+/// the closest of the candidate crossings, whose positions are relative to the base
+fn closest_hit(ray: &Ray, candidates: impl Iterator<Item = SurfaceCrossing>) -> Option<HitPoint> {
+    let closest = candidates.min_by(|a, b| a.t.partial_cmp(&b.t).unwrap())?;
+    Some(SurfaceCrossing { position: ray.at(closest.t), ..closest }.to_hitpoint(ray))
+}
+
+/// crossing of the side going up height, with the radius of the section at the bottom
+fn side_crossing(local: Vector3f, t: f32, outward_normal: Vector3f, radius: f32, height: f32) -> SurfaceCrossing {
+    SurfaceCrossing {
+        t,
+        position: local,
+        outward_normal,
+        uv: Vector2f::new(angle_u(&local), local.y / height),
+        dpdu: around_y_tangent(&local),
+        // a cone narrows by radius over the height, a cylinder by 0
+        dpdv: radial_direction(&local) * -radius + Vector3f::new(0.0, height, 0.0),
+    }
+}
+
+/// crossing of a cap of given radius, uv polar like `Disk`
+fn cap_crossing(local: Vector3f, t: f32, outward_normal: Vector3f, radius: f32) -> SurfaceCrossing {
+    let distance = (local.x * local.x + local.z * local.z).sqrt();
+    SurfaceCrossing {
+        t,
+        position: local,
+        outward_normal,
+        uv: Vector2f::new(angle_u(&local), distance / radius),
+        dpdu: around_y_tangent(&local),
+        dpdv: radial_direction(&local) * radius,
+    }
+}
+
+/// hit with the disk of given radius at height y above base
+fn hit_cap(local_ray: &Ray, y: f32, radius: f32, outward_normal: Vector3f,
+        t_min: f32, t_max: f32) -> Option<SurfaceCrossing> {
+    let t = (y - local_ray.origin.y) / local_ray.direction.y;
+    if !(t > t_min && t < t_max) {
+        return None;
+    }
+    let local = local_ray.at(t);
+    if local.x * local.x + local.z * local.z > radius * radius {
+        return None;
+    }
+    Some(cap_crossing(local, t, outward_normal, radius))
+}
+
+/// Cylinder standing on base along the y axis.
+/// uv go around the axis and up the side; caps, if any, use polar uv like `Disk`
+#[derive(Clone, Debug)]
+pub struct Cylinder {
+    pub base: Vector3f,
+    pub radius: f32,
+    pub height: f32,
+    pub capped: bool,
+    pub material: Option<Box<dyn Material>>
+}
+
+impl Hittable for Cylinder {
+
+    fn ray_intersaction(&self, ray: &Ray, t_min:f32, t_max:f32) -> Option<HitPoint> {
+        let local_ray = Ray { origin: ray.origin - self.base, direction: ray.direction, time: ray.time };
+        let (o, d) = (local_ray.origin, local_ray.direction);
+        // x^2 + z^2 = radius^2
+        let side_roots = solve_quadratic(
+            (d.x * d.x + d.z * d.z) as f64,
+            2.0 * (o.x * d.x + o.z * d.z) as f64,
+            (o.x * o.x + o.z * o.z - self.radius * self.radius) as f64);
+        let side_hits = side_roots.into_iter()
+            .map(|t| t as f32)
+            .filter(|t| *t > t_min && *t < t_max)
+            .filter_map(|t| {
+                let local = local_ray.at(t);
+                if local.y < 0.0 || local.y > self.height {
+                    return None;
+                }
+                let outward_normal = Vector3f::new(local.x, 0.0, local.z) / self.radius;
+                Some(side_crossing(local, t, outward_normal, 0.0, self.height))
+            });
+
+        let cap_hits = if self.capped {
+            vec![
+                hit_cap(&local_ray, 0.0, self.radius, Vector3f::new(0.0, -1.0, 0.0), t_min, t_max),
+                hit_cap(&local_ray, self.height, self.radius, Vector3f::new(0.0, 1.0, 0.0), t_min, t_max),
+            ]
+        } else {
+            Vec::new()
+        };
+        closest_hit(ray, side_hits.chain(cap_hits.into_iter().flatten()))
+    }
+
+    fn material(&self) -> &Option<Box<dyn Material>> {
+        &self.material
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let radius = self.radius.abs();
+        Some(Aabb::new(self.base - Vector3f::new(radius, 0.0, radius),
+            self.base + Vector3f::new(radius, self.height, radius)))
+    }
+
+    /// the infinite cylinder clipped by the slab between the caps, only capped cylinders are solids
+    fn ray_intervals(&self, ray: &Ray) -> Option<Vec<RayInterval>> {
+        if !self.capped {
+            return None;
+        }
+        let local_ray = Ray { origin: ray.origin - self.base, direction: ray.direction, time: ray.time };
+        let (o, d) = (local_ray.origin, local_ray.direction);
+        let side_crossing = |t: f32| {
+            let local = local_ray.at(t);
+            let outward_normal = Vector3f::new(local.x, 0.0, local.z) / self.radius;
+            SurfaceCrossing { position: ray.at(t), ..side_crossing(local, t, outward_normal, 0.0, self.height) }
+        };
+        let cap_crossing = |t: f32, is_top: bool| {
+            let outward_normal = Vector3f::new(0.0, if is_top { 1.0 } else { -1.0 }, 0.0);
+            SurfaceCrossing { position: ray.at(t), ..cap_crossing(local_ray.at(t), t, outward_normal, self.radius) }
+        };
+
+        let a = d.x * d.x + d.z * d.z;
+        let c = o.x * o.x + o.z * o.z - self.radius * self.radius;
+        let (mut enter, mut exit) = if a == 0.0 {
+            // parallel to the axis
+            if c > 0.0 {
+                return Some(Vec::new());
+            }
+            (SurfaceCrossing::at_infinity(f32::NEG_INFINITY), SurfaceCrossing::at_infinity(f32::INFINITY))
+        } else {
+            let mut roots = solve_quadratic(a as f64, 2.0 * (o.x * d.x + o.z * d.z) as f64, c as f64);
+            if roots.len() < 2 {
+                return Some(Vec::new());
+            }
+            roots.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            (side_crossing(roots[0] as f32), side_crossing(roots[1] as f32))
+        };
+
+        if d.y == 0.0 {
+            if o.y < 0.0 || o.y > self.height {
+                return Some(Vec::new());
+            }
+        } else {
+            let t_bottom = -o.y / d.y;
+            let t_top = (self.height - o.y) / d.y;
+            let (t0, t0_is_top, t1, t1_is_top) = if t_bottom < t_top {
+                (t_bottom, false, t_top, true)
+            } else {
+                (t_top, true, t_bottom, false)
+            };
+            if t0 > enter.t {
+                enter = cap_crossing(t0, t0_is_top);
+            }
+            if t1 < exit.t {
+                exit = cap_crossing(t1, t1_is_top);
+            }
+        }
+        if enter.t < exit.t {
+            Some(vec![RayInterval { enter, exit }])
+        } else {
+            Some(Vec::new())
+        }
+    }
+
+}
+
+/// Cone with its base disk on base and its apex height above it, along the y axis.
+/// uv go around the axis and up the side; the cap, if any, uses polar uv like `Disk`
+#[derive(Clone, Debug)]
+pub struct Cone {
+    pub base: Vector3f,
+    /// radius of the base
+    pub radius: f32,
+    pub height: f32,
+    pub capped: bool,
+    pub material: Option<Box<dyn Material>>
+}
+
+impl Hittable for Cone {
+
+    fn ray_intersaction(&self, ray: &Ray, t_min:f32, t_max:f32) -> Option<HitPoint> {
+        let local_ray = Ray { origin: ray.origin - self.base, direction: ray.direction, time: ray.time };
+        let (o, d) = (local_ray.origin, local_ray.direction);
+        // x^2 + z^2 = (slope (height - y))^2
+        let slope2 = (self.radius / self.height).powi(2);
+        let below_apex = self.height - o.y;
+        let side_roots = solve_quadratic(
+            (d.x * d.x + d.z * d.z - slope2 * d.y * d.y) as f64,
+            2.0 * (o.x * d.x + o.z * d.z + slope2 * below_apex * d.y) as f64,
+            (o.x * o.x + o.z * o.z - slope2 * below_apex * below_apex) as f64);
+        let side_hits = side_roots.into_iter()
+            .map(|t| t as f32)
+            .filter(|t| *t > t_min && *t < t_max)
+            .filter_map(|t| {
+                let local = local_ray.at(t);
+                // the equation also describes the mirrored cone above the apex
+                if local.y < 0.0 || local.y > self.height {
+                    return None;
+                }
+                let gradient = Vector3f::new(local.x, slope2 * (self.height - local.y), local.z);
+                let outward_normal = if gradient == Vector3f::zeros() {
+                    Vector3f::new(0.0, 1.0, 0.0)
+                } else {
+                    gradient.normalize()
+                };
+                Some(side_crossing(local, t, outward_normal, self.radius, self.height))
+            });
+
+        let cap_hit = if self.capped {
+            hit_cap(&local_ray, 0.0, self.radius, Vector3f::new(0.0, -1.0, 0.0), t_min, t_max)
+        } else {
+            None
+        };
+        closest_hit(ray, side_hits.chain(cap_hit))
+    }
+
+    fn material(&self) -> &Option<Box<dyn Material>> {
+        &self.material
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let radius = self.radius.abs();
+        Some(Aabb::new(self.base - Vector3f::new(radius, 0.0, radius),
+            self.base + Vector3f::new(radius, self.height, radius)))
+    }
+
+    /// the double cone of the side clipped by the slab between the base and the apex,
+    /// only capped cones are solids
+    fn ray_intervals(&self, ray: &Ray) -> Option<Vec<RayInterval>> {
+        if !self.capped {
+            return None;
+        }
+        let local_ray = Ray { origin: ray.origin - self.base, direction: ray.direction, time: ray.time };
+        let (o, d) = (local_ray.origin, local_ray.direction);
+        let slope2 = (self.radius / self.height).powi(2);
+        let implicit = |t: f32| {
+            let local = local_ray.at(t);
+            local.x * local.x + local.z * local.z - slope2 * (self.height - local.y).powi(2)
+        };
+        let side_crossing = |t: f32| {
+            let local = local_ray.at(t);
+            let gradient = Vector3f::new(local.x, slope2 * (self.height - local.y), local.z);
+            let outward_normal = if gradient == Vector3f::zeros() { Vector3f::new(0.0, 1.0, 0.0) } else { gradient.normalize() };
+            SurfaceCrossing { position: ray.at(t), ..side_crossing(local, t, outward_normal, self.radius, self.height) }
+        };
+        let cap_crossing = |t: f32, is_apex: bool| {
+            let outward_normal = Vector3f::new(0.0, if is_apex { 1.0 } else { -1.0 }, 0.0);
+            SurfaceCrossing { position: ray.at(t), ..cap_crossing(local_ray.at(t), t, outward_normal, self.radius) }
+        };
+
+        // the slab between the planes of the base and of the apex
+        let (slab_enter, slab_exit) = if d.y == 0.0 {
+            if o.y < 0.0 || o.y > self.height {
+                return Some(Vec::new());
+            }
+            (SurfaceCrossing::at_infinity(f32::NEG_INFINITY), SurfaceCrossing::at_infinity(f32::INFINITY))
+        } else {
+            let t_base = -o.y / d.y;
+            let t_apex = (self.height - o.y) / d.y;
+            if t_base < t_apex {
+                (cap_crossing(t_base, false), cap_crossing(t_apex, true))
+            } else {
+                (cap_crossing(t_apex, true), cap_crossing(t_base, false))
+            }
+        };
+
+        // the roots split the line into parts inside or outside the double cone,
+        // the slab keeps at most one of them as the cone is convex
+        let mut roots : Vec<f32> = solve_quadratic(
+            (d.x * d.x + d.z * d.z - slope2 * d.y * d.y) as f64,
+            2.0 * (o.x * d.x + o.z * d.z + slope2 * (self.height - o.y) * d.y) as f64,
+            (o.x * o.x + o.z * o.z - slope2 * (self.height - o.y).powi(2)) as f64)
+            .into_iter().map(|t| t as f32).collect();
+        roots.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let mut bounds = vec![f32::NEG_INFINITY];
+        bounds.extend(roots);
+        bounds.push(f32::INFINITY);
+
+        let mut intervals = Vec::new();
+        for part in bounds.windows(2) {
+            let (t0, t1) = (part[0], part[1]);
+            let middle = match (t0.is_finite(), t1.is_finite()) {
+                (true, true) => 0.5 * (t0 + t1),
+                (true, false) => t0 + 1.0,
+                (false, true) => t1 - 1.0,
+                (false, false) => 0.0,
+            };
+            if implicit(middle) > 0.0 {
+                continue;
+            }
+            let enter = if t0 > slab_enter.t { side_crossing(t0) } else { slab_enter.clone() };
+            let exit = if t1 < slab_exit.t { side_crossing(t1) } else { slab_exit.clone() };
+            if enter.t < exit.t {
+                intervals.push(RayInterval { enter, exit });
+            }
+        }
+        Some(intervals)
+    }
+
+}
+
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use rand::Rng;
+
+    /// first t where the implicit function of a solid turns negative, found by marching and bisection
+    pub(crate) fn first_crossing(inside: impl Fn(&Vector3f) -> f32, ray: &Ray, t_max: f32) -> Option<f32> {
+        let step = 1e-3;
+        let mut t = step;
+        while t < t_max {
+            if inside(&ray.at(t)) < 0.0 {
+                let (mut low, mut high) = (t - step, t);
+                for _ in 0..30 {
+                    let middle = 0.5 * (low + high);
+                    if inside(&ray.at(middle)) < 0.0 { high = middle } else { low = middle }
+                }
+                return Some(high);
+            }
+            t += step;
+        }
+        None
+    }
+
+    /// random ray from the z > 0 side towards the region around the y axis
+    pub(crate) fn random_ray_towards_origin() -> Ray {
+        let mut rng = rand::thread_rng();
+        let origin = Vector3f::new(rng.gen_range(-3.0, 3.0), rng.gen_range(-3.0, 3.0), 4.0);
+        let target = Vector3f::new(rng.gen_range(-1.0, 1.0), rng.gen_range(-0.5, 1.5), rng.gen_range(-1.0, 1.0));
+        Ray { origin, direction: (target - origin).normalize(), time: 0.0 }
+    }
+
+    #[test]
+    fn cylinder_and_cone_match_implicit_equations() {
+        let cylinder = Cylinder { base: Vector3f::zeros(), radius: 0.8, height: 1.0, capped: true, material: None };
+        let cone = Cone { base: Vector3f::zeros(), radius: 0.8, height: 1.0, capped: true, material: None };
+        let inside_cylinder = |p: &Vector3f| (p.x * p.x + p.z * p.z - 0.64).max(-p.y).max(p.y - 1.0);
+        let inside_cone = |p: &Vector3f| (p.x * p.x + p.z * p.z - (0.8 * (1.0 - p.y)).powi(2)).max(-p.y).max(p.y - 1.0);
+
+        let mut mismatches = 0;
+        for _ in 0..200 {
+            let ray = random_ray_towards_origin();
+            for (hittable, inside) in [(&cylinder as &dyn Hittable, &inside_cylinder as &dyn Fn(&Vector3f) -> f32),
+                    (&cone, &inside_cone)] {
+                let hit = hittable.ray_intersaction(&ray, 0.001, 20.0);
+                let reference = first_crossing(inside, &ray, 20.0);
+                match (hit, reference) {
+                    (Some(hit), Some(t)) => {
+                        assert!((hit.t - t).abs() < 2e-3, "{:?} {} {}", ray, hit.t, t);
+                        assert!(hit.front_face);
+                        // the normal points out of the solid
+                        assert!(inside(&(hit.position + hit.normal * 1e-2)) > 0.0);
+                        // the tangents lie in the surface
+                        assert!(hit.dpdu.dot(&hit.normal).abs() < 1e-3 && hit.dpdv.dot(&hit.normal).abs() < 1e-3);
+                    },
+                    (None, None) => (),
+                    // grazing rays can be missed by the marching
+                    _ => mismatches += 1,
+                }
+            }
+        }
+        assert!(mismatches <= 4);
+
+        // the solids enter where the rays from outside hit them
+        for _ in 0..200 {
+            let ray = random_ray_towards_origin();
+            for hittable in [&cylinder as &dyn Hittable, &cone] {
+                let hit = hittable.ray_intersaction(&ray, 0.001, 20.0);
+                let intervals = hittable.ray_intervals(&ray).unwrap();
+                assert!(intervals.len() <= 1);
+                match (hit, intervals.first()) {
+                    (Some(hit), Some(interval)) => {
+                        assert!((hit.t - interval.enter.t).abs() < 1e-3, "{:?} {} {}", ray, hit.t, interval.enter.t);
+                        assert!((hit.normal - interval.enter.outward_normal).norm() < 1e-3);
+                    },
+                    (None, None) => (),
+                    // a ray through the apex only
+                    (None, Some(interval)) => assert!(interval.exit.t - interval.enter.t < 1e-3),
+                    (Some(hit), None) => panic!("{:?} hits at {} outside of the solid", ray, hit.t),
+                }
+            }
+        }
+        let sideways = Ray { origin: Vector3f::zeros(), direction: Vector3f::new(1.0, 0.0, 0.0), time: 0.0 };
+        assert!(Cone { capped: false, ..cone.clone() }.ray_intervals(&sideways).is_none());
+
+        let uncapped = Cylinder { capped: false, ..cylinder.clone() };
+        let ray = Ray { origin: Vector3f::new(0.0, 3.0, 0.0), direction: Vector3f::new(0.0, -1.0, 0.0), time: 0.0 };
+        assert!(uncapped.ray_intersaction(&ray, 0.001, 20.0).is_none());
+        assert!((cylinder.ray_intersaction(&ray, 0.001, 20.0).unwrap().t - 2.0).abs() < 1e-5);
+    }
+}
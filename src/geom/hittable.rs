@@ -1,14 +1,61 @@
 use crate::raycasting::ray::Ray;
-use crate::raycasting::ray::HitPoint;
+use crate::raycasting::ray::{HitPoint, RayInterval};
 use crate::material::material::Material;
+use crate::geom::aabb::Aabb;
+use crate::types::Vector3f;
 
 use dyn_clone::{DynClone, clone_trait_object};
+use std::fmt::Debug;
 
-pub trait Hittable : Send+Sync+DynClone {
+/// cut out hits that opaque_intersection skips along a ray before taking it as unblocked
+const MAX_CUT_OUT_HITS: usize = 64;
+
+pub trait Hittable : Send+Sync+DynClone+Debug {
 
     fn ray_intersaction(&self, ray: &Ray, t_min:f32, t_max:f32) -> Option<HitPoint>;
     fn material(&self) -> &Option<Box<dyn Material>>;
+    /// box containing the hittable, None if it is unbounded
+    fn bounding_box(&self) -> Option<Aabb>;
+
+    /// the intervals of the whole line of the ray, for any t, that are inside the hittable,
+    /// sorted and disjoint. None if the hittable is not a solid, those cannot be used in CSG
+    fn ray_intervals(&self, _ray: &Ray) -> Option<Vec<RayInterval>> {
+        None
+    }
+
+    /// radiance emitted at the hit towards the ray, as by glowing media.
+    /// Wrappers pass the ray and the hitpoint in the space of their object
+    fn emitted(&self, _ray: &Ray, _hitpoint: &HitPoint) -> Vector3f {
+        Vector3f::zeros()
+    }
+
+    /// the closest hit where the material is not cut out, see `Material::is_cut_out`.
+    /// The hits cut out are skipped by carrying on along the ray just past them, on this
+    /// hittable only, up to MAX_CUT_OUT_HITS of them
+    fn opaque_intersection(&self, ray: &Ray, t_min:f32, t_max:f32) -> Option<HitPoint> {
+        let mut t_min = t_min;
+        for _ in 0..MAX_CUT_OUT_HITS {
+            let hitpoint = self.ray_intersaction(ray, t_min, t_max)?;
+            match self.material() {
+                Some(material) if material.is_cut_out(&hitpoint) => t_min = hitpoint.t + 1e-4,
+                _ => return Some(hitpoint),
+            }
+        }
+        None
+    }
+
+    /// fraction of the light that crosses the hittable along the ray between t_min and t_max.
+    /// Surfaces block it when they are hit where they are not cut out, media attenuate it
+    fn transmittance(&self, ray: &Ray, t_min:f32, t_max:f32) -> f32 {
+        surface_transmittance(self, ray, t_min, t_max)
+    }
 
 }
 
-clone_trait_object!(Hittable);
\ No newline at end of file
+clone_trait_object!(Hittable);
+
+/// 0 when the ray hits the hittable where its material is not cut out, 1 otherwise.
+/// Wrappers replacing the material of their object use it, so that their material also decides where it is cut out
+pub fn surface_transmittance<H: Hittable + ?Sized>(hittable: &H, ray: &Ray, t_min:f32, t_max:f32) -> f32 {
+    if hittable.opaque_intersection(ray, t_min, t_max).is_some() { 0.0 } else { 1.0 }
+}
@@ -1,3 +1,18 @@
 pub mod sphere;
 pub mod hittable;
-pub mod rand_geom;
\ No newline at end of file
+pub mod rand_geom;
+pub mod aabb;
+pub mod bvh;
+pub mod transformed;
+pub mod quad;
+pub mod rect;
+pub mod cuboid;
+pub mod plane;
+pub mod polynomial;
+pub mod cylinder;
+pub mod torus;
+pub mod csg;
+pub mod sdf;
+pub mod moving;
+pub mod constant_medium;
+pub mod voxel_medium;
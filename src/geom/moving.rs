@@ -0,0 +1,333 @@
+use crate::types::Vector3f;
+use crate::raycasting::ray::{Ray, HitPoint, RayInterval, SurfaceCrossing};
+use super::hittable::{surface_transmittance, Hittable};
+use super::aabb::Aabb;
+use super::sphere::{hit_sphere, Sphere};
+use super::transformed::make_transform;
+use crate::material::material::Material;
+use nalgebra::{Matrix4, Point3, U3, Unit, UnitQuaternion};
+use std::sync::Arc;
+
+/// the motion bounding box is the union of the boxes at this many steps of the motion
+const MOTION_BOX_STEPS: usize = 32;
+
+/// fraction of the motion done at time, motions stay still before time0 and after time1
+fn motion_fraction(time: f32, time0: f32, time1: f32) -> f32 {
+    if time1 <= time0 {
+        return if time < time0 { 0.0 } else { 1.0 };
+    }
+    ((time - time0) / (time1 - time0)).clamp(0.0, 1.0)
+}
+
+/// Sphere whose center moves linearly from origin0 at time0 to origin1 at time1
+#[derive(Clone, Debug)]
+pub struct MovingSphere {
+    pub origin0: Vector3f,
+    pub origin1: Vector3f,
+    pub time0: f32,
+    pub time1: f32,
+    pub radius: f32,
+    pub material: Option<Box<dyn Material>>
+}
+
+impl MovingSphere {
+
+    pub fn origin_at(&self, time: f32) -> Vector3f {
+        self.origin0 + (self.origin1 - self.origin0) * motion_fraction(time, self.time0, self.time1)
+    }
+
+    /// the still sphere at the time of the ray
+    fn sphere_at(&self, time: f32) -> Sphere {
+        Sphere { origin: self.origin_at(time), radius: self.radius, material: None }
+    }
+
+}
+
+impl Hittable for MovingSphere {
+
+    fn ray_intersaction(&self, ray: &Ray, t_min:f32, t_max:f32) -> Option<HitPoint> {
+        hit_sphere(&self.sphere_at(ray.time), ray, t_min, t_max)
+    }
+
+    fn material(&self) -> &Option<Box<dyn Material>> {
+        &self.material
+    }
+
+    /// covers the sphere along the whole motion
+    fn bounding_box(&self) -> Option<Aabb> {
+        let radius = Vector3f::repeat(self.radius.abs());
+        let start = Aabb::new(self.origin0 - radius, self.origin0 + radius);
+        let end = Aabb::new(self.origin1 - radius, self.origin1 + radius);
+        Some(start.surrounding(&end))
+    }
+
+    fn ray_intervals(&self, ray: &Ray) -> Option<Vec<RayInterval>> {
+        self.sphere_at(ray.time).ray_intervals(ray)
+    }
+
+}
+
+/// Scale, rotation around an axis and translation, composed as in `make_transform`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Keyframe {
+    pub translation: Vector3f,
+    pub rotation_axis: Vector3f,
+    pub rotation_degrees: f32,
+    pub scale: Vector3f,
+}
+
+impl Keyframe {
+
+    pub fn matrix(&self) -> Matrix4<f32> {
+        make_transform(self.translation, self.rotation_axis, self.rotation_degrees, self.scale)
+    }
+
+    fn rotation(&self) -> UnitQuaternion<f32> {
+        if self.rotation_axis == Vector3f::zeros() {
+            return UnitQuaternion::identity();
+        }
+        UnitQuaternion::from_axis_angle(&Unit::new_normalize(self.rotation_axis), self.rotation_degrees.to_radians())
+    }
+
+}
+
+/// Transform interpolated between two keyframes, from start at time0 to end at time1.
+/// Rotations around the same axis interpolate the angle, so they can spin for more
+/// than a turn; rotations around different axes take the shortest path
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TransformMotion {
+    pub start: Keyframe,
+    pub end: Keyframe,
+    pub time0: f32,
+    pub time1: f32,
+}
+
+impl TransformMotion {
+
+    pub fn matrix_at(&self, time: f32) -> Matrix4<f32> {
+        let fraction = motion_fraction(time, self.time0, self.time1);
+        let translation = self.start.translation.lerp(&self.end.translation, fraction);
+        let scale = self.start.scale.lerp(&self.end.scale, fraction);
+        if self.start.rotation_axis == self.end.rotation_axis {
+            let degrees = self.start.rotation_degrees + (self.end.rotation_degrees - self.start.rotation_degrees) * fraction;
+            return make_transform(translation, self.start.rotation_axis, degrees, scale);
+        }
+        let (start, end) = (self.start.rotation(), self.end.rotation());
+        // half turns apart there is no shortest path, any of them will do
+        let rotation = start.try_slerp(&end, fraction, 1e-6).unwrap_or(if fraction < 0.5 { start } else { end });
+        Matrix4::new_translation(&translation) * rotation.to_homogeneous() * Matrix4::new_nonuniform_scaling(&scale)
+    }
+
+    /// largest angle the transform rotates by between two steps of the motion
+    fn max_step_angle(&self, steps: usize) -> f32 {
+        let angle = if self.start.rotation_axis == self.end.rotation_axis {
+            (self.end.rotation_degrees - self.start.rotation_degrees).to_radians().abs()
+        } else {
+            self.start.rotation().angle_to(&self.end.rotation())
+        };
+        angle / steps as f32
+    }
+
+}
+
+/// A hittable moved by a time-varying transform. The world transform at time is
+/// before × motion(time) × after, where before and after are fixed, e.g. the transforms
+/// of the parent and the child of a moving node. The object is shared like in `Transformed`
+#[derive(Clone, Debug)]
+pub struct MovingTransformed {
+    object: Arc<dyn Hittable>,
+    before: Matrix4<f32>,
+    motion: TransformMotion,
+    after: Matrix4<f32>,
+    /// replaces the material of the object when set
+    material: Option<Box<dyn Material>>,
+}
+
+impl MovingTransformed {
+
+    pub fn new(object: Arc<dyn Hittable>, before: Matrix4<f32>, motion: TransformMotion,
+            after: Matrix4<f32>) -> MovingTransformed {
+        MovingTransformed { object, before, motion, after, material: None }
+    }
+
+    pub fn with_material(object: Arc<dyn Hittable>, before: Matrix4<f32>, motion: TransformMotion,
+            after: Matrix4<f32>, material: Box<dyn Material>) -> MovingTransformed {
+        let mut moving = MovingTransformed::new(object, before, motion, after);
+        moving.material = Some(material);
+        moving
+    }
+
+    /// object space to world space at time
+    pub fn transform_at(&self, time: f32) -> Matrix4<f32> {
+        self.before * self.motion.matrix_at(time) * self.after
+    }
+
+    /// the ray in object space, the transform at the ray time and its inverse transpose
+    /// for the normals, None if the transform is singular at the ray time
+    fn object_ray(&self, ray: &Ray) -> Option<(Ray, Matrix4<f32>, Matrix4<f32>)> {
+        let transform = self.transform_at(ray.time);
+        let inverse_transform = transform.try_inverse()?;
+        let object_ray = Ray {
+            origin: inverse_transform.transform_point(&Point3::from(ray.origin)).coords,
+            direction: inverse_transform.transform_vector(&ray.direction),
+            time: ray.time,
+        };
+        Some((object_ray, transform, inverse_transform.transpose()))
+    }
+
+    /// the world hitpoint in object space, where the object evaluates it,
+    /// with the transforms object_ray gives
+    fn object_hitpoint(object_ray: &Ray, transform: &Matrix4<f32>, normal_transform: &Matrix4<f32>,
+            hitpoint: &HitPoint) -> HitPoint {
+        let inverse_transform = normal_transform.transpose();
+        HitPoint {
+            t: hitpoint.t,
+            position: object_ray.at(hitpoint.t),
+            normal: transform.transpose().transform_vector(&hitpoint.normal).normalize(),
+            front_face: hitpoint.front_face,
+            uv: hitpoint.uv,
+            dpdu: inverse_transform.transform_vector(&hitpoint.dpdu),
+            dpdv: inverse_transform.transform_vector(&hitpoint.dpdv),
+        }
+    }
+
+    fn world_crossing(ray: &Ray, transform: &Matrix4<f32>, normal_transform: &Matrix4<f32>,
+            object_crossing: &SurfaceCrossing) -> SurfaceCrossing {
+        if object_crossing.t.is_infinite() {
+            return object_crossing.clone();
+        }
+        SurfaceCrossing {
+            t: object_crossing.t,
+            position: ray.at(object_crossing.t),
+            outward_normal: normal_transform.transform_vector(&object_crossing.outward_normal).normalize(),
+            uv: object_crossing.uv,
+            dpdu: transform.transform_vector(&object_crossing.dpdu),
+            dpdv: transform.transform_vector(&object_crossing.dpdv),
+        }
+    }
+
+}
+
+impl Hittable for MovingTransformed {
+
+    fn ray_intersaction(&self, ray: &Ray, t_min:f32, t_max:f32) -> Option<HitPoint> {
+        let (object_ray, transform, normal_transform) = self.object_ray(ray)?;
+        let object_hitpoint = self.object.ray_intersaction(&object_ray, t_min, t_max)?;
+        Some(HitPoint {
+            t: object_hitpoint.t,
+            position: ray.at(object_hitpoint.t),
+            normal: normal_transform.transform_vector(&object_hitpoint.normal).normalize(),
+            front_face: object_hitpoint.front_face,
+            uv: object_hitpoint.uv,
+            dpdu: transform.transform_vector(&object_hitpoint.dpdu),
+            dpdv: transform.transform_vector(&object_hitpoint.dpdv),
+        })
+    }
+
+    fn material(&self) -> &Option<Box<dyn Material>> {
+        if self.material.is_some() {
+            &self.material
+        } else {
+            self.object.material()
+        }
+    }
+
+    /// union of the boxes at steps of the motion, padded by how far
+    /// the rotation between two steps can bulge out of them
+    fn bounding_box(&self) -> Option<Aabb> {
+        let object_box = self.object.bounding_box()?;
+        let mut aabb = object_box.transformed(&self.transform_at(self.motion.time0));
+        for step in 1..=MOTION_BOX_STEPS {
+            let time = self.motion.time0 + (self.motion.time1 - self.motion.time0) * step as f32 / MOTION_BOX_STEPS as f32;
+            aabb = aabb.surrounding(&object_box.transformed(&self.transform_at(time)));
+        }
+        // points rotate around the origin of the motion space
+        let inner_box = object_box.transformed(&self.after);
+        let largest_scale = self.motion.start.scale.abs().max().max(self.motion.end.scale.abs().max());
+        let radius = inner_box.corners().iter().map(|corner| corner.norm()).fold(0.0, f32::max) * largest_scale;
+        let sagitta = radius * (1.0 - (self.motion.max_step_angle(MOTION_BOX_STEPS) / 2.0).cos());
+        // before stretches lengths at most by the norm of its linear part
+        let stretch = self.before.fixed_slice::<U3, U3>(0, 0).norm();
+        let padding = Vector3f::repeat(sagitta * stretch);
+        Some(Aabb::new(aabb.min - padding, aabb.max + padding))
+    }
+
+    fn ray_intervals(&self, ray: &Ray) -> Option<Vec<RayInterval>> {
+        let (object_ray, transform, normal_transform) = match self.object_ray(ray) {
+            Some(object_ray) => object_ray,
+            None => return Some(Vec::new()),
+        };
+        let object_intervals = self.object.ray_intervals(&object_ray)?;
+        Some(object_intervals.iter().map(|interval| RayInterval {
+            enter: MovingTransformed::world_crossing(ray, &transform, &normal_transform, &interval.enter),
+            exit: MovingTransformed::world_crossing(ray, &transform, &normal_transform, &interval.exit),
+        }).collect())
+    }
+
+    fn emitted(&self, ray: &Ray, hitpoint: &HitPoint) -> Vector3f {
+        match self.object_ray(ray) {
+            Some((object_ray, transform, normal_transform)) => self.object.emitted(&object_ray,
+                &Self::object_hitpoint(&object_ray, &transform, &normal_transform, hitpoint)),
+            None => Vector3f::zeros(),
+        }
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min:f32, t_max:f32) -> f32 {
+        if self.material.is_some() {
+            return surface_transmittance(self, ray, t_min, t_max);
+        }
+        match self.object_ray(ray) {
+            Some((object_ray, _, _)) => self.object.transmittance(&object_ray, t_min, t_max),
+            None => 1.0,
+        }
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moving_hittables_follow_the_ray_time() {
+        let sphere = MovingSphere {
+            origin0: Vector3f::new(0.0, 0.0, -5.0),
+            origin1: Vector3f::new(2.0, 0.0, -5.0),
+            time0: 0.0,
+            time1: 1.0,
+            radius: 0.5,
+            material: None
+        };
+        let ray_at = |time| Ray { origin: Vector3f::new(2.0, 0.0, 0.0), direction: Vector3f::new(0.0, 0.0, -1.0), time };
+        assert!(sphere.ray_intersaction(&ray_at(0.0), 0.001, f32::INFINITY).is_none());
+        assert!((sphere.ray_intersaction(&ray_at(1.0), 0.001, f32::INFINITY).unwrap().t - 4.5).abs() < 1e-5);
+        // still after the end of the motion
+        assert!(sphere.ray_intersaction(&ray_at(3.0), 0.001, f32::INFINITY).is_some());
+        let aabb = sphere.bounding_box().unwrap();
+        assert_eq!(aabb, Aabb::new(Vector3f::new(-0.5, -0.5, -5.5), Vector3f::new(2.5, 0.5, -4.5)));
+
+        // a quarter turn around y of a sphere offset along x, sweeping from x to -z
+        let offset_sphere : Arc<dyn Hittable> = Arc::new(Sphere { origin: Vector3f::new(3.0, 0.0, 0.0), radius: 0.5, material: None });
+        let still = Keyframe {
+            translation: Vector3f::zeros(),
+            rotation_axis: Vector3f::new(0.0, 1.0, 0.0),
+            rotation_degrees: 0.0,
+            scale: Vector3f::repeat(1.0),
+        };
+        let motion = TransformMotion { start: still, end: Keyframe { rotation_degrees: 90.0, ..still }, time0: 0.0, time1: 1.0 };
+        let moving = MovingTransformed::new(offset_sphere, Matrix4::identity(), motion, Matrix4::identity());
+        let aabb = moving.bounding_box().unwrap();
+        for step in 0..=100 {
+            let angle = (step as f32 * 0.9).to_radians();
+            let center = Vector3f::new(3.0 * angle.cos(), 0.0, -3.0 * angle.sin());
+            assert!(aabb.min <= center - Vector3f::repeat(0.5) && center + Vector3f::repeat(0.5) <= aabb.max);
+        }
+        // at the half of the motion the sphere is at 45 degrees
+        let diagonal = Vector3f::new(1.0, 0.0, -1.0).normalize() * 3.0;
+        let ray = Ray { origin: diagonal + Vector3f::new(0.0, 5.0, 0.0), direction: Vector3f::new(0.0, -1.0, 0.0), time: 0.5 };
+        let hit = moving.ray_intersaction(&ray, 0.001, f32::INFINITY).unwrap();
+        assert!((hit.t - 4.5).abs() < 1e-4);
+        assert!((hit.normal - Vector3f::new(0.0, 1.0, 0.0)).norm() < 1e-4);
+    }
+}
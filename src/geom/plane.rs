@@ -0,0 +1,188 @@
+use crate::types::{Vector2f, Vector3f};
+use crate::raycasting::ray::{Ray, HitPoint, RayInterval, SurfaceCrossing};
+use super::hittable::Hittable;
+use super::aabb::Aabb;
+use super::quad::padded_aabb;
+use crate::material::material::Material;
+
+/// two unit vectors spanning the plane normal to the unit vector normal
+fn tangent_basis(normal: &Vector3f) -> (Vector3f, Vector3f) {
+    let helper = if normal.x.abs() > 0.9 { Vector3f::new(0.0, 1.0, 0.0) } else { Vector3f::new(1.0, 0.0, 0.0) };
+    let tangent = (helper - normal * normal.dot(&helper)).normalize();
+    (tangent, normal.cross(&tangent))
+}
+
+/// where the ray crosses the plane through point, with the front face flag
+fn hit_plane(point: &Vector3f, normal: &Vector3f, ray: &Ray, t_min: f32, t_max: f32) -> Option<(f32, bool)> {
+    let denominator = normal.dot(&ray.direction);
+    if denominator.abs() < 1e-8 {
+        return None;
+    }
+    let t = normal.dot(&(point - ray.origin)) / denominator;
+    if t <= t_min || t >= t_max {
+        return None;
+    }
+    Some((t, denominator < 0.0))
+}
+
+/// Infinite plane through a point. It has no bounding box.
+/// uv are the distances from the point along two tangent directions,
+/// so textures repeat at a fixed size all over the plane
+#[derive(Clone, Debug)]
+pub struct Plane {
+    point: Vector3f,
+    /// unit vector, the front face is on its side
+    normal: Vector3f,
+    tangent: Vector3f,
+    bitangent: Vector3f,
+    pub material: Option<Box<dyn Material>>
+}
+
+impl Plane {
+
+    pub fn new(point: Vector3f, normal: Vector3f, material: Option<Box<dyn Material>>) -> Plane {
+        let normal = normal.normalize();
+        let (tangent, bitangent) = tangent_basis(&normal);
+        Plane { point, normal, tangent, bitangent, material }
+    }
+
+    fn crossing(&self, ray: &Ray, t: f32) -> SurfaceCrossing {
+        let position = ray.at(t);
+        let planar = position - self.point;
+        SurfaceCrossing {
+            t,
+            position,
+            outward_normal: self.normal,
+            uv: Vector2f::new(planar.dot(&self.tangent), planar.dot(&self.bitangent)),
+            dpdu: self.tangent,
+            dpdv: self.bitangent,
+        }
+    }
+
+}
+
+impl Hittable for Plane {
+
+    fn ray_intersaction(&self, ray: &Ray, t_min:f32, t_max:f32) -> Option<HitPoint> {
+        let (t, _is_front_face) = hit_plane(&self.point, &self.normal, ray, t_min, t_max)?;
+        Some(self.crossing(ray, t).to_hitpoint(ray))
+    }
+
+    fn material(&self) -> &Option<Box<dyn Material>> {
+        &self.material
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+
+    /// the plane bounds the half space behind it
+    fn ray_intervals(&self, ray: &Ray) -> Option<Vec<RayInterval>> {
+        let denominator = self.normal.dot(&ray.direction);
+        let behind = self.normal.dot(&(ray.origin - self.point)) < 0.0;
+        if denominator == 0.0 {
+            return Some(if behind {
+                vec![RayInterval {
+                    enter: SurfaceCrossing::at_infinity(f32::NEG_INFINITY),
+                    exit: SurfaceCrossing::at_infinity(f32::INFINITY),
+                }]
+            } else {
+                Vec::new()
+            });
+        }
+        let crossing = self.crossing(ray, self.normal.dot(&(self.point - ray.origin)) / denominator);
+        Some(vec![if denominator < 0.0 {
+            RayInterval { enter: crossing, exit: SurfaceCrossing::at_infinity(f32::INFINITY) }
+        } else {
+            RayInterval { enter: SurfaceCrossing::at_infinity(f32::NEG_INFINITY), exit: crossing }
+        }])
+    }
+
+}
+
+/// Flat disk. uv are polar coordinates: the angle around the center
+/// over a full turn and the distance from the center over the radius
+#[derive(Clone, Debug)]
+pub struct Disk {
+    center: Vector3f,
+    /// unit vector, the front face is on its side
+    normal: Vector3f,
+    radius: f32,
+    tangent: Vector3f,
+    bitangent: Vector3f,
+    pub material: Option<Box<dyn Material>>
+}
+
+impl Disk {
+
+    pub fn new(center: Vector3f, normal: Vector3f, radius: f32, material: Option<Box<dyn Material>>) -> Disk {
+        let normal = normal.normalize();
+        let (tangent, bitangent) = tangent_basis(&normal);
+        Disk { center, normal, radius, tangent, bitangent, material }
+    }
+
+}
+
+impl Hittable for Disk {
+
+    fn ray_intersaction(&self, ray: &Ray, t_min:f32, t_max:f32) -> Option<HitPoint> {
+        let (t, is_front_face) = hit_plane(&self.center, &self.normal, ray, t_min, t_max)?;
+        let position = ray.at(t);
+        let planar = position - self.center;
+        let distance = planar.norm();
+        if distance > self.radius {
+            return None;
+        }
+        let angle = planar.dot(&self.bitangent).atan2(planar.dot(&self.tangent)) + std::f32::consts::PI;
+        Some(HitPoint {
+            t,
+            position,
+            normal: if is_front_face { self.normal } else { -self.normal },
+            front_face: is_front_face,
+            uv: Vector2f::new(angle / (2.0 * std::f32::consts::PI), distance / self.radius),
+            // the angle turns from the tangent to the bitangent
+            dpdu: self.normal.cross(&planar) * (2.0 * std::f32::consts::PI),
+            dpdv: planar.try_normalize(1e-9).unwrap_or(self.tangent) * self.radius,
+        })
+    }
+
+    fn material(&self) -> &Option<Box<dyn Material>> {
+        &self.material
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        // extent along each axis of a disk of unit radius
+        let extent = self.normal.map(|n| (1.0 - n * n).max(0.0).sqrt()) * self.radius;
+        Some(padded_aabb(Aabb::new(self.center - extent, self.center + extent)))
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plane_and_disk_hits() {
+        let plane = Plane::new(Vector3f::new(0.0, -1.0, 0.0), Vector3f::new(0.0, 2.0, 0.0), None);
+        let ray = Ray { origin: Vector3f::new(3.0, 1.0, -2.0), direction: Vector3f::new(0.0, -1.0, 0.0), time: 0.0 };
+        let hit = plane.ray_intersaction(&ray, 0.001, f32::INFINITY).unwrap();
+        assert!((hit.t - 2.0).abs() < 1e-5);
+        assert!(hit.front_face);
+        assert_eq!(hit.normal, Vector3f::new(0.0, 1.0, 0.0));
+        // uv measure distances on the plane
+        assert!((hit.uv.norm() - (13.0f32).sqrt()).abs() < 1e-5);
+        assert!(plane.bounding_box().is_none());
+
+        let disk = Disk::new(Vector3f::new(0.0, 0.0, -5.0), Vector3f::new(0.0, 0.0, 1.0), 2.0, None);
+        let ray = Ray { origin: Vector3f::new(1.0, 0.0, 0.0), direction: Vector3f::new(0.0, 0.0, -1.0), time: 0.0 };
+        let hit = disk.ray_intersaction(&ray, 0.001, f32::INFINITY).unwrap();
+        assert!((hit.t - 5.0).abs() < 1e-5);
+        assert!((hit.uv.y - 0.5).abs() < 1e-5);
+        let outside = Ray { origin: Vector3f::new(2.5, 0.0, 0.0), direction: Vector3f::new(0.0, 0.0, -1.0), time: 0.0 };
+        assert!(disk.ray_intersaction(&outside, 0.001, f32::INFINITY).is_none());
+        let aabb = disk.bounding_box().unwrap();
+        assert!((aabb.max - Vector3f::new(2.0, 2.0, -5.0)).norm() < 1e-3);
+    }
+}
@@ -0,0 +1,131 @@
+//! Real roots of polynomials up to the fourth degree, closed form.
+//! Coefficients go from the highest degree down, roots are unsorted.
+//! Computed in f64, the quartic of the torus loses too much in f32
+
+const EPSILON: f64 = 1e-12;
+
+fn is_zero(x: f64) -> bool {
+    x.abs() < EPSILON
+}
+
+/// roots of a x^2 + b x + c
+pub fn solve_quadratic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    if is_zero(a) {
+        return if is_zero(b) { Vec::new() } else { vec![-c / b] };
+    }
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        Vec::new()
+    } else if discriminant == 0.0 {
+        vec![-b / (2.0 * a)]
+    } else {
+        // avoids the cancellation of -b + sqrt(discriminant) when b is large
+        let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+        if q == 0.0 {
+            vec![0.0]
+        } else {
+            vec![q / a, c / q]
+        }
+    }
+}
+
+/// roots of a x^3 + b x^2 + c x + d
+pub fn solve_cubic(a: f64, b: f64, c: f64, d: f64) -> Vec<f64> {
+    if is_zero(a) {
+        return solve_quadratic(b, c, d);
+    }
+    // x = y - b / 3a gives the depressed cubic y^3 + p y + q
+    let (b, c, d) = (b / a, c / a, d / a);
+    let shift = b / 3.0;
+    let p = c - b * shift;
+    let q = 2.0 * shift * shift * shift - shift * c + d;
+
+    let roots = if is_zero(p) && is_zero(q) {
+        vec![0.0]
+    } else {
+        let discriminant = (q / 2.0).powi(2) + (p / 3.0).powi(3);
+        if discriminant > EPSILON {
+            let sqrt_discriminant = discriminant.sqrt();
+            vec![(-q / 2.0 + sqrt_discriminant).cbrt() + (-q / 2.0 - sqrt_discriminant).cbrt()]
+        } else if discriminant < -EPSILON {
+            // three real roots, trigonometric form
+            let radius = (-p / 3.0).sqrt();
+            let angle = (-q / (2.0 * radius.powi(3))).clamp(-1.0, 1.0).acos() / 3.0;
+            (0..3).map(|k| 2.0 * radius * (angle - 2.0 * std::f64::consts::PI * k as f64 / 3.0).cos()).collect()
+        } else {
+            let u = (-q / 2.0).cbrt();
+            vec![2.0 * u, -u]
+        }
+    };
+    roots.into_iter().map(|y| y - shift).collect()
+}
+
+/// roots of a x^4 + b x^3 + c x^2 + d x + e, Ferrari's method
+pub fn solve_quartic(a: f64, b: f64, c: f64, d: f64, e: f64) -> Vec<f64> {
+    if is_zero(a) {
+        return solve_cubic(b, c, d, e);
+    }
+    // x = y - b / 4a gives the depressed quartic y^4 + p y^2 + q y + r
+    let (b, c, d, e) = (b / a, c / a, d / a, e / a);
+    let shift = b / 4.0;
+    let shift2 = shift * shift;
+    let p = c - 6.0 * shift2;
+    let q = d - 2.0 * c * shift + 8.0 * shift * shift2;
+    let r = e - d * shift + c * shift2 - 3.0 * shift2 * shift2;
+
+    let roots = if is_zero(q) {
+        // biquadratic
+        solve_quadratic(1.0, p, r).into_iter()
+            .filter(|z| *z >= 0.0)
+            .flat_map(|z| vec![z.sqrt(), -z.sqrt()])
+            .collect()
+    } else {
+        // any root m > 0 of the resolvent cubic splits the quartic in two quadratics
+        let m = solve_cubic(8.0, 8.0 * p, 2.0 * p * p - 8.0 * r, -q * q).into_iter()
+            .fold(f64::NEG_INFINITY, f64::max);
+        if m <= 0.0 {
+            return Vec::new();
+        }
+        let sqrt_2m = (2.0 * m).sqrt();
+        let mut roots = solve_quadratic(1.0, sqrt_2m, p / 2.0 + m - q / (2.0 * sqrt_2m));
+        roots.extend(solve_quadratic(1.0, -sqrt_2m, p / 2.0 + m + q / (2.0 * sqrt_2m)));
+        roots
+    };
+    roots.into_iter().map(|y| y - shift).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(mut roots: Vec<f64>) -> Vec<f64> {
+        roots.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        roots
+    }
+
+    fn assert_roots(roots: Vec<f64>, expected: &[f64]) {
+        let roots = sorted(roots);
+        assert_eq!(roots.len(), expected.len(), "{:?} != {:?}", roots, expected);
+        for (root, expected) in roots.iter().zip(expected) {
+            assert!((root - expected).abs() < 1e-6, "{:?} != {:?}", roots, expected);
+        }
+    }
+
+    #[test]
+    fn polynomial_roots() {
+        // (x - 1)(x + 2)
+        assert_roots(solve_quadratic(1.0, 1.0, -2.0), &[-2.0, 1.0]);
+        assert_roots(solve_quadratic(1.0, 0.0, 1.0), &[]);
+        // (x - 1)(x - 2)(x + 3)
+        assert_roots(solve_cubic(2.0, 0.0, -14.0, 12.0), &[-3.0, 1.0, 2.0]);
+        // (x - 1)(x^2 + 1)
+        assert_roots(solve_cubic(1.0, -1.0, 1.0, -1.0), &[1.0]);
+        // (x - 1)(x - 2)(x - 3)(x + 4)
+        assert_roots(solve_quartic(1.0, -2.0, -13.0, 38.0, -24.0), &[-4.0, 1.0, 2.0, 3.0]);
+        // (x^2 - 4)(x^2 - 9), biquadratic
+        assert_roots(solve_quartic(1.0, 0.0, -13.0, 0.0, 36.0), &[-3.0, -2.0, 2.0, 3.0]);
+        // (x^2 + 1)(x^2 + 2)
+        assert_roots(solve_quartic(1.0, 0.0, 3.0, 0.0, 2.0), &[]);
+    }
+}
@@ -0,0 +1,134 @@
+use crate::types::{Vector2f, Vector3f};
+use crate::raycasting::ray::{Ray, HitPoint};
+use super::hittable::Hittable;
+use super::aabb::Aabb;
+use crate::material::material::Material;
+
+/// flat boxes are padded by this much, so the slab test does not miss them
+const FLAT_BOX_PADDING: f32 = 1e-4;
+
+/// Parallelogram with a corner and two edges u and v starting from it.
+/// The front face is the one u × v points to, uv are the coordinates along the edges
+#[derive(Clone, Debug)]
+pub struct Quad {
+    corner: Vector3f,
+    u: Vector3f,
+    v: Vector3f,
+    /// u × v
+    normal: Vector3f,
+    /// normal / (normal · normal), projects a plane point on the edges
+    w: Vector3f,
+    pub material: Option<Box<dyn Material>>
+}
+
+impl Quad {
+
+    pub fn new(corner: Vector3f, u: Vector3f, v: Vector3f, material: Option<Box<dyn Material>>) -> Quad {
+        let normal = u.cross(&v);
+        Quad {
+            corner,
+            u,
+            v,
+            normal,
+            w: normal / normal.dot(&normal),
+            material
+        }
+    }
+
+    pub fn corner(&self) -> Vector3f {
+        self.corner
+    }
+
+    pub fn edges(&self) -> (Vector3f, Vector3f) {
+        (self.u, self.v)
+    }
+
+    /// coordinates along the edges of a point of the plane of the quad
+    pub fn uv_at(&self, position: &Vector3f) -> Vector2f {
+        let planar = position - self.corner;
+        Vector2f::new(self.w.dot(&planar.cross(&self.v)), self.w.dot(&self.u.cross(&planar)))
+    }
+
+}
+
+/// pads the axes along which the box is flat
+pub fn padded_aabb(aabb: Aabb) -> Aabb {
+    let padding = aabb.extent().map(|extent| if extent < FLAT_BOX_PADDING { FLAT_BOX_PADDING } else { 0.0 });
+    Aabb::new(aabb.min - padding, aabb.max + padding)
+}
+
+impl Hittable for Quad {
+
+    fn ray_intersaction(&self, ray: &Ray, t_min:f32, t_max:f32) -> Option<HitPoint> {
+        let denominator = self.normal.dot(&ray.direction);
+        // parallel to the plane
+        if denominator.abs() < 1e-8 {
+            return None;
+        }
+        let t = self.normal.dot(&(self.corner - ray.origin)) / denominator;
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+
+        let position = ray.at(t);
+        let uv = self.uv_at(&position);
+        if !(0.0..=1.0).contains(&uv.x) || !(0.0..=1.0).contains(&uv.y) {
+            return None;
+        }
+
+        let outward_normal = self.normal.normalize();
+        let is_front_face = denominator < 0.0;
+        Some(HitPoint {
+            t,
+            position,
+            normal: if is_front_face { outward_normal } else { -outward_normal },
+            front_face: is_front_face,
+            uv,
+            dpdu: self.u,
+            dpdv: self.v,
+        })
+    }
+
+    fn material(&self) -> &Option<Box<dyn Material>> {
+        &self.material
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(padded_aabb(Aabb::from_points(&[
+            self.corner,
+            self.corner + self.u,
+            self.corner + self.v,
+            self.corner + self.u + self.v,
+        ])))
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quad_hit_uv_and_faces() {
+        let quad = Quad::new(Vector3f::new(-1.0, -1.0, -2.0),
+            Vector3f::new(2.0, 0.0, 0.0), Vector3f::new(1.0, 2.0, 0.0), None);
+
+        let ray = Ray { origin: Vector3f::new(0.5, 0.0, 0.0), direction: Vector3f::new(0.0, 0.0, -1.0), time: 0.0 };
+        let hit = quad.ray_intersaction(&ray, 0.001, f32::INFINITY).unwrap();
+        assert!((hit.t - 2.0).abs() < 1e-5);
+        assert!(hit.front_face);
+        assert_eq!(hit.normal, Vector3f::new(0.0, 0.0, 1.0));
+        assert!((hit.uv - Vector2f::new(0.5, 0.5)).norm() < 1e-5);
+
+        let back_ray = Ray { origin: Vector3f::new(0.5, 0.0, -4.0), direction: Vector3f::new(0.0, 0.0, 1.0), time: 0.0 };
+        let back_hit = quad.ray_intersaction(&back_ray, 0.001, f32::INFINITY).unwrap();
+        assert!(!back_hit.front_face);
+        assert_eq!(back_hit.normal, Vector3f::new(0.0, 0.0, -1.0));
+
+        // inside the bounding rectangle, outside the parallelogram
+        let miss = Ray { origin: Vector3f::new(-0.9, 0.9, 0.0), direction: Vector3f::new(0.0, 0.0, -1.0), time: 0.0 };
+        assert!(quad.ray_intersaction(&miss, 0.001, f32::INFINITY).is_none());
+        assert!(quad.bounding_box().unwrap().hit(&ray, 0.001, f32::INFINITY));
+    }
+}
@@ -9,3 +9,34 @@ pub fn random_in_unit_sphere() -> Vector3f {
     let z = rng.gen_range(-1.0, 1.0);
     Vector3f::new(x, y, z).normalize()
 }
+
+/// distance a ray travels in a medium of this density before colliding,
+/// exponentially distributed
+pub fn free_flight_distance(density: f32) -> f32 {
+    let mut rng = rand::thread_rng();
+    // 1 - gen is in (0, 1], its logarithm is finite
+    -(1.0 - rng.gen::<f32>()).ln() / density
+}
+
+/// uniformly distributed over the sphere
+pub fn random_unit_vector() -> Vector3f {
+    let mut rng = rand::thread_rng();
+    let z : f32 = rng.gen_range(-1.0, 1.0);
+    let phi = 2.0 * std::f32::consts::PI * rng.gen::<f32>();
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    Vector3f::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// two unit vectors normal to the unit vector axis and to each other
+pub fn orthonormal_basis(axis: &Vector3f) -> (Vector3f, Vector3f) {
+    let helper = if axis.x.abs() > 0.9 { Vector3f::new(0.0, 1.0, 0.0) } else { Vector3f::new(1.0, 0.0, 0.0) };
+    let tangent = axis.cross(&helper).normalize();
+    (tangent, axis.cross(&tangent))
+}
+
+/// unit vector at the angle with this cosine from the unit vector axis, turned by phi around it
+pub fn direction_around(axis: &Vector3f, cos_theta: f32, phi: f32) -> Vector3f {
+    let (tangent, bitangent) = orthonormal_basis(axis);
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    axis * cos_theta + (tangent * phi.cos() + bitangent * phi.sin()) * sin_theta
+}
@@ -0,0 +1,141 @@
+use crate::types::{Vector2f, Vector3f};
+use crate::raycasting::ray::{Ray, HitPoint};
+use super::hittable::Hittable;
+use super::aabb::Aabb;
+use super::quad::padded_aabb;
+use crate::material::material::Material;
+use serde::{Deserialize, Serialize};
+
+/// the plane an axis aligned rectangle lies in
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RectPlane {
+    Xy,
+    Yz,
+    Xz,
+}
+
+impl RectPlane {
+
+    /// the two axes spanning the plane and the axis normal to it
+    pub fn axes(&self) -> (usize, usize, usize) {
+        match self {
+            RectPlane::Xy => (0, 1, 2),
+            RectPlane::Yz => (1, 2, 0),
+            RectPlane::Xz => (0, 2, 1),
+        }
+    }
+
+}
+
+/// Rectangle in a plane normal to an axis, at coordinate k along that axis.
+/// The front face looks towards the positive side of the normal axis
+#[derive(Clone, Debug)]
+pub struct AxisAlignedRect {
+    pub plane: RectPlane,
+    /// min and max corners in the plane coordinates, e.g. (x, y) for Xy
+    pub min: Vector2f,
+    pub max: Vector2f,
+    pub k: f32,
+    pub material: Option<Box<dyn Material>>
+}
+
+impl AxisAlignedRect {
+
+    pub fn xy(x0: f32, x1: f32, y0: f32, y1: f32, k: f32, material: Option<Box<dyn Material>>) -> AxisAlignedRect {
+        AxisAlignedRect { plane: RectPlane::Xy, min: Vector2f::new(x0, y0), max: Vector2f::new(x1, y1), k, material }
+    }
+
+    pub fn yz(y0: f32, y1: f32, z0: f32, z1: f32, k: f32, material: Option<Box<dyn Material>>) -> AxisAlignedRect {
+        AxisAlignedRect { plane: RectPlane::Yz, min: Vector2f::new(y0, z0), max: Vector2f::new(y1, z1), k, material }
+    }
+
+    pub fn xz(x0: f32, x1: f32, z0: f32, z1: f32, k: f32, material: Option<Box<dyn Material>>) -> AxisAlignedRect {
+        AxisAlignedRect { plane: RectPlane::Xz, min: Vector2f::new(x0, z0), max: Vector2f::new(x1, z1), k, material }
+    }
+
+}
+
+impl Hittable for AxisAlignedRect {
+
+    fn ray_intersaction(&self, ray: &Ray, t_min:f32, t_max:f32) -> Option<HitPoint> {
+        let (a, b, normal_axis) = self.plane.axes();
+        let t = (self.k - ray.origin[normal_axis]) / ray.direction[normal_axis];
+        // also rejects NaN, for rays parallel to the plane
+        if !(t > t_min && t < t_max) {
+            return None;
+        }
+        let position = ray.at(t);
+        if position[a] < self.min.x || position[a] > self.max.x
+                || position[b] < self.min.y || position[b] > self.max.y {
+            return None;
+        }
+
+        let mut outward_normal = Vector3f::zeros();
+        outward_normal[normal_axis] = 1.0;
+        let is_front_face = ray.direction[normal_axis] < 0.0;
+        let extent = self.max - self.min;
+        let (mut dpdu, mut dpdv) = (Vector3f::zeros(), Vector3f::zeros());
+        dpdu[a] = extent.x;
+        dpdv[b] = extent.y;
+        Some(HitPoint {
+            t,
+            position,
+            normal: if is_front_face { outward_normal } else { -outward_normal },
+            front_face: is_front_face,
+            uv: Vector2f::new((position[a] - self.min.x) / extent.x, (position[b] - self.min.y) / extent.y),
+            dpdu,
+            dpdv,
+        })
+    }
+
+    fn material(&self) -> &Option<Box<dyn Material>> {
+        &self.material
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let (a, b, normal_axis) = self.plane.axes();
+        let mut min = Vector3f::zeros();
+        let mut max = Vector3f::zeros();
+        min[a] = self.min.x;
+        min[b] = self.min.y;
+        min[normal_axis] = self.k;
+        max[a] = self.max.x;
+        max[b] = self.max.y;
+        max[normal_axis] = self.k;
+        Some(padded_aabb(Aabb::new(min, max)))
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rect_hit_uv_and_faces() {
+        let rect = AxisAlignedRect::yz(0.0, 2.0, -1.0, 3.0, 1.0, None);
+
+        let ray = Ray { origin: Vector3f::new(3.0, 0.5, 2.0), direction: Vector3f::new(-1.0, 0.0, 0.0), time: 0.0 };
+        let hit = rect.ray_intersaction(&ray, 0.001, f32::INFINITY).unwrap();
+        assert!((hit.t - 2.0).abs() < 1e-5);
+        assert!(hit.front_face);
+        assert_eq!(hit.normal, Vector3f::new(1.0, 0.0, 0.0));
+        // u along y, v along z
+        assert!((hit.uv - Vector2f::new(0.25, 0.75)).norm() < 1e-5);
+
+        let back_ray = Ray { origin: Vector3f::new(-1.0, 0.5, 2.0), direction: Vector3f::new(1.0, 0.0, 0.0), time: 0.0 };
+        let back_hit = rect.ray_intersaction(&back_ray, 0.001, f32::INFINITY).unwrap();
+        assert!((back_hit.t - 2.0).abs() < 1e-5);
+        assert!(!back_hit.front_face);
+        assert_eq!(back_hit.normal, Vector3f::new(-1.0, 0.0, 0.0));
+
+        // outside the rectangle, and parallel to its plane
+        let outside = Ray { origin: Vector3f::new(3.0, 2.5, 2.0), direction: Vector3f::new(-1.0, 0.0, 0.0), time: 0.0 };
+        assert!(rect.ray_intersaction(&outside, 0.001, f32::INFINITY).is_none());
+        let parallel = Ray { origin: Vector3f::new(3.0, 0.5, 2.0), direction: Vector3f::new(0.0, 1.0, 0.0), time: 0.0 };
+        assert!(rect.ray_intersaction(&parallel, 0.001, f32::INFINITY).is_none());
+        assert!(rect.bounding_box().unwrap().hit(&ray, 0.001, f32::INFINITY));
+    }
+}
@@ -0,0 +1,286 @@
+use crate::types::{to_vector3f, Vector3f};
+use crate::raycasting::ray::{Ray, HitPoint, SurfaceCrossing};
+use super::hittable::Hittable;
+use super::aabb::Aabb;
+use super::sphere::{sphere_tangents, sphere_uv};
+use crate::material::material::Material;
+use dyn_clone::DynClone;
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+
+/// the march stops when closer than this to the surface
+const HIT_DISTANCE: f32 = 1e-4;
+const MAX_STEPS: usize = 256;
+/// how far unbounded SDFs are marched
+const MAX_UNBOUNDED_DISTANCE: f32 = 100.0;
+/// finite difference step of the normals
+const NORMAL_STEP: f32 = 1e-4;
+
+/// Signed distance function: distance from the point to the surface,
+/// negative inside. It must not overestimate the distance, the sphere tracing
+/// steps by it
+pub trait Sdf : Send + Sync + DynClone + Debug {
+    fn distance(&self, point: &Vector3f) -> f32;
+
+    /// box containing the surface, None if it is unbounded
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+}
+
+dyn_clone::clone_trait_object!(Sdf);
+
+/// Expression tree of distance functions, the SDFs of scene files
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SdfExpr {
+    Sphere { center: [f32; 3], radius: f32 },
+    /// box with half_size extents from the center, edges rounded by rounding
+    Cuboid { center: [f32; 3], half_size: [f32; 3], #[serde(default)] rounding: f32 },
+    /// around the y axis
+    Torus { center: [f32; 3], major_radius: f32, minor_radius: f32 },
+    /// fractal at the origin, about 1.2 in radius. The classic one has power 8
+    Mandelbulb { power: f32, iterations: usize },
+    Union { children: Vec<SdfExpr> },
+    /// union blending the surfaces closer than smoothness
+    SmoothUnion { children: Vec<SdfExpr>, smoothness: f32 },
+    /// infinite copies of the child, one every period along each axis, 0 to not repeat
+    Repeat { period: [f32; 3], child: Box<SdfExpr> },
+}
+
+/// polynomial smooth minimum
+fn smooth_min(a: f32, b: f32, smoothness: f32) -> f32 {
+    if smoothness <= 0.0 {
+        return a.min(b);
+    }
+    let h = (0.5 + 0.5 * (b - a) / smoothness).clamp(0.0, 1.0);
+    b + (a - b) * h - smoothness * h * (1.0 - h)
+}
+
+fn mandelbulb_distance(point: &Vector3f, power: f32, iterations: usize) -> f32 {
+    let mut z = *point;
+    let mut derivative = 1.0;
+    let mut radius = z.norm();
+    for _ in 0..iterations {
+        if radius > 2.0 || radius == 0.0 {
+            break;
+        }
+        let theta = (z.z / radius).acos() * power;
+        let phi = z.y.atan2(z.x) * power;
+        derivative = radius.powf(power - 1.0) * power * derivative + 1.0;
+        z = radius.powf(power) * Vector3f::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos()) + point;
+        radius = z.norm();
+    }
+    if radius == 0.0 {
+        return 0.0;
+    }
+    0.5 * radius.ln() * radius / derivative
+}
+
+impl Sdf for SdfExpr {
+
+    fn distance(&self, point: &Vector3f) -> f32 {
+        match self {
+            SdfExpr::Sphere { center, radius } => (point - to_vector3f(*center)).norm() - radius,
+            SdfExpr::Cuboid { center, half_size, rounding } => {
+                let q = (point - to_vector3f(*center)).abs() - to_vector3f(*half_size) + Vector3f::repeat(*rounding);
+                q.sup(&Vector3f::zeros()).norm() + q.max().min(0.0) - rounding
+            },
+            SdfExpr::Torus { center, major_radius, minor_radius } => {
+                let local = point - to_vector3f(*center);
+                let ring_distance = (local.x * local.x + local.z * local.z).sqrt() - major_radius;
+                (ring_distance * ring_distance + local.y * local.y).sqrt() - minor_radius
+            },
+            SdfExpr::Mandelbulb { power, iterations } => mandelbulb_distance(point, *power, *iterations),
+            SdfExpr::Union { children } => children.iter()
+                .map(|child| child.distance(point))
+                .fold(f32::INFINITY, f32::min),
+            SdfExpr::SmoothUnion { children, smoothness } => children.iter()
+                .map(|child| child.distance(point))
+                .reduce(|a, b| smooth_min(a, b, *smoothness))
+                .unwrap_or(f32::INFINITY),
+            SdfExpr::Repeat { period, child } => {
+                let mut local = *point;
+                for axis in 0..3 {
+                    if period[axis] > 0.0 {
+                        local[axis] -= period[axis] * (local[axis] / period[axis]).round();
+                    }
+                }
+                child.distance(&local)
+            },
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        match self {
+            SdfExpr::Sphere { center, radius } => {
+                let extent = Vector3f::repeat(radius.abs());
+                Some(Aabb::new(to_vector3f(*center) - extent, to_vector3f(*center) + extent))
+            },
+            SdfExpr::Cuboid { center, half_size, .. } => {
+                let extent = to_vector3f(*half_size).abs();
+                Some(Aabb::new(to_vector3f(*center) - extent, to_vector3f(*center) + extent))
+            },
+            SdfExpr::Torus { center, major_radius, minor_radius } => {
+                let horizontal = major_radius.abs() + minor_radius.abs();
+                let extent = Vector3f::new(horizontal, minor_radius.abs(), horizontal);
+                Some(Aabb::new(to_vector3f(*center) - extent, to_vector3f(*center) + extent))
+            },
+            SdfExpr::Mandelbulb { .. } => Some(Aabb::new(Vector3f::repeat(-1.25), Vector3f::repeat(1.25))),
+            SdfExpr::Union { children } => children.iter()
+                .map(|child| child.bounding_box())
+                .reduce(|a, b| Some(a?.surrounding(&b?)))
+                .flatten(),
+            SdfExpr::SmoothUnion { children, smoothness } => {
+                let aabb = children.iter()
+                    .map(|child| child.bounding_box())
+                    .reduce(|a, b| Some(a?.surrounding(&b?)))
+                    .flatten()?;
+                // blending grows the union by at most a quarter of the smoothness
+                let padding = Vector3f::repeat(smoothness.abs() * 0.25);
+                Some(Aabb::new(aabb.min - padding, aabb.max + padding))
+            },
+            SdfExpr::Repeat { .. } => None,
+        }
+    }
+
+}
+
+/// Hittable found by sphere tracing a signed distance function.
+/// Normals are the gradient of the SDF by central differences,
+/// uv map the normal direction like `Sphere` does
+#[derive(Clone, Debug)]
+pub struct SdfHittable {
+    sdf: Box<dyn Sdf>,
+    /// the march is limited to this box, when set
+    bounds: Option<Aabb>,
+    pub material: Option<Box<dyn Material>>
+}
+
+impl SdfHittable {
+
+    /// bounded by the bounding box of the SDF
+    pub fn new(sdf: Box<dyn Sdf>, material: Option<Box<dyn Material>>) -> SdfHittable {
+        let bounds = sdf.bounding_box();
+        SdfHittable { sdf, bounds, material }
+    }
+
+    /// bounds are needed to render unbounded SDFs, as repetitions, efficiently
+    pub fn with_bounds(sdf: Box<dyn Sdf>, bounds: Aabb, material: Option<Box<dyn Material>>) -> SdfHittable {
+        SdfHittable { sdf, bounds: Some(bounds), material }
+    }
+
+    pub fn normal(&self, point: &Vector3f) -> Vector3f {
+        let mut gradient = Vector3f::zeros();
+        for axis in 0..3 {
+            let mut step = Vector3f::zeros();
+            step[axis] = NORMAL_STEP;
+            gradient[axis] = self.sdf.distance(&(point + step)) - self.sdf.distance(&(point - step));
+        }
+        gradient.normalize()
+    }
+
+    /// the range of t to march, within the bounds
+    fn march_range(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<(f32, f32)> {
+        match &self.bounds {
+            Some(bounds) => bounds.clip(ray, t_min, t_max),
+            None => Some((t_min, t_max.min(MAX_UNBOUNDED_DISTANCE / ray.direction.norm()))),
+        }
+    }
+
+}
+
+impl Hittable for SdfHittable {
+
+    fn ray_intersaction(&self, ray: &Ray, t_min:f32, t_max:f32) -> Option<HitPoint> {
+        let (t_start, t_end) = self.march_range(ray, t_min, t_max)?;
+        let direction_norm = ray.direction.norm();
+        // rays leaving a surface start on it, they hit only after moving away from it.
+        // Marching from the entry in the bounds, any surface there is a hit
+        let mut left_start = t_start > t_min;
+        let mut t = t_start;
+        for _ in 0..MAX_STEPS {
+            let distance = self.sdf.distance(&ray.at(t));
+            if left_start && distance.abs() < HIT_DISTANCE {
+                if t <= t_min || t >= t_end {
+                    return None;
+                }
+                let position = ray.at(t);
+                let outward_normal = self.normal(&position);
+                // uv and tangents of the point of the unit sphere along the normal
+                let (dpdu, dpdv) = sphere_tangents(&outward_normal);
+                let crossing = SurfaceCrossing { t, position, outward_normal, uv: sphere_uv(&outward_normal), dpdu, dpdv };
+                return Some(crossing.to_hitpoint(ray));
+            }
+            left_start = left_start || distance.abs() > 2.0 * HIT_DISTANCE;
+            t += distance.abs().max(HIT_DISTANCE) / direction_norm;
+            if t > t_end {
+                return None;
+            }
+        }
+        None
+    }
+
+    fn material(&self) -> &Option<Box<dyn Material>> {
+        &self.material
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bounds
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geom::sphere::Sphere;
+    use rand::Rng;
+
+    #[test]
+    fn sdf_sphere_matches_analytic_sphere() {
+        let mut rng = rand::thread_rng();
+        let sphere = Sphere { origin: Vector3f::new(0.2, 0.1, -3.0), radius: 0.8, material: None };
+        let sdf = SdfHittable::new(Box::new(SdfExpr::Sphere { center: [0.2, 0.1, -3.0], radius: 0.8 }), None);
+
+        for _ in 0..500 {
+            let ray = Ray {
+                origin: Vector3f::zeros(),
+                direction: Vector3f::new(rng.gen_range(-0.4, 0.4), rng.gen_range(-0.4, 0.4), -1.0) * 2.0,
+                time: 0.0,
+            };
+            match (sphere.ray_intersaction(&ray, 0.001, f32::INFINITY), sdf.ray_intersaction(&ray, 0.001, f32::INFINITY)) {
+                // grazing rays stop early, within the hit distance of the surface
+                (Some(expected), Some(hit)) if expected.normal.dot(&ray.direction.normalize()).abs() < 0.2 => {
+                    assert!(((hit.position - sphere.origin).norm() - sphere.radius).abs() < 1e-3);
+                },
+                (Some(expected), Some(hit)) => {
+                    assert!((expected.t - hit.t).abs() < 1e-3);
+                    assert!((expected.normal - hit.normal).norm() < 1e-2);
+                    assert!(hit.front_face);
+
+                    // leaving the surface from inside, as refracted rays do
+                    let inner_ray = Ray { origin: hit.position, direction: ray.direction, time: ray.time };
+                    let inner_expected = sphere.ray_intersaction(&inner_ray, 0.001, f32::INFINITY).unwrap();
+                    let inner_hit = sdf.ray_intersaction(&inner_ray, 0.001, f32::INFINITY).unwrap();
+                    assert!((inner_expected.t - inner_hit.t).abs() < 1e-3);
+                    assert!(!inner_hit.front_face);
+                },
+                (None, None) => (),
+                // grazing rays
+                (Some(expected), None) => assert!(expected.normal.dot(&ray.direction.normalize()).abs() < 0.1),
+                (None, Some(hit)) => assert!(((hit.position - sphere.origin).norm() - sphere.radius).abs() < 1e-3),
+            }
+        }
+
+        let repeated = SdfExpr::Repeat { period: [2.0, 0.0, 0.0], child: Box::new(SdfExpr::Sphere { center: [0.0; 3], radius: 0.5 }) };
+        assert!((repeated.distance(&Vector3f::new(10.0, 1.0, 0.0)) - 0.5).abs() < 1e-5);
+        let blend = SdfExpr::SmoothUnion { children: vec![
+            SdfExpr::Sphere { center: [-0.6, 0.0, 0.0], radius: 0.5 },
+            SdfExpr::Sphere { center: [0.6, 0.0, 0.0], radius: 0.5 },
+        ], smoothness: 0.6 };
+        // the blend fills the gap between the spheres
+        assert!(blend.distance(&Vector3f::zeros()) < 0.0);
+    }
+}
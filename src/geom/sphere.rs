@@ -1,16 +1,43 @@
-use crate::types::Vector3f;
-use crate::raycasting::ray::{Ray, HitPoint};
+use crate::types::{Vector2f, Vector3f};
+use crate::raycasting::ray::{Ray, HitPoint, RayInterval, SurfaceCrossing};
 use super::hittable::Hittable;
+use super::aabb::Aabb;
 use crate::material::material::Material;
 
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct Sphere {
    pub origin: Vector3f,
    pub radius: f32,
    pub material: Option<Box<dyn Material>>
 }
 
+/// longitude and latitude of a point of the unit sphere, mapped to [0, 1].
+/// u starts at -x going around y, v goes from -y to +y
+pub fn sphere_uv(point: &Vector3f) -> Vector2f {
+    let theta = (-point.y).clamp(-1.0, 1.0).acos();
+    let phi = (-point.z).atan2(point.x) + std::f32::consts::PI;
+    Vector2f::new(phi / (2.0 * std::f32::consts::PI), theta / std::f32::consts::PI)
+}
+
+/// derivative of a point relative to the y axis with the angle u of `sphere_uv`,
+/// shared by the shapes going around the y axis
+pub fn around_y_tangent(local: &Vector3f) -> Vector3f {
+    Vector3f::new(local.z, 0.0, -local.x) * (2.0 * std::f32::consts::PI)
+}
+
+/// unit vector from the y axis towards a point, zero on the axis
+pub fn radial_direction(local: &Vector3f) -> Vector3f {
+    Vector3f::new(local.x, 0.0, local.z).try_normalize(1e-9).unwrap_or_else(Vector3f::zeros)
+}
+
+/// derivatives with the uv of `sphere_uv` of a point of a sphere, relative to its origin
+pub fn sphere_tangents(local: &Vector3f) -> (Vector3f, Vector3f) {
+    let distance_to_axis = (local.x * local.x + local.z * local.z).sqrt();
+    let dpdv = (radial_direction(local) * -local.y + Vector3f::new(0.0, distance_to_axis, 0.0)) * std::f32::consts::PI;
+    (around_y_tangent(local), dpdv)
+}
+
 pub fn hit_sphere(sphere: &Sphere, ray: &Ray, t_min:f32, t_max:f32) -> Option<HitPoint> {
     let oc = ray.origin - sphere.origin;
     let a = ray.direction.dot(&ray.direction);
@@ -20,34 +47,31 @@ pub fn hit_sphere(sphere: &Sphere, ray: &Ray, t_min:f32, t_max:f32) -> Option<Hi
     if discriminant > 0.0f32 {
         let t1 = (-b - discriminant.sqrt() ) / (2.0*a);
         let t2 = (-b + discriminant.sqrt() ) / (2.0*a);
-        let t1_hit_position = ray.direction * t1;
-        let t2_hit_position = ray.direction * t2;
-
-        // let t1_after_ray_origin = (t1_hit_position-ray.origin).dot(&ray.direction) > 0.0f32;
-        // let t2_after_ray_origin = (t2_hit_position-ray.origin).dot(&ray.direction) > 0.0f32;
-        let t1_after_ray_origin = t1 > t_min;
-        // let t2_after_ray_origin = t2 > t_min;
-        let t2_after_ray_origin = false;
-
-        if t1_after_ray_origin || t2_after_ray_origin {
-            let hit_position = if t1_after_ray_origin {
-                t1_hit_position
-            } else {
-                assert!(false);
-                t2_hit_position
-            };
-            let outward_normal = (hit_position - sphere.origin).normalize();
-            let is_front_face = outward_normal.dot(&ray.direction) < 0f32;
-            let normal = if is_front_face {outward_normal} else {-outward_normal}; 
-            let hitpoint = HitPoint{
-                position: hit_position,
-                normal: normal,
-                front_face: is_front_face
-            };
-            Some(hitpoint)
+
+        // t1 <= t2, take the first one inside the valid range
+        let t = if t1 > t_min && t1 < t_max {
+            t1
+        } else if t2 > t_min && t2 < t_max {
+            t2
         } else {
-            None
-        }
+            return None;
+        };
+
+        let hit_position = ray.at(t);
+        let outward_normal = (hit_position - sphere.origin) / sphere.radius;
+        let is_front_face = outward_normal.dot(&ray.direction) < 0f32;
+        let normal = if is_front_face {outward_normal} else {-outward_normal};
+        let (dpdu, dpdv) = sphere_tangents(&(hit_position - sphere.origin));
+        let hitpoint = HitPoint{
+            t,
+            position: hit_position,
+            normal,
+            front_face: is_front_face,
+            uv: sphere_uv(&outward_normal),
+            dpdu,
+            dpdv,
+        };
+        Some(hitpoint)
     } else {
         None
     }
@@ -57,11 +81,37 @@ pub fn hit_sphere(sphere: &Sphere, ray: &Ray, t_min:f32, t_max:f32) -> Option<Hi
 impl Hittable for Sphere {
 
     fn ray_intersaction(&self, ray: &Ray, t_min:f32, t_max:f32) -> Option<HitPoint> {
-        return hit_sphere(self, ray, t_min, t_max);
+        hit_sphere(self, ray, t_min, t_max)
     }
 
     fn material(&self) -> &Option<Box<dyn Material>> {
         &self.material
     }
 
-}
\ No newline at end of file
+    fn bounding_box(&self) -> Option<Aabb> {
+        let radius = Vector3f::repeat(self.radius.abs());
+        Some(Aabb::new(self.origin - radius, self.origin + radius))
+    }
+
+    fn ray_intervals(&self, ray: &Ray) -> Option<Vec<RayInterval>> {
+        let oc = ray.origin - self.origin;
+        let a = ray.direction.dot(&ray.direction);
+        let half_b = oc.dot(&ray.direction);
+        let c = oc.dot(&oc) - self.radius*self.radius;
+        let discriminant = half_b*half_b - a * c;
+        if discriminant <= 0.0 {
+            return Some(Vec::new());
+        }
+        let crossing = |t: f32| {
+            let position = ray.at(t);
+            let outward_normal = (position - self.origin) / self.radius;
+            let (dpdu, dpdv) = sphere_tangents(&(position - self.origin));
+            SurfaceCrossing { t, position, outward_normal, uv: sphere_uv(&outward_normal), dpdu, dpdv }
+        };
+        Some(vec![RayInterval {
+            enter: crossing((-half_b - discriminant.sqrt()) / a),
+            exit: crossing((-half_b + discriminant.sqrt()) / a),
+        }])
+    }
+
+}
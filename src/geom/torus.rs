@@ -0,0 +1,146 @@
+use crate::types::{Vector2f, Vector3f};
+use crate::raycasting::ray::{Ray, HitPoint, RayInterval, SurfaceCrossing};
+use super::hittable::Hittable;
+use super::aabb::Aabb;
+use super::polynomial::solve_quartic;
+use super::sphere::{around_y_tangent, radial_direction};
+use crate::material::material::Material;
+use std::f32::consts::PI;
+
+/// Torus around the y axis through center: a tube of minor_radius
+/// whose center line is a circle of major_radius.
+/// u goes around the y axis, v around the tube starting from the outer side
+#[derive(Clone, Debug)]
+pub struct Torus {
+    pub center: Vector3f,
+    pub major_radius: f32,
+    pub minor_radius: f32,
+    pub material: Option<Box<dyn Material>>
+}
+
+impl Torus {
+
+    /// outward normal at a point of the surface, relative to center
+    fn outward_normal(&self, local: &Vector3f) -> Vector3f {
+        let (major2, minor2) = (self.major_radius.powi(2), self.minor_radius.powi(2));
+        // gradient of (|p|^2 + R^2 - r^2)^2 - 4 R^2 (x^2 + z^2), divided by 4
+        let sum = local.norm_squared() - major2 - minor2;
+        Vector3f::new(local.x * sum, local.y * (sum + 2.0 * major2), local.z * sum).normalize()
+    }
+
+    /// all the t where the ray line crosses the surface, sorted
+    fn roots(&self, ray: &Ray) -> Vec<f32> {
+        // start from the closest point of the ray to the bounding sphere,
+        // the quartic coefficients lose precision far from the torus
+        let direction_norm = ray.direction.norm() as f64;
+        let unit_direction = ray.direction.map(|x| x as f64) / direction_norm;
+        let center_offset = (self.center - ray.origin).map(|x| x as f64);
+        let bounding_radius = (self.major_radius + self.minor_radius) as f64;
+        let shift = (center_offset.dot(&unit_direction) - bounding_radius).max(0.0);
+        let o = -center_offset + unit_direction * shift;
+        let d = unit_direction;
+
+        // |o + t d|^2 = t^2 + 2 (o.d) t + o.o, with |d| = 1
+        let (major2, minor2) = ((self.major_radius as f64).powi(2), (self.minor_radius as f64).powi(2));
+        let od = o.dot(&d);
+        let k = o.norm_squared() + major2 - minor2;
+        let mut roots : Vec<f32> = solve_quartic(
+            1.0,
+            4.0 * od,
+            2.0 * k + 4.0 * od * od - 4.0 * major2 * (d.x * d.x + d.z * d.z),
+            4.0 * od * k - 8.0 * major2 * (o.x * d.x + o.z * d.z),
+            k * k - 4.0 * major2 * (o.x * o.x + o.z * o.z))
+            .into_iter()
+            .map(|root| ((root + shift) / direction_norm) as f32)
+            .collect();
+        roots.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        roots
+    }
+
+    fn crossing(&self, ray: &Ray, t: f32) -> SurfaceCrossing {
+        let position = ray.at(t);
+        let local = position - self.center;
+        let ring_distance = (local.x * local.x + local.z * local.z).sqrt() - self.major_radius;
+        let u = ((-local.z).atan2(local.x) + PI) / (2.0 * PI);
+        let v = (local.y.atan2(ring_distance) + 2.0 * PI) % (2.0 * PI) / (2.0 * PI);
+        let around_tube = radial_direction(&local) * -local.y + Vector3f::new(0.0, ring_distance, 0.0);
+        SurfaceCrossing {
+            t,
+            position,
+            outward_normal: self.outward_normal(&local),
+            uv: Vector2f::new(u, v),
+            dpdu: around_y_tangent(&local),
+            dpdv: around_tube * (2.0 * PI),
+        }
+    }
+
+}
+
+impl Hittable for Torus {
+
+    fn ray_intersaction(&self, ray: &Ray, t_min:f32, t_max:f32) -> Option<HitPoint> {
+        let t = self.roots(ray).into_iter().find(|t| *t > t_min && *t < t_max)?;
+        Some(self.crossing(ray, t).to_hitpoint(ray))
+    }
+
+    fn material(&self) -> &Option<Box<dyn Material>> {
+        &self.material
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let horizontal = self.major_radius.abs() + self.minor_radius.abs();
+        let extent = Vector3f::new(horizontal, self.minor_radius.abs(), horizontal);
+        Some(Aabb::new(self.center - extent, self.center + extent))
+    }
+
+    /// the roots pair up into the parts of the ray inside the tube
+    fn ray_intervals(&self, ray: &Ray) -> Option<Vec<RayInterval>> {
+        let roots = self.roots(ray);
+        Some(roots.chunks_exact(2)
+            .map(|pair| RayInterval { enter: self.crossing(ray, pair[0]), exit: self.crossing(ray, pair[1]) })
+            .collect())
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geom::cylinder::tests::{first_crossing, random_ray_towards_origin};
+
+    #[test]
+    fn torus_matches_implicit_equation() {
+        let torus = Torus { center: Vector3f::new(0.0, 0.5, 0.0), major_radius: 0.8, minor_radius: 0.3, material: None };
+        let inside = |p: &Vector3f| {
+            let local = p - Vector3f::new(0.0, 0.5, 0.0);
+            (local.norm_squared() + 0.64 - 0.09).powi(2) - 4.0 * 0.64 * (local.x * local.x + local.z * local.z)
+        };
+
+        let mut mismatches = 0;
+        for _ in 0..200 {
+            let ray = random_ray_towards_origin();
+            match (torus.ray_intersaction(&ray, 0.001, 20.0), first_crossing(inside, &ray, 20.0)) {
+                (Some(hit), Some(t)) => {
+                    assert!((hit.t - t).abs() < 2e-3, "{:?} {} {}", ray, hit.t, t);
+                    assert!(hit.front_face);
+                    assert!(inside(&(hit.position + hit.normal * 1e-2)) > 0.0);
+                    // the tangents lie in the surface
+                    assert!(hit.dpdu.dot(&hit.normal).abs() < 1e-3 && hit.dpdv.dot(&hit.normal).abs() < 1e-3);
+                },
+                (None, None) => (),
+                // grazing rays can be missed by the marching
+                _ => mismatches += 1,
+            }
+        }
+        assert!(mismatches <= 4);
+
+        // through the hole, then from inside the tube
+        let ray = Ray { origin: Vector3f::new(0.0, 3.0, 0.0), direction: Vector3f::new(0.0, -1.0, 0.0), time: 0.0 };
+        assert!(torus.ray_intersaction(&ray, 0.001, 20.0).is_none());
+        let ray = Ray { origin: Vector3f::new(0.8, 0.5, 0.0), direction: Vector3f::new(1.0, 0.0, 0.0), time: 0.0 };
+        let hit = torus.ray_intersaction(&ray, 0.001, 20.0).unwrap();
+        assert!((hit.t - 0.3).abs() < 1e-4);
+        assert!(!hit.front_face);
+    }
+}
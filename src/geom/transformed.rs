@@ -0,0 +1,179 @@
+use crate::types::Vector3f;
+use crate::raycasting::ray::{Ray, HitPoint, RayInterval, SurfaceCrossing};
+use super::hittable::{surface_transmittance, Hittable};
+use super::aabb::Aabb;
+use crate::material::material::Material;
+use nalgebra::{Matrix4, Point3, Unit};
+use std::sync::Arc;
+
+/// A hittable placed in the scene by an affine transform.
+/// The object is shared, so the same geometry can be instanced many times
+/// at the cost of a matrix per instance
+#[derive(Clone, Debug)]
+pub struct Transformed {
+    object: Arc<dyn Hittable>,
+    /// object space to world space
+    transform: Matrix4<f32>,
+    inverse_transform: Matrix4<f32>,
+    /// replaces the material of the object when set
+    material: Option<Box<dyn Material>>,
+}
+
+impl Transformed {
+
+    /// panics if the transform is not invertible
+    pub fn new(object: Arc<dyn Hittable>, transform: Matrix4<f32>) -> Transformed {
+        let inverse_transform = transform.try_inverse()
+            .expect("Transformed needs an invertible transform");
+        Transformed {
+            object,
+            transform,
+            inverse_transform,
+            material: None
+        }
+    }
+
+    pub fn with_material(object: Arc<dyn Hittable>, transform: Matrix4<f32>,
+            material: Box<dyn Material>) -> Transformed {
+        let mut transformed = Transformed::new(object, transform);
+        transformed.material = Some(material);
+        transformed
+    }
+
+    pub fn transform(&self) -> &Matrix4<f32> {
+        &self.transform
+    }
+
+    /// the ray in object space. The direction is not normalized, so t is the same in both spaces
+    fn object_ray(&self, ray: &Ray) -> Ray {
+        Ray {
+            origin: self.inverse_transform.transform_point(&Point3::from(ray.origin)).coords,
+            direction: self.inverse_transform.transform_vector(&ray.direction),
+            time: ray.time,
+        }
+    }
+
+    /// normals transform with the inverse transpose
+    fn world_normal(&self, object_normal: &Vector3f) -> Vector3f {
+        self.inverse_transform.transpose()
+            .transform_vector(object_normal)
+            .normalize()
+    }
+
+    /// the world hitpoint in object space, where the object evaluates it
+    fn object_hitpoint(&self, object_ray: &Ray, hitpoint: &HitPoint) -> HitPoint {
+        HitPoint {
+            t: hitpoint.t,
+            position: object_ray.at(hitpoint.t),
+            normal: self.transform.transpose().transform_vector(&hitpoint.normal).normalize(),
+            front_face: hitpoint.front_face,
+            uv: hitpoint.uv,
+            dpdu: self.inverse_transform.transform_vector(&hitpoint.dpdu),
+            dpdv: self.inverse_transform.transform_vector(&hitpoint.dpdv),
+        }
+    }
+
+    fn world_crossing(&self, ray: &Ray, object_crossing: &SurfaceCrossing) -> SurfaceCrossing {
+        if object_crossing.t.is_infinite() {
+            return object_crossing.clone();
+        }
+        SurfaceCrossing {
+            t: object_crossing.t,
+            position: ray.at(object_crossing.t),
+            outward_normal: self.world_normal(&object_crossing.outward_normal),
+            uv: object_crossing.uv,
+            dpdu: self.transform.transform_vector(&object_crossing.dpdu),
+            dpdv: self.transform.transform_vector(&object_crossing.dpdv),
+        }
+    }
+
+}
+
+/// matrix that scales a point, rotates it around axis by angle_degrees and then translates it
+pub fn make_transform(translation: Vector3f, axis: Vector3f, angle_degrees: f32, scale: Vector3f) -> Matrix4<f32> {
+    let rotation = if angle_degrees == 0.0 || axis == Vector3f::zeros() {
+        Matrix4::identity()
+    } else {
+        Matrix4::from_axis_angle(&Unit::new_normalize(axis), angle_degrees.to_radians())
+    };
+    Matrix4::new_translation(&translation) * rotation * Matrix4::new_nonuniform_scaling(&scale)
+}
+
+impl Hittable for Transformed {
+
+    fn ray_intersaction(&self, ray: &Ray, t_min:f32, t_max:f32) -> Option<HitPoint> {
+        let object_hitpoint = self.object.ray_intersaction(&self.object_ray(ray), t_min, t_max)?;
+        Some(HitPoint {
+            t: object_hitpoint.t,
+            position: ray.at(object_hitpoint.t),
+            normal: self.world_normal(&object_hitpoint.normal),
+            front_face: object_hitpoint.front_face,
+            uv: object_hitpoint.uv,
+            // tangents transform as the positions
+            dpdu: self.transform.transform_vector(&object_hitpoint.dpdu),
+            dpdv: self.transform.transform_vector(&object_hitpoint.dpdv),
+        })
+    }
+
+    fn material(&self) -> &Option<Box<dyn Material>> {
+        if self.material.is_some() {
+            &self.material
+        } else {
+            self.object.material()
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.object.bounding_box().map(|aabb| aabb.transformed(&self.transform))
+    }
+
+    fn ray_intervals(&self, ray: &Ray) -> Option<Vec<RayInterval>> {
+        let object_intervals = self.object.ray_intervals(&self.object_ray(ray))?;
+        Some(object_intervals.iter().map(|interval| RayInterval {
+            enter: self.world_crossing(ray, &interval.enter),
+            exit: self.world_crossing(ray, &interval.exit),
+        }).collect())
+    }
+
+    fn emitted(&self, ray: &Ray, hitpoint: &HitPoint) -> Vector3f {
+        let object_ray = self.object_ray(ray);
+        self.object.emitted(&object_ray, &self.object_hitpoint(&object_ray, hitpoint))
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min:f32, t_max:f32) -> f32 {
+        if self.material.is_some() {
+            return surface_transmittance(self, ray, t_min, t_max);
+        }
+        self.object.transmittance(&self.object_ray(ray), t_min, t_max)
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geom::sphere::Sphere;
+
+    #[test]
+    fn transformed_sphere_hit() {
+        let unit_sphere : Arc<dyn Hittable> = Arc::new(Sphere {
+            origin: Vector3f::zeros(),
+            radius: 1.0,
+            material: None
+        });
+        let transform = make_transform(Vector3f::new(0.0, 0.0, -5.0),
+            Vector3f::new(1.0, 0.0, 0.0), 90.0, Vector3f::new(1.0, 1.0, 2.0));
+        let instance = Transformed::new(unit_sphere, transform);
+
+        // the z scale of 2 is rotated onto the y axis
+        let ray = Ray { origin: Vector3f::zeros(), direction: Vector3f::new(0.0, 0.0, -2.0), time: 0.0 };
+        let hit = instance.ray_intersaction(&ray, 0.001, f32::INFINITY).unwrap();
+        assert!((hit.t - 2.0).abs() < 1e-5);
+        assert!((hit.normal - Vector3f::new(0.0, 0.0, 1.0)).norm() < 1e-5);
+
+        let aabb = instance.bounding_box().unwrap();
+        assert!((aabb.min - Vector3f::new(-1.0, -2.0, -6.0)).norm() < 1e-5);
+        assert!((aabb.max - Vector3f::new(1.0, 2.0, -4.0)).norm() < 1e-5);
+    }
+}
@@ -0,0 +1,138 @@
+use crate::types::{Vector2f, Vector3f};
+use crate::raycasting::ray::{Ray, HitPoint};
+use crate::voxel::VoxelGrid;
+use super::hittable::Hittable;
+use super::aabb::Aabb;
+use super::rand_geom::free_flight_distance;
+use crate::material::material::Material;
+use rand::Rng;
+use std::sync::Arc;
+
+/// Medium of varying density given by a voxel grid stretched over a box, as clouds or fire.
+/// Collisions are sampled by delta tracking and transmittance is estimated by ratio tracking,
+/// both against the largest density, so that neither is biased.
+/// The material is the phase function, the emission of the grid glows with emission_color
+#[derive(Clone, Debug)]
+pub struct VoxelMedium {
+    grid: Arc<VoxelGrid>,
+    bounds: Aabb,
+    density_scale: f32,
+    /// the largest density in the box, bounds the density everywhere
+    majorant: f32,
+    emission_color: Vector3f,
+    pub phase_function: Option<Box<dyn Material>>
+}
+
+impl VoxelMedium {
+
+    pub fn new(grid: Arc<VoxelGrid>, bounds: Aabb, density_scale: f32, emission_color: Vector3f,
+            phase_function: Option<Box<dyn Material>>) -> VoxelMedium {
+        let majorant = grid.max_density() * density_scale;
+        VoxelMedium { grid, bounds, density_scale, majorant, emission_color, phase_function }
+    }
+
+    /// the point in the unit cube the grid spans
+    fn grid_point(&self, position: &Vector3f) -> Vector3f {
+        (position - self.bounds.min).component_div(&self.bounds.extent())
+    }
+
+    pub fn density_at(&self, position: &Vector3f) -> f32 {
+        self.grid.density_at(&self.grid_point(position)) * self.density_scale
+    }
+
+    /// calls on_collision at the tentative collisions against the majorant,
+    /// with the ratio of the density there, until it returns true. The t of that collision if any
+    fn track(&self, ray: &Ray, t_min: f32, t_max: f32, mut on_collision: impl FnMut(f32) -> bool) -> Option<f32> {
+        if self.majorant <= 0.0 {
+            return None;
+        }
+        let (t_start, t_end) = self.bounds.clip(ray, t_min, t_max)?;
+        let direction_norm = ray.direction.norm();
+        let mut t = t_start;
+        loop {
+            t += free_flight_distance(self.majorant) / direction_norm;
+            if t >= t_end {
+                return None;
+            }
+            if on_collision(self.density_at(&ray.at(t)) / self.majorant) {
+                return Some(t);
+            }
+        }
+    }
+
+}
+
+impl Hittable for VoxelMedium {
+
+    /// delta tracking: a tentative collision is real with the probability of the density ratio
+    fn ray_intersaction(&self, ray: &Ray, t_min:f32, t_max:f32) -> Option<HitPoint> {
+        let mut rng = rand::thread_rng();
+        let t = self.track(ray, t_min, t_max, |density_ratio| rng.gen::<f32>() < density_ratio)?;
+        Some(HitPoint {
+            t,
+            position: ray.at(t),
+            normal: Vector3f::new(1.0, 0.0, 0.0),
+            front_face: true,
+            uv: Vector2f::zeros(),
+            dpdu: Vector3f::new(0.0, 1.0, 0.0),
+            dpdv: Vector3f::new(0.0, 0.0, 1.0),
+        })
+    }
+
+    fn material(&self) -> &Option<Box<dyn Material>> {
+        &self.phase_function
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+
+    fn emitted(&self, ray: &Ray, hitpoint: &HitPoint) -> Vector3f {
+        self.emission_color * self.grid.emission_at(&self.grid_point(&ray.at(hitpoint.t)))
+    }
+
+    /// ratio tracking: the product of the null collision probabilities
+    fn transmittance(&self, ray: &Ray, t_min:f32, t_max:f32) -> f32 {
+        let mut transmittance = 1.0;
+        self.track(ray, t_min, t_max, |density_ratio| {
+            transmittance *= 1.0 - density_ratio;
+            false
+        });
+        transmittance
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::material::HenyeyGreenstein;
+
+    #[test]
+    fn tracking_matches_beer_lambert() {
+        // the density is 0 in the lower half of the box and 2 in the upper one,
+        // with a linear ramp between the voxel centers
+        let density = (0..32).map(|index| if (index / 4) % 4 < 2 { 0.0 } else { 1.0 }).collect();
+        let grid = Arc::new(VoxelGrid::new([4, 4, 2], density, None));
+        let bounds = Aabb::new(Vector3f::new(-1.0, -1.0, -1.0), Vector3f::new(1.0, 1.0, 1.0));
+        let medium = VoxelMedium::new(grid, bounds, 2.0, Vector3f::zeros(), None);
+
+        // upwards along y: 0.75 units of full density and 0.5 of ramp, averaging half of it
+        let ray = Ray { origin: Vector3f::new(0.1, -3.0, 0.2), direction: Vector3f::new(0.0, 0.5, 0.0), time: 0.0 };
+        let expected = (-2.0f32 * (0.75 + 0.25)).exp();
+        let samples = 20000;
+        let crossed = (0..samples).filter(|_| medium.ray_intersaction(&ray, 0.001, f32::INFINITY).is_none()).count();
+        assert!((crossed as f32 / samples as f32 - expected).abs() < 0.02);
+        let ratio_tracked : f32 = (0..samples).map(|_| medium.transmittance(&ray, 0.001, f32::INFINITY)).sum();
+        assert!((ratio_tracked / samples as f32 - expected).abs() < 0.02);
+        // no collision in the empty half
+        let empty = Ray { origin: Vector3f::new(-3.0, -0.5, 0.0), direction: Vector3f::new(1.0, 0.0, 0.0), time: 0.0 };
+        assert!(medium.ray_intersaction(&empty, 0.001, f32::INFINITY).is_none());
+
+        // the mean cosine of the Henyey–Greenstein scattering angle is the anisotropy
+        let phase_function = HenyeyGreenstein::new(Vector3f::repeat(1.0), 0.6);
+        let mean_cosine : f32 = (0..samples).map(|index| phase_function.sample_cos_theta((index as f32 + 0.5) / samples as f32)).sum::<f32>() / samples as f32;
+        assert!((mean_cosine - 0.6).abs() < 1e-3);
+    }
+}
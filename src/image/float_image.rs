@@ -0,0 +1,67 @@
+use crate::image::image::Image;
+use crate::types::{Size2i, Vector2i, Vector3f, PointwiseSqrtExt, vector3f_to_rgb8};
+
+/// Linear, unclamped rgb image, as produced by the renderer before tonemapping
+#[derive(Clone, Debug)]
+pub struct FloatImage {
+    pub size: Size2i,
+    pub data: Vec<Vector3f>
+}
+
+impl FloatImage {
+
+    pub fn new(size: Size2i) -> FloatImage {
+        let data_size = (size.width()*size.height()) as usize;
+        FloatImage {
+            size,
+            data: vec![Vector3f::zeros(); data_size]
+        }
+    }
+
+    fn pixel_index(&self, position: Vector2i) -> usize {
+        assert!(position.y >= 0 && position.y < self.size.height());
+        assert!(position.x >= 0 && position.x < self.size.width());
+        (position.y * self.size.width() + position.x) as usize
+    }
+
+    /*
+        origin: upper left corner
+    */
+    pub fn pixel(&self, position: Vector2i) -> Vector3f {
+        self.data[self.pixel_index(position)]
+    }
+
+    pub fn set_pixel(&mut self, position: Vector2i, color: Vector3f) {
+        let pixel_index = self.pixel_index(position);
+        self.data[pixel_index] = color;
+    }
+
+    /// add other to this image, pixel by pixel
+    pub fn add(&mut self, other: &FloatImage) {
+        assert_eq!(self.size, other.size);
+        for (pixel, other_pixel) in self.data.iter_mut().zip(other.data.iter()) {
+            *pixel += other_pixel;
+        }
+    }
+
+    pub fn scaled(&self, scale: f32) -> FloatImage {
+        FloatImage {
+            size: self.size,
+            data: self.data.iter().map(|pixel| pixel * scale).collect()
+        }
+    }
+
+    /// apply gamma 2 correction and clamp to an 8 bit image
+    pub fn to_image(&self) -> Image {
+        let mut image = Image::new(self.size);
+        for image_y in 0..self.size.height() {
+            for image_x in 0..self.size.width() {
+                let pixel_position = Vector2i::new(image_x, image_y);
+                let corrected_color_vector = self.pixel(pixel_position).pointwise_sqrt();
+                image.set_pixel(pixel_position, vector3f_to_rgb8(corrected_color_vector));
+            }
+        }
+        image
+    }
+
+}
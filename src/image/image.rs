@@ -29,14 +29,9 @@ impl Image {
     pub fn set_pixel(&mut self, position: Vector2i, rgb: Rgb8) {
         assert!(position.y >= 0 && position.y < self.size.height());
         assert!(position.x >= 0 && position.x < self.size.width());
-        let image_size = self.size.height() * self.size.width();
         let pixel_index = position.y * self.size.width() + position.x;
 
-        // self.data[(0*image_size+pixel_index) as usize] = rgb.r;
-        // self.data[(1*image_size+pixel_index) as usize] = rgb.g;
-        // self.data[(2*image_size+pixel_index) as usize] = rgb.b;
-
-        self.data[(pixel_index*3+0) as usize] = rgb.r;
+        self.data[(pixel_index*3) as usize] = rgb.r;
         self.data[(pixel_index*3+1) as usize] = rgb.g;
         self.data[(pixel_index*3+2) as usize] = rgb.b;
     }
@@ -0,0 +1,23 @@
+#![allow(clippy::module_inception)]
+
+extern crate color;
+extern crate nalgebra;
+extern crate rand;
+extern crate dyn_clone;
+extern crate serde;
+extern crate serde_json;
+extern crate crc32fast;
+extern crate image as image_codecs;
+
+pub mod geom;
+pub mod image;
+pub mod ppm;
+pub mod pfm;
+pub mod raycasting;
+pub mod renderer;
+pub mod types;
+pub mod material;
+pub mod light;
+pub mod scene;
+pub mod screenshot;
+pub mod voxel;
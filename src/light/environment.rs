@@ -0,0 +1,330 @@
+use crate::types::{luminance, Vector3f};
+use crate::light::light::{Light, LightSample};
+use crate::pfm;
+use nalgebra::{Rotation3, Vector3};
+use rand::Rng;
+use std::f32::consts::PI;
+use std::fmt::Debug;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Light arriving from infinitely far in every direction, what rays leaving the scene find
+pub trait Environment : Send + Sync + Debug {
+    /// radiance arriving from the unit vector direction
+    fn radiance(&self, direction: &Vector3f) -> Vector3f;
+
+    /// whether the renderer samples the environment for direct lighting, otherwise
+    /// only the rays escaping the scene find its light
+    fn is_sampled(&self) -> bool {
+        false
+    }
+
+    /// a unit vector direction towards the environment with its solid angle density
+    fn sample(&self) -> Option<(Vector3f, f32)> {
+        None
+    }
+
+    fn pdf(&self, _direction: &Vector3f) -> f32 {
+        0.0
+    }
+}
+
+/// Blend from the bottom color, looking down, to the top one, looking up
+#[derive(Clone, Debug)]
+pub struct GradientEnvironment {
+    pub bottom: Vector3f,
+    pub top: Vector3f,
+}
+
+impl Default for GradientEnvironment {
+    /// white to light blue, the sky of the book
+    fn default() -> Self {
+        GradientEnvironment {
+            bottom: Vector3f::new(1.0, 1.0, 1.0),
+            top: Vector3f::new(0.5, 0.7, 1.0),
+        }
+    }
+}
+
+impl Environment for GradientEnvironment {
+
+    fn radiance(&self, direction: &Vector3f) -> Vector3f {
+        let t = 0.5 * (direction.y + 1.0);
+        (1.0 - t) * self.bottom + t * self.top
+    }
+
+}
+
+/// The same radiance from every direction
+#[derive(Clone, Debug)]
+pub struct ConstantEnvironment {
+    pub radiance: Vector3f,
+}
+
+impl Environment for ConstantEnvironment {
+
+    fn radiance(&self, _direction: &Vector3f) -> Vector3f {
+        self.radiance
+    }
+
+}
+
+/// Piecewise constant distribution over [0, 1] with one piece per weight
+#[derive(Clone, Debug)]
+struct Distribution1D {
+    weights: Vec<f32>,
+    /// cdf[i] is the sum of the weights before i, there is one more than the weights
+    cdf: Vec<f32>,
+}
+
+impl Distribution1D {
+
+    fn new(weights: Vec<f32>) -> Distribution1D {
+        let mut cdf = Vec::with_capacity(weights.len() + 1);
+        cdf.push(0.0);
+        for weight in &weights {
+            cdf.push(cdf.last().unwrap() + weight);
+        }
+        Distribution1D { weights, cdf }
+    }
+
+    fn total(&self) -> f32 {
+        *self.cdf.last().unwrap()
+    }
+
+    /// index of the piece where xi in [0, 1) falls and the position within it, in [0, 1)
+    fn sample(&self, xi: f32) -> (usize, f32) {
+        let target = xi * self.total();
+        // the last piece whose start is at most target, skipping the empty pieces
+        let mut index = (self.cdf.partition_point(|start| *start <= target) - 1).min(self.weights.len() - 1);
+        // xi * total may round up to the total, past the last non empty piece
+        while self.weights[index] <= 0.0 && index > 0 {
+            index -= 1;
+        }
+        let offset = ((target - self.cdf[index]) / self.weights[index]).clamp(0.0, 0.9999);
+        (index, offset)
+    }
+
+    /// density over [0, 1] in the piece
+    fn pdf(&self, index: usize) -> f32 {
+        self.weights[index] * self.weights.len() as f32 / self.total()
+    }
+
+}
+
+/// Equirectangular HDR image around the scene. The center of the image is towards -z
+/// and its top row is straight up, before the rotation around y.
+/// Directions are sampled in proportion to the luminance they bring
+#[derive(Clone, Debug)]
+pub struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    /// rows from the top, already multiplied by the intensity
+    pixels: Vec<Vector3f>,
+    rotation: Rotation3<f32>,
+    rows: Distribution1D,
+    /// the distribution of the columns in each row
+    columns: Vec<Distribution1D>,
+}
+
+impl EnvironmentMap {
+
+    /// rgb pixels, rows from the top
+    pub fn new(width: usize, height: usize, pixels: Vec<Vector3f>, rotation_degrees: f32, intensity: f32) -> EnvironmentMap {
+        assert!(width > 0 && height > 0 && pixels.len() == width * height, "EnvironmentMap needs a pixel per width x height");
+        let pixels : Vec<Vector3f> = pixels.into_iter().map(|pixel| pixel * intensity).collect();
+        // the rows near the poles cover a smaller solid angle
+        let columns : Vec<_> = pixels.chunks_exact(width).enumerate().map(|(y, row)| {
+            let sin_theta = (PI * (y as f32 + 0.5) / height as f32).sin();
+            Distribution1D::new(row.iter().map(|pixel| luminance(pixel).max(0.0) * sin_theta).collect())
+        }).collect();
+        let rows = Distribution1D::new(columns.iter().map(Distribution1D::total).collect());
+        EnvironmentMap {
+            width,
+            height,
+            pixels,
+            rotation: Rotation3::from_axis_angle(&Vector3::y_axis(), rotation_degrees.to_radians()),
+            rows,
+            columns,
+        }
+    }
+
+    /// load a Radiance .hdr or a .pfm image
+    pub fn load(path: &Path, rotation_degrees: f32, intensity: f32) -> io::Result<EnvironmentMap> {
+        let is_pfm = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("pfm"));
+        let (width, height, pixels) = if is_pfm {
+            let (buffer, width, height) = pfm::read_image_from_pfm(path)?;
+            let pixels = buffer.chunks_exact(3).map(|rgb| Vector3f::new(rgb[0], rgb[1], rgb[2])).collect();
+            (width as usize, height as usize, pixels)
+        } else {
+            let file = io::BufReader::new(std::fs::File::open(path)?);
+            let decoder = image_codecs::hdr::HdrDecoder::new(file)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            let metadata = decoder.metadata();
+            let pixels = decoder.read_image_hdr()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+                .into_iter().map(|rgb| Vector3f::new(rgb[0], rgb[1], rgb[2])).collect();
+            (metadata.width as usize, metadata.height as usize, pixels)
+        };
+        if width == 0 || height == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "empty environment map"));
+        }
+        Ok(EnvironmentMap::new(width, height, pixels, rotation_degrees, intensity))
+    }
+
+    /// the point of the image, in [0, 1]², the world direction is at
+    fn image_point(&self, direction: &Vector3f) -> (f32, f32) {
+        let local = self.rotation.inverse_transform_vector(direction);
+        let phi = local.x.atan2(-local.z);
+        // unlike the arc cosine of y, precise near the poles
+        let theta = local.x.hypot(local.z).atan2(local.y);
+        (0.5 + phi / (2.0 * PI), theta / PI)
+    }
+
+    fn direction(&self, u: f32, v: f32) -> Vector3f {
+        let phi = (u - 0.5) * 2.0 * PI;
+        let theta = v * PI;
+        let local = Vector3f::new(theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos());
+        self.rotation * local
+    }
+
+    fn pixel_index(&self, u: f32, v: f32) -> (usize, usize) {
+        let x = ((u * self.width as f32) as usize).min(self.width - 1);
+        let y = ((v * self.height as f32) as usize).min(self.height - 1);
+        (x, y)
+    }
+
+    /// the density over the image of the pixel divided by the solid angle a unit of the image covers at v
+    fn pixel_pdf(&self, x: usize, y: usize, v: f32) -> f32 {
+        let sin_theta = (v * PI).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        self.rows.pdf(y) * self.columns[y].pdf(x) / (2.0 * PI * PI * sin_theta)
+    }
+
+}
+
+impl Environment for EnvironmentMap {
+
+    fn radiance(&self, direction: &Vector3f) -> Vector3f {
+        let (u, v) = self.image_point(direction);
+        let (x, y) = self.pixel_index(u, v);
+        self.pixels[x + y * self.width]
+    }
+
+    fn is_sampled(&self) -> bool {
+        self.rows.total() > 0.0
+    }
+
+    fn sample(&self) -> Option<(Vector3f, f32)> {
+        if !self.is_sampled() {
+            return None;
+        }
+        let mut rng = rand::thread_rng();
+        let (y, row_offset) = self.rows.sample(rng.gen());
+        let (x, column_offset) = self.columns[y].sample(rng.gen());
+        // strictly inside the pixel, so that v stays off the poles where sin theta is 0
+        let inside = |offset: f32| offset.clamp(1e-3, 1.0 - 1e-3);
+        let u = (x as f32 + inside(column_offset)) / self.width as f32;
+        let v = (y as f32 + inside(row_offset)) / self.height as f32;
+        // the pdf of the pixel drawn, not of the direction mapped back to the image,
+        // which may round into a neighbour pixel
+        Some((self.direction(u, v), self.pixel_pdf(x, y, v)))
+    }
+
+    fn pdf(&self, direction: &Vector3f) -> f32 {
+        if !self.is_sampled() {
+            return 0.0;
+        }
+        let (u, v) = self.image_point(direction);
+        let (x, y) = self.pixel_index(u, v);
+        self.pixel_pdf(x, y, v)
+    }
+
+}
+
+/// An environment sampled for direct lighting as the other lights
+#[derive(Clone, Debug)]
+pub struct EnvironmentLight {
+    pub environment: Arc<dyn Environment>,
+}
+
+impl Light for EnvironmentLight {
+
+    fn sample(&self, _point: &Vector3f) -> Option<LightSample> {
+        let (direction, pdf) = self.environment.sample()?;
+        Some(LightSample {
+            direction,
+            distance: f32::INFINITY,
+            radiance: self.environment.radiance(&direction),
+            pdf,
+        })
+    }
+
+    fn is_delta(&self) -> bool {
+        false
+    }
+
+    fn pdf(&self, _point: &Vector3f, direction: &Vector3f) -> f32 {
+        self.environment.pdf(direction)
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn environment_map_sampling_matches_its_pdf() {
+        // a dim map with a bright spot, rotated a quarter turn
+        let (width, height) = (16, 8);
+        let pixels = (0..width * height)
+            .map(|index| if index == 3 + 2 * width { Vector3f::repeat(50.0) } else { Vector3f::repeat(0.5) })
+            .collect();
+        let map = EnvironmentMap::new(width, height, pixels, 90.0, 2.0);
+
+        // directions round trip through the image
+        let direction = Vector3f::new(0.3, 0.5, -0.8).normalize();
+        let (u, v) = map.image_point(&direction);
+        assert!((map.direction(u, v) - direction).norm() < 1e-5);
+
+        // the pdf integrates to 1 over the sphere, summed at the centers of a grid finer than the pixels
+        let (grid_width, grid_height) = (128, 64);
+        let mut integral = 0.0;
+        let mut irradiance = 0.0;
+        for y in 0..grid_height {
+            for x in 0..grid_width {
+                let (u, v) = ((x as f32 + 0.5) / grid_width as f32, (y as f32 + 0.5) / grid_height as f32);
+                let solid_angle = 2.0 * PI * PI * (v * PI).sin() / (grid_width * grid_height) as f32;
+                integral += map.pdf(&map.direction(u, v)) * solid_angle;
+                irradiance += map.radiance(&map.direction(u, v)).x * solid_angle;
+            }
+        }
+        assert!((integral - 1.0).abs() < 1e-3, "pdf integral {}", integral);
+
+        let samples = 50000;
+        // the light arriving from the whole sphere, estimated by importance sampling
+        let mut sampled = 0.0;
+        for _ in 0..samples {
+            let (direction, pdf) = map.sample().unwrap();
+            sampled += map.radiance(&direction).x / pdf;
+        }
+        let sampled = sampled / samples as f32;
+        assert!((sampled - irradiance).abs() < 0.02 * irradiance, "{} {}", sampled, irradiance);
+
+        // light only at the poles is still sampled, with a positive pdf
+        let poles = (0..4 * 3).map(|index| if index / 4 == 1 { Vector3f::zeros() } else { Vector3f::repeat(1.0) }).collect();
+        let poles = EnvironmentMap::new(4, 3, poles, 0.0, 1.0);
+        for _ in 0..10000 {
+            let (_, pdf) = poles.sample().unwrap();
+            assert!(pdf > 0.0 && pdf.is_finite());
+        }
+
+        let black = EnvironmentMap::new(1, 1, vec![Vector3f::zeros()], 0.0, 1.0);
+        assert!(!black.is_sampled() && black.sample().is_none());
+    }
+}
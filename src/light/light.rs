@@ -0,0 +1,340 @@
+use crate::types::Vector3f;
+use crate::geom::hittable::Hittable;
+use crate::geom::quad::Quad;
+use crate::geom::rand_geom::direction_around;
+use crate::geom::sphere::Sphere;
+use crate::material::material::DiffuseLight;
+use dyn_clone::DynClone;
+use rand::Rng;
+use std::f32::consts::PI;
+use std::fmt::Debug;
+
+/// Light arriving at a point from a sampled point of a light
+#[derive(Clone, Debug)]
+pub struct LightSample {
+    /// unit vector from the lit point towards the light
+    pub direction: Vector3f,
+    /// from the lit point to the light, infinite for directional lights
+    pub distance: f32,
+    /// radiance arriving along direction, irradiance for the lights that are a single
+    /// point or direction
+    pub radiance: Vector3f,
+    /// solid angle density of the sample, 1 for the lights that are a single point or direction
+    pub pdf: f32,
+}
+
+/// Light source sampled by the renderer towards the points it shades
+pub trait Light : Send + Sync + DynClone + Debug {
+    /// None if the light does not reach the point
+    fn sample(&self, point: &Vector3f) -> Option<LightSample>;
+
+    /// a single point or direction, that rays cannot hit and only sampling reaches
+    fn is_delta(&self) -> bool;
+
+    /// solid angle density of sample choosing the unit vector direction from point,
+    /// 0 for delta lights
+    fn pdf(&self, _point: &Vector3f, _direction: &Vector3f) -> f32 {
+        0.0
+    }
+
+    /// the surface of the light, for the lights that rays can hit
+    fn hittable(&self) -> Option<Box<dyn Hittable>> {
+        None
+    }
+}
+
+dyn_clone::clone_trait_object!(Light);
+
+/// Light emitted from a point in all directions, intensity in watts per steradian
+#[derive(Clone, Debug)]
+pub struct PointLight {
+    pub position: Vector3f,
+    pub intensity: Vector3f,
+}
+
+impl Light for PointLight {
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+
+    fn sample(&self, point: &Vector3f) -> Option<LightSample> {
+        let to_light = self.position - point;
+        let distance = to_light.norm();
+        Some(LightSample {
+            direction: to_light / distance,
+            distance,
+            radiance: self.intensity / (distance * distance),
+            pdf: 1.0,
+        })
+    }
+
+}
+
+/// Point light shining in a cone around direction. The intensity falls off smoothly
+/// from the inner angle to the outer one, there is no light outside it
+#[derive(Clone, Debug)]
+pub struct SpotLight {
+    pub position: Vector3f,
+    /// unit vector
+    pub direction: Vector3f,
+    pub cos_inner_angle: f32,
+    pub cos_outer_angle: f32,
+    pub intensity: Vector3f,
+}
+
+impl SpotLight {
+
+    /// angles in degrees from the direction
+    pub fn new(position: Vector3f, direction: Vector3f, inner_degrees: f32, outer_degrees: f32, intensity: Vector3f) -> SpotLight {
+        let outer_degrees = outer_degrees.max(inner_degrees);
+        SpotLight {
+            position,
+            direction: direction.normalize(),
+            cos_inner_angle: inner_degrees.to_radians().cos(),
+            cos_outer_angle: outer_degrees.to_radians().cos(),
+            intensity,
+        }
+    }
+
+    /// fraction of the intensity emitted at this cosine with the direction, smoothstep between the angles
+    fn falloff(&self, cos_angle: f32) -> f32 {
+        if cos_angle >= self.cos_inner_angle {
+            return 1.0;
+        }
+        let x = ((cos_angle - self.cos_outer_angle) / (self.cos_inner_angle - self.cos_outer_angle)).clamp(0.0, 1.0);
+        x * x * (3.0 - 2.0 * x)
+    }
+
+}
+
+impl Light for SpotLight {
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+
+    fn sample(&self, point: &Vector3f) -> Option<LightSample> {
+        let to_light = self.position - point;
+        let distance = to_light.norm();
+        let direction = to_light / distance;
+        let falloff = self.falloff(-direction.dot(&self.direction));
+        if falloff <= 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            direction,
+            distance,
+            radiance: self.intensity * falloff / (distance * distance),
+            pdf: 1.0,
+        })
+    }
+
+}
+
+/// Light arriving from infinitely far along a direction, as sunlight.
+/// Irradiance on a surface facing it
+#[derive(Clone, Debug)]
+pub struct DirectionalLight {
+    /// unit vector, where the light travels to
+    pub direction: Vector3f,
+    pub irradiance: Vector3f,
+}
+
+impl Light for DirectionalLight {
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+
+    fn sample(&self, _point: &Vector3f) -> Option<LightSample> {
+        Some(LightSample {
+            direction: -self.direction,
+            distance: f32::INFINITY,
+            radiance: self.irradiance,
+            pdf: 1.0,
+        })
+    }
+
+}
+
+/// Sphere emitting the same radiance from all its surface.
+/// It is sampled over the cone it covers seen from the lit point
+#[derive(Clone, Debug)]
+pub struct SphereLight {
+    pub center: Vector3f,
+    pub radius: f32,
+    pub radiance: Vector3f,
+}
+
+impl SphereLight {
+
+    /// 1 - the cosine of the half angle of the cone the sphere covers seen from point, None inside it.
+    /// Computed from the sine, the difference would be 0 for small or far spheres
+    fn one_minus_cos_max(&self, point: &Vector3f) -> Option<f32> {
+        let center_distance = (self.center - point).norm();
+        if center_distance <= self.radius {
+            return None;
+        }
+        let sin2_max = (self.radius / center_distance).powi(2);
+        Some(sin2_max / (1.0 + (1.0 - sin2_max).sqrt()))
+    }
+
+}
+
+impl Light for SphereLight {
+
+    fn is_delta(&self) -> bool {
+        false
+    }
+
+    fn sample(&self, point: &Vector3f) -> Option<LightSample> {
+        let one_minus_cos_max = self.one_minus_cos_max(point)?;
+        let to_center = self.center - point;
+        let center_distance = to_center.norm();
+        let axis = to_center / center_distance;
+
+        let mut rng = rand::thread_rng();
+        let cos_theta = 1.0 - rng.gen::<f32>() * one_minus_cos_max;
+        let direction = direction_around(&axis, cos_theta, 2.0 * PI * rng.gen::<f32>());
+
+        // the first crossing of the sphere along the direction
+        let along = center_distance * cos_theta;
+        let distance = along - (self.radius * self.radius - center_distance * center_distance * (1.0 - cos_theta * cos_theta)).max(0.0).sqrt();
+        Some(LightSample {
+            direction,
+            distance,
+            radiance: self.radiance,
+            pdf: 1.0 / (2.0 * PI * one_minus_cos_max),
+        })
+    }
+
+    fn pdf(&self, point: &Vector3f, direction: &Vector3f) -> f32 {
+        match self.one_minus_cos_max(point) {
+            Some(one_minus_cos_max) if direction.dot(&(self.center - point).normalize()) >= 1.0 - one_minus_cos_max =>
+                1.0 / (2.0 * PI * one_minus_cos_max),
+            _ => 0.0,
+        }
+    }
+
+    fn hittable(&self) -> Option<Box<dyn Hittable>> {
+        Some(Box::new(Sphere {
+            origin: self.center,
+            radius: self.radius,
+            material: Some(Box::new(DiffuseLight::new(self.radiance)))
+        }))
+    }
+
+}
+
+/// Parallelogram emitting from its front face, the side u × v points to.
+/// It is sampled uniformly over its area
+#[derive(Clone, Debug)]
+pub struct QuadLight {
+    pub corner: Vector3f,
+    pub u: Vector3f,
+    pub v: Vector3f,
+    pub radiance: Vector3f,
+}
+
+impl Light for QuadLight {
+
+    fn is_delta(&self) -> bool {
+        false
+    }
+
+    fn sample(&self, point: &Vector3f) -> Option<LightSample> {
+        let mut rng = rand::thread_rng();
+        let light_point = self.corner + self.u * rng.gen::<f32>() + self.v * rng.gen::<f32>();
+        let to_light = light_point - point;
+        let distance = to_light.norm();
+        let direction = to_light / distance;
+        let normal = self.u.cross(&self.v);
+        let area = normal.norm();
+        let cos_light = -direction.dot(&normal) / area;
+        if cos_light <= 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            direction,
+            distance,
+            radiance: self.radiance,
+            pdf: distance * distance / (cos_light * area),
+        })
+    }
+
+    fn pdf(&self, point: &Vector3f, direction: &Vector3f) -> f32 {
+        let normal = self.u.cross(&self.v);
+        let facing = direction.dot(&normal);
+        if facing >= 0.0 {
+            return 0.0;
+        }
+        let distance = (self.corner - point).dot(&normal) / facing;
+        if distance <= 0.0 {
+            return 0.0;
+        }
+        // coordinates of the hit along the edges, as in Quad
+        let offset = point + direction * distance - self.corner;
+        let w = normal / normal.norm_squared();
+        let alpha = w.dot(&offset.cross(&self.v));
+        let beta = w.dot(&self.u.cross(&offset));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return 0.0;
+        }
+        // the area times the cosine with the light normal is -facing
+        distance * distance / -facing
+    }
+
+    fn hittable(&self) -> Option<Box<dyn Hittable>> {
+        Some(Box::new(Quad::new(self.corner, self.u, self.v, Some(Box::new(DiffuseLight::new(self.radiance))))))
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// irradiance at the point on a surface facing the normal, estimated by sampling the light
+    fn estimate_irradiance(light: &dyn Light, point: &Vector3f, normal: &Vector3f) -> f32 {
+        let samples = 20000;
+        let total : f32 = (0..samples).filter_map(|_| light.sample(point))
+            .map(|sample| sample.radiance.x * sample.direction.dot(normal).max(0.0) / sample.pdf)
+            .sum();
+        total / samples as f32
+    }
+
+    #[test]
+    fn light_samples_give_the_irradiance() {
+        let up = Vector3f::new(0.0, 1.0, 0.0);
+        let point = Vector3f::zeros();
+
+        // a sphere of radiance L at distance d covers a cone giving π L sin²
+        let sphere = SphereLight { center: Vector3f::new(0.0, 4.0, 0.0), radius: 1.0, radiance: Vector3f::repeat(2.0) };
+        let expected = PI * 2.0 * (1.0f32 / 4.0).powi(2);
+        assert!((estimate_irradiance(&sphere, &point, &up) - expected).abs() < 0.01 * expected + 1e-3);
+
+        // a small quad straight above, facing down, is about a point of intensity L A
+        let quad = QuadLight {
+            corner: Vector3f::new(-0.05, 3.0, -0.05),
+            u: Vector3f::new(0.1, 0.0, 0.0),
+            v: Vector3f::new(0.0, 0.0, 0.1),
+            radiance: Vector3f::repeat(100.0),
+        };
+        assert!((estimate_irradiance(&quad, &point, &up) - 1.0 / 9.0).abs() < 1e-3);
+
+        // the pdf of a sampled direction is the one of the sample
+        for light in [&sphere as &dyn Light, &quad] {
+            let sample = light.sample(&point).unwrap();
+            assert!((light.pdf(&point, &sample.direction) - sample.pdf).abs() < 1e-3 * sample.pdf);
+            assert_eq!(light.pdf(&point, &-up), 0.0);
+        }
+
+        let spot = SpotLight::new(Vector3f::new(0.0, 2.0, 0.0), -up, 10.0, 20.0, Vector3f::repeat(4.0));
+        assert!((spot.sample(&point).unwrap().radiance.x - 1.0).abs() < 1e-5);
+        assert!(spot.sample(&Vector3f::new(2.0, 0.0, 0.0)).is_none());
+        let point_light = PointLight { position: Vector3f::new(0.0, 2.0, 0.0), intensity: Vector3f::repeat(4.0) };
+        assert_eq!(point_light.sample(&point).unwrap().radiance, Vector3f::repeat(1.0));
+    }
+}
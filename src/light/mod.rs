@@ -0,0 +1,3 @@
+pub mod light;
+pub mod environment;
+pub mod sky;
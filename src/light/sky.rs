@@ -0,0 +1,231 @@
+use crate::types::Vector3f;
+use crate::geom::rand_geom::{direction_around, random_unit_vector};
+use crate::light::environment::Environment;
+use rand::Rng;
+use std::f32::consts::PI;
+
+/// sky radiance per kcd/m² of the model, a clear sky is about 1 at the zenith
+const SKY_UNITS: f32 = 0.1;
+/// radiance of the sun disk above the atmosphere, in the same units
+const SUN_RADIANCE: f32 = 2.3e5;
+/// angular radius of the sun disk, in radians
+const SUN_ANGULAR_RADIUS: f32 = 0.00465;
+
+/// coefficients A to E of the Perez distribution, linear in the turbidity
+fn perez_coefficients(turbidity: f32, slopes: [f32; 5], offsets: [f32; 5]) -> [f32; 5] {
+    [0, 1, 2, 3, 4].map(|i| slopes[i] * turbidity + offsets[i])
+}
+
+/// Perez distribution at the zenith angle theta of the view, gamma away from the sun
+fn perez(coefficients: &[f32; 5], cos_theta: f32, gamma: f32) -> f32 {
+    let [a, b, c, d, e] = *coefficients;
+    (1.0 + a * (b / cos_theta.max(0.01)).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+}
+
+/// zenith chromaticity, a polynomial in the turbidity and the sun zenith angle
+fn zenith_chromaticity(turbidity: f32, sun_theta: f32, matrix: [[f32; 4]; 3]) -> f32 {
+    let turbidities = [turbidity * turbidity, turbidity, 1.0];
+    let angles = [sun_theta.powi(3), sun_theta.powi(2), sun_theta, 1.0];
+    (0..3).map(|row| turbidities[row] * (0..4).map(|column| matrix[row][column] * angles[column]).sum::<f32>()).sum()
+}
+
+/// linear sRGB of the CIE luminance Y and chromaticity x, y
+fn yxy_to_rgb(luminance: f32, x: f32, y: f32) -> Vector3f {
+    let big_x = x / y * luminance;
+    let big_z = (1.0 - x - y) / y * luminance;
+    Vector3f::new(
+        3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z,
+        -0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z,
+        0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z,
+    ).map(|channel| channel.max(0.0))
+}
+
+/// Preetham analytic model of a clear sky, with the sun disk seen through the atmosphere.
+/// The turbidity goes from 2, very clear, to 10, hazy. Below the horizon is a diffuse ground
+/// of the given albedo, lit by the sky and the sun.
+/// The sun is sampled directly as a light, the rest of the sky over the whole sphere
+#[derive(Clone, Debug)]
+pub struct PreethamSky {
+    /// unit vector towards the sun, kept above the horizon
+    sun_direction: Vector3f,
+    sun_theta: f32,
+    /// Perez coefficients of the luminance and of the x and y chromaticities
+    coefficients: [[f32; 5]; 3],
+    /// luminance and chromaticity at the zenith
+    zenith: [f32; 3],
+    sun_radiance: Vector3f,
+    /// 1 - the cosine of the angular radius of the sun
+    sun_one_minus_cos: f32,
+    ground_radiance: Vector3f,
+    /// chance to sample the sun rather than the whole sphere
+    sun_probability: f32,
+}
+
+impl PreethamSky {
+
+    pub fn new(sun_direction: Vector3f, turbidity: f32, ground_albedo: Vector3f) -> PreethamSky {
+        let turbidity = turbidity.clamp(1.7, 10.0);
+        let mut sun_direction = sun_direction.normalize();
+        sun_direction.y = sun_direction.y.max(0.01);
+        let sun_direction = sun_direction.normalize();
+        let sun_theta = sun_direction.y.acos();
+
+        let coefficients = [
+            perez_coefficients(turbidity, [0.1787, -0.3554, -0.0227, 0.1206, -0.0670], [-1.4630, 0.4275, 5.3251, -2.5771, 0.3703]),
+            perez_coefficients(turbidity, [-0.0193, -0.0665, -0.0004, -0.0641, -0.0033], [-0.2592, 0.0008, 0.2125, -0.8989, 0.0452]),
+            perez_coefficients(turbidity, [-0.0167, -0.0950, -0.0079, -0.0441, -0.0109], [-0.2608, 0.0092, 0.2102, -1.6537, 0.0529]),
+        ];
+        let chi = (4.0 / 9.0 - turbidity / 120.0) * (PI - 2.0 * sun_theta);
+        let zenith_luminance = (4.0453 * turbidity - 4.9710) * chi.tan() - 0.2155 * turbidity + 2.4192;
+        let zenith = [
+            zenith_luminance.max(0.0),
+            zenith_chromaticity(turbidity, sun_theta, [
+                [0.00166, -0.00375, 0.00209, 0.0],
+                [-0.02903, 0.06377, -0.03202, 0.00394],
+                [0.11693, -0.21196, 0.06052, 0.25886],
+            ]),
+            zenith_chromaticity(turbidity, sun_theta, [
+                [0.00275, -0.00610, 0.00317, 0.0],
+                [-0.04214, 0.08970, -0.04153, 0.00516],
+                [0.15346, -0.26756, 0.06670, 0.26688],
+            ]),
+        ];
+
+        let half_radius_sin = (SUN_ANGULAR_RADIUS / 2.0).sin();
+        let mut sky = PreethamSky {
+            sun_direction,
+            sun_theta,
+            coefficients,
+            zenith,
+            sun_radiance: PreethamSky::sun_transmittance(sun_theta, turbidity) * SUN_RADIANCE,
+            sun_one_minus_cos: 2.0 * half_radius_sin * half_radius_sin,
+            ground_radiance: Vector3f::zeros(),
+            sun_probability: 0.5,
+        };
+
+        // the ground reflects the light of the sky and the sun on a horizontal plane
+        let sun_irradiance = sky.sun_radiance * (2.0 * PI * sky.sun_one_minus_cos) * sun_direction.y;
+        let sky_irradiance = sky.sky_irradiance();
+        sky.ground_radiance = ground_albedo.component_mul(&(sun_irradiance + sky_irradiance)) / PI;
+        let (sun_power, sky_power) = (sun_irradiance.sum(), sky_irradiance.sum());
+        if sun_power + sky_power > 0.0 {
+            sky.sun_probability = (sun_power / (sun_power + sky_power)).clamp(0.2, 0.8);
+        }
+        sky
+    }
+
+    /// fraction of the sunlight crossing the atmosphere, for red, green and blue,
+    /// through Rayleigh and aerosol scattering along the optical air mass
+    fn sun_transmittance(sun_theta: f32, turbidity: f32) -> Vector3f {
+        let air_mass = 1.0 / (sun_theta.cos() + 0.15 * (93.885 - sun_theta.to_degrees()).powf(-1.253));
+        let beta = 0.04608 * turbidity - 0.04586;
+        // wavelengths in micrometers
+        Vector3f::new(0.68, 0.55, 0.44).map(|wavelength| {
+            let rayleigh = (-0.008735 * wavelength.powf(-4.08) * air_mass).exp();
+            let aerosol = (-beta * wavelength.powf(-1.3) * air_mass).exp();
+            rayleigh * aerosol
+        })
+    }
+
+    /// radiance of the sky without the sun, for a direction above the horizon
+    fn sky_radiance(&self, direction: &Vector3f) -> Vector3f {
+        let cos_theta = direction.y;
+        let gamma = direction.dot(&self.sun_direction).clamp(-1.0, 1.0).acos();
+        let [luminance, x, y] = [0, 1, 2].map(|channel| {
+            self.zenith[channel] * perez(&self.coefficients[channel], cos_theta, gamma)
+                / perez(&self.coefficients[channel], 1.0, self.sun_theta)
+        });
+        yxy_to_rgb(luminance * SKY_UNITS, x, y)
+    }
+
+    /// irradiance of the sky on a horizontal plane, summed over a grid of the hemisphere
+    fn sky_irradiance(&self) -> Vector3f {
+        let (thetas, phis) = (32, 64);
+        let mut irradiance = Vector3f::zeros();
+        for i in 0..thetas {
+            let theta = (i as f32 + 0.5) / thetas as f32 * PI / 2.0;
+            for j in 0..phis {
+                let phi = (j as f32 + 0.5) / phis as f32 * 2.0 * PI;
+                let direction = Vector3f::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin());
+                let solid_angle = theta.sin() * (PI / 2.0 / thetas as f32) * (2.0 * PI / phis as f32);
+                irradiance += self.sky_radiance(&direction) * theta.cos() * solid_angle;
+            }
+        }
+        irradiance
+    }
+
+    fn in_sun(&self, direction: &Vector3f) -> bool {
+        direction.dot(&self.sun_direction) >= 1.0 - self.sun_one_minus_cos
+    }
+
+}
+
+impl Environment for PreethamSky {
+
+    fn radiance(&self, direction: &Vector3f) -> Vector3f {
+        if direction.y <= 0.0 {
+            return self.ground_radiance;
+        }
+        let sky = self.sky_radiance(direction);
+        if self.in_sun(direction) { sky + self.sun_radiance } else { sky }
+    }
+
+    fn is_sampled(&self) -> bool {
+        true
+    }
+
+    fn sample(&self) -> Option<(Vector3f, f32)> {
+        let mut rng = rand::thread_rng();
+        let direction = if rng.gen::<f32>() < self.sun_probability {
+            let cos_theta = 1.0 - rng.gen::<f32>() * self.sun_one_minus_cos;
+            direction_around(&self.sun_direction, cos_theta, 2.0 * PI * rng.gen::<f32>())
+        } else {
+            random_unit_vector()
+        };
+        Some((direction, self.pdf(&direction)))
+    }
+
+    fn pdf(&self, direction: &Vector3f) -> f32 {
+        let sphere_pdf = (1.0 - self.sun_probability) / (4.0 * PI);
+        if self.in_sun(direction) {
+            sphere_pdf + self.sun_probability / (2.0 * PI * self.sun_one_minus_cos)
+        } else {
+            sphere_pdf
+        }
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sky_matches_the_model_at_the_zenith() {
+        let sun = Vector3f::new(0.0, 0.5, -1.0);
+        let sky = PreethamSky::new(sun, 3.0, Vector3f::repeat(0.3));
+        let up = Vector3f::new(0.0, 1.0, 0.0);
+        // the Perez ratio is 1 at the zenith, the luminance is the zenith one
+        let zenith = sky.radiance(&up);
+        let luminance = 0.2126 * zenith.x + 0.7152 * zenith.y + 0.0722 * zenith.z;
+        assert!((luminance - sky.zenith[0] * SKY_UNITS).abs() < 0.01 * luminance);
+        assert!(zenith.z > zenith.x, "the sky is blue");
+        // the sun is brighter and redder at sunset
+        let sunset = PreethamSky::new(Vector3f::new(0.0, 0.05, -1.0), 3.0, Vector3f::repeat(0.3));
+        assert!(sunset.sun_radiance.x / sunset.sun_radiance.z > sky.sun_radiance.x / sky.sun_radiance.z);
+        assert!(sky.radiance(&sky.sun_direction).y > 1000.0 * zenith.y);
+
+        // a black ground reflects nothing
+        let black_ground = PreethamSky::new(sun, 3.0, Vector3f::zeros());
+        assert_eq!(black_ground.radiance(&-up), Vector3f::zeros());
+        assert!(sky.radiance(&-up).y > 0.0);
+
+        // sampled directions have their pdf, which integrates to 1
+        for _ in 0..100 {
+            let (direction, pdf) = sky.sample().unwrap();
+            assert_eq!(pdf, sky.pdf(&direction));
+        }
+        assert!((sky.sun_probability + sky.pdf(&up) * 4.0 * PI - 1.0).abs() < 1e-6);
+    }
+}
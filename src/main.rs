@@ -1,23 +1,17 @@
-extern crate color;
-extern crate nalgebra;
-extern crate rand;
 extern crate arc_swap;
 extern crate dyn_clone;
-
-mod geom;
-mod image;
-mod ppm;
-mod raycasting;
-mod renderer;
-mod types;
-mod material;
+extern crate ray_tracing_in_one_weekend_rust;
 
 use arc_swap::ArcSwap;
-use crate::geom::hittable::Hittable;
-use crate::geom::sphere::Sphere;
-use crate::types::{Vector3f, Vector2i};
-use crate::renderer::camera::Camera;
-use crate::material::material::{Lambertian, Metal, Dielectric};
+use ray_tracing_in_one_weekend_rust::{image, renderer, screenshot};
+use ray_tracing_in_one_weekend_rust::types::{Rgb8, Vector3f, Vector2i};
+use ray_tracing_in_one_weekend_rust::renderer::camera::Camera;
+use ray_tracing_in_one_weekend_rust::renderer::settings::RendererSettings;
+use ray_tracing_in_one_weekend_rust::renderer::accumulator::Accumulator;
+use ray_tracing_in_one_weekend_rust::renderer::stats::RenderStats;
+use ray_tracing_in_one_weekend_rust::scene::scene::{Scene, SceneHit};
+use ray_tracing_in_one_weekend_rust::scene::description::{MaterialDesc, SceneDesc, ShapeDesc, TextureDesc, TransformDesc};
+use ray_tracing_in_one_weekend_rust::scene::graph::{self, SceneNode};
 
 
 extern crate piston_window;
@@ -30,6 +24,8 @@ use piston_window::*;
 // use image::Image as PistonImage;
 use piston_image::ImageBuffer;
 use piston_image::buffer::ConvertBuffer;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::thread;
 use std::sync::mpsc::{channel, Sender, Receiver};
 
@@ -41,80 +37,107 @@ type MainTextureContext = piston_window::TextureContext<
     gfx_device_gl::CommandBuffer>;
 
 fn renderer_image_to_piston_imagebuffer(src: image::image::Image) -> RgbImageU8Vec{
-    let dest = RgbImageU8Vec::from_raw(
-        src.size.width() as u32, src.size.height() as u32, src.data).unwrap();
-    dest
+    RgbImageU8Vec::from_raw(
+        src.size.width() as u32, src.size.height() as u32, src.data).unwrap()
 }
 
 
 fn rgb2rgba(src: RgbImageU8Vec) -> RgbaImageU8Vec {
-    let dest =    src.convert();
-    dest
+    src.convert()
 }
 
 use std::collections::HashSet;
 
-type ArcMutex<T> = std::sync::Arc<std::sync::Mutex<T>>;
+/// ArcSwap shared between the main and the render thread.
+/// ArcSwap::clone creates an independent copy, so it is shared through an Arc
+type SharedArcSwap<T> = std::sync::Arc<ArcSwap<T>>;
 
-fn make_arc_mutex<T>(value: T) -> ArcMutex<T>{
-    std::sync::Arc::new(std::sync::Mutex::new(value))
+fn make_shared_arc_swap<T>(value: T) -> SharedArcSwap<T> {
+    std::sync::Arc::new(ArcSwap::new(std::sync::Arc::new(value)))
 }
 
 struct UserInput {
     pressed_keys: HashSet<Key>,
-    exit_requested: bool
+    exit_requested: bool,
+    screenshot_requested: bool,
+    stats_requested: bool,
+    /// image pixel under the cursor when the left mouse button was pressed
+    clicked_pixel: Option<Vector2i>
 }
 
 impl UserInput {
     pub fn new() -> UserInput {
         UserInput {
             pressed_keys: HashSet::new(),
-            exit_requested: false
+            exit_requested: false,
+            screenshot_requested: false,
+            stats_requested: false,
+            clicked_pixel: None
         }
     }
 }
 
 fn start_render_thread(user_input_rx: Receiver<UserInput>,
         renderer_framebuffer_tx: Sender<RgbaImageU8Vec>,
-        game_conf: GameConf,
-        game_state: ArcSwap<GameState>) -> std::thread::JoinHandle<()> {
+        _game_conf: GameConf,
+        game_state: SharedArcSwap<GameState>,
+        renderer_settings: SharedArcSwap<RendererSettings>) -> std::thread::JoinHandle<()> {
 
-    let game: Game = Game::new();
+    let mut game: Game = Game::new();
 
-    let renderer_thread = thread::spawn( move || {
-        let mut running = true;
-        while running {
-            if let Ok(user_input) = user_input_rx.try_recv() {
+    thread::spawn( move || {
+        'render_loop: loop {
+            while let Ok(user_input) = user_input_rx.try_recv() {
                 if user_input.exit_requested {
-                    running = false;
-                    break;
+                    break 'render_loop;
+                }
+                if user_input.screenshot_requested {
+                    game.save_screenshot();
+                }
+                if user_input.stats_requested {
+                    game.print_stats();
                 }
             }
 
-
-
-            println!("{:?}", game_state.load().camera.origin);
-            let rendered_image = game.render(game_state.load().as_ref());
-            println!("rendered!");
+            let rendered_image = game.render(game_state.load_full(),
+                renderer_settings.load_full());
             let image_buffer = renderer_image_to_piston_imagebuffer(rendered_image);
             let image_buffer_rgba = rgb2rgba(image_buffer);
-            renderer_framebuffer_tx.send(image_buffer_rgba);
+            if renderer_framebuffer_tx.send(image_buffer_rgba).is_err() {
+                break;
+            }
         }
         println!("exit from render thread");
-    });
-    renderer_thread
+    })
 }
 
 
+#[derive(Clone)]
 struct GameConf{
-    camera_size: Vector2i
+    _camera_size: Vector2i,
+    /// where the scene is loaded from and saved to
+    scene_path: PathBuf
+}
+
+/// the rendered image is drawn scaled by this factor
+const IMAGE_DISPLAY_SCALE: f64 = 5.0;
+
+fn window_to_image_xy(window_position: [f64; 2]) -> Vector2i {
+    Vector2i::new((window_position[0] / IMAGE_DISPLAY_SCALE) as i32,
+                  (window_position[1] / IMAGE_DISPLAY_SCALE) as i32)
 }
 
-fn generate_user_input(e: &Event) -> UserInput {
+fn generate_user_input(e: &Event, cursor_position: [f64; 2]) -> UserInput {
     let mut user_input = UserInput::new();
 
-    if let Some(Button::Keyboard(key)) = e.press_args() {
-        user_input.pressed_keys.insert(key);
+    match e.press_args() {
+        Some(Button::Keyboard(key)) => {
+            user_input.pressed_keys.insert(key);
+        },
+        Some(Button::Mouse(MouseButton::Left)) => {
+            user_input.clicked_pixel = Some(window_to_image_xy(cursor_position));
+        },
+        _ => ()
     }
 
     if user_input.pressed_keys.contains(&Key::Escape) {
@@ -122,6 +145,14 @@ fn generate_user_input(e: &Event) -> UserInput {
         user_input.exit_requested = true;
     }
 
+    if user_input.pressed_keys.contains(&Key::P) {
+        user_input.screenshot_requested = true;
+    }
+
+    if user_input.pressed_keys.contains(&Key::L) {
+        user_input.stats_requested = true;
+    }
+
     user_input
 }
 
@@ -141,9 +172,9 @@ fn draw_window(window: &mut PistonWindow, e: &Event,
                     image.height().into()));
                 let texture_settings = TextureSettings::new();
                 let texture = Texture::from_image(texture_context, &image, &texture_settings ).unwrap();
-                img.draw(&texture, &c.draw_state, c.transform.scale(5.0, 5.0), g);
+                img.draw(&texture, &c.draw_state, c.transform.scale(IMAGE_DISPLAY_SCALE, IMAGE_DISPLAY_SCALE), g);
             },
-            Err(e) => {
+            Err(_e) => {
 
             }
         }
@@ -158,9 +189,9 @@ fn make_wasd_vector(user_input: &UserInput) -> Vector3f {
         wasd_vector.z -= 1.0;
     }
 
-    if(user_input.pressed_keys.contains(&Key::A)) {
+    if user_input.pressed_keys.contains(&Key::A) {
         wasd_vector.x += 1.0;
-    } else if (user_input.pressed_keys.contains(&Key::D)) {
+    } else if user_input.pressed_keys.contains(&Key::D) {
         wasd_vector.x -= 1.0;
     }
 
@@ -169,21 +200,182 @@ fn make_wasd_vector(user_input: &UserInput) -> Vector3f {
 
 fn update_camera(user_input: &UserInput, camera: Camera) -> Camera {
     let mut new_camera = camera;
-    let mut wasd_vector = make_wasd_vector(user_input);
+    let wasd_vector = make_wasd_vector(user_input);
     new_camera.fps_move(wasd_vector);
     new_camera
 }
 
-fn update_game_state(user_input: &UserInput, previous_game_state: &GameState) -> GameState {
+/// settings keys:
+/// T toggles antialiasing, =/- double/halve the samples,
+/// ]/[ increase/decrease max depth, I switches integrator
+fn update_renderer_settings(user_input: &UserInput, settings: &RendererSettings) -> Option<RendererSettings> {
+    let keys = &user_input.pressed_keys;
+    let builder = settings.to_builder();
+    let builder = if keys.contains(&Key::T) {
+        builder.antialiasing_on(!settings.antialiasing_on())
+    } else if keys.contains(&Key::Equals) {
+        builder.antialiasing_samples(settings.antialiasing_samples().saturating_mul(2))
+    } else if keys.contains(&Key::Minus) {
+        builder.antialiasing_samples(settings.antialiasing_samples() / 2)
+    } else if keys.contains(&Key::RightBracket) {
+        builder.max_depth(settings.max_depth().saturating_add(1))
+    } else if keys.contains(&Key::LeftBracket) {
+        builder.max_depth(settings.max_depth().saturating_sub(1))
+    } else if keys.contains(&Key::I) {
+        builder.integrator(settings.integrator().next())
+    } else {
+        return None;
+    };
+    Some(builder.build())
+}
+
+/// cast a ray through the pixel and print what it hits
+fn pick_object(game_state: &GameState, pixel: Vector2i) -> Option<SceneHit> {
+    let viewport = &game_state.camera.viewport;
+    if pixel.x < 0 || pixel.x >= viewport.image_width() || pixel.y < 0 || pixel.y >= viewport.image_height() {
+        return None;
+    }
+    let ray = game_state.camera.get_ray_from_image_xy(pixel);
+    let hit = game_state.scene.closest_hit(&ray, 0.001, f32::INFINITY);
+    match &hit {
+        Some(hit) => {
+            let hittable = game_state.scene.hittable(hit.index);
+            println!("picked node {} at pixel {:?}", game_state.hittable_paths[hit.index], pixel);
+            println!("  hittable: {:?}", hittable);
+            println!("  material: {:?}", hittable.material());
+            println!("  distance: {}", hit.hitpoint.t * ray.direction.magnitude());
+            println!("  position: {:?}", hit.hitpoint.position);
+            println!("  normal: {:?} ({})", hit.hitpoint.normal,
+                if hit.hitpoint.front_face { "front face" } else { "back face" });
+        },
+        None => println!("nothing picked at pixel {:?}", pixel)
+    }
+    hit
+}
+
+/// material editing keys, applied to the selected object:
+/// 1/2/3 switch to lambertian/metal/dielectric, R/G/B step the albedo channels,
+/// Up/Down change fuzziness or reflective index.
+/// Returns the edited material, None if no editing key was pressed
+fn edit_material(user_input: &UserInput, material: &MaterialDesc) -> Option<MaterialDesc> {
+    let keys = &user_input.pressed_keys;
+    let type_keys = [Key::D1, Key::D2, Key::D3];
+    if let Some(type_index) = type_keys.iter().position(|key| keys.contains(key)) {
+        return material.converted_to(MaterialDesc::TYPE_NAMES[type_index]);
+    }
+
+    let mut edited = material.clone();
+    let channel_keys = [Key::R, Key::G, Key::B];
+    if let Some(channel) = channel_keys.iter().position(|key| keys.contains(key)) {
+        let mut albedo = material.albedo()?;
+        // step by 0.1, wrapping back to 0 after 1
+        albedo[channel] = if albedo[channel] >= 0.95 { 0.0 } else { albedo[channel] + 0.1 };
+        edited.set_albedo(albedo);
+    } else if keys.contains(&Key::Up) || keys.contains(&Key::Down) {
+        let (_name, value) = material.scalar_parameter()?;
+        let step = if keys.contains(&Key::Up) { 0.05 } else { -0.05 };
+        edited.set_scalar_parameter(value + step);
+    } else {
+        return None;
+    }
+    Some(edited)
+}
+
+/// node editing keys, applied to the selected node:
+/// H toggles its visibility, arrows Left/Right, PageUp/PageDown and Home/End
+/// move it along x, y and z.
+/// Returns the edited node, None if no editing key was pressed
+fn edit_node(user_input: &UserInput, node: &SceneNode) -> Option<SceneNode> {
+    let keys = &user_input.pressed_keys;
+    let mut edited = node.clone();
+    if keys.contains(&Key::H) {
+        edited.visible = !node.visible;
+        return Some(edited);
+    }
+
+    let move_keys = [
+        (Key::Left, Key::Right),
+        (Key::PageDown, Key::PageUp),
+        (Key::End, Key::Home),
+    ];
+    let (axis, (backward, _forward)) = move_keys.iter().enumerate()
+        .find(|(_, (backward, forward))| keys.contains(backward) || keys.contains(forward))?;
+    let step = if keys.contains(backward) { -0.1 } else { 0.1 };
+    let mut transform = node.transform.clone().unwrap_or_default();
+    transform.translation[axis] += step;
+    edited.transform = Some(transform);
+    Some(edited)
+}
+
+/// the new game state, None if the user input did not change it
+fn update_game_state(user_input: &UserInput, previous_game_state: &GameState) -> Option<GameState> {
+    let camera_moved = make_wasd_vector(user_input) != Vector3f::zeros();
+    let scene_desc = &previous_game_state.scene_desc;
+    // hittables instanced from other nodes are edited through the instancing node
+    let selected_node = previous_game_state.selected.as_deref()
+        .and_then(|selected| scene_desc.closest_node_path(selected));
+    let edited_material = selected_node.and_then(|path| {
+        let material = scene_desc.node_material(path)?;
+        edit_material(user_input, &material)
+    });
+    let edited_node = selected_node.and_then(|path| edit_node(user_input, scene_desc.node(path)?));
+    let select_parent = user_input.pressed_keys.contains(&Key::Backspace) && selected_node.is_some();
+    if !camera_moved && user_input.clicked_pixel.is_none() && edited_material.is_none()
+            && edited_node.is_none() && !select_parent {
+        return None;
+    }
+
     let mut new_game_state = dyn_clone::clone(previous_game_state);
     new_game_state.camera = update_camera(user_input, new_game_state.camera);
-    new_game_state
+    if let Some(pixel) = user_input.clicked_pixel {
+        new_game_state.selected = pick_object(previous_game_state, pixel)
+            .map(|hit| previous_game_state.hittable_paths[hit.index].clone());
+    } else if select_parent {
+        new_game_state.selected = selected_node.and_then(graph::parent_path).map(str::to_string);
+        println!("selected node {:?}", new_game_state.selected);
+    }
+
+    let scene_edited = edited_node.is_some() || edited_material.is_some();
+    if let Some(path) = selected_node {
+        let node = new_game_state.scene_desc.node_mut(path).unwrap();
+        if let Some(edited_node) = edited_node {
+            println!("node {}: visible {}, transform {:?}", path, edited_node.visible, edited_node.transform);
+            *node = edited_node;
+        }
+        if let Some(material) = edited_material {
+            println!("node {} material: {:?}", path, material);
+            node.material = Some(material);
+        }
+    }
+    if scene_edited {
+        let (scene, hittable_paths, errors) = new_game_state.scene_desc.build_with_paths();
+        print_scene_errors(&errors);
+        new_game_state.scene = scene;
+        new_game_state.hittable_paths = hittable_paths;
+    }
+    Some(new_game_state)
+}
+
+/// the nodes left out of the scene as it is rebuilt, and why
+fn print_scene_errors(errors: &[String]) {
+    for error in errors {
+        println!("scene error: {}", error);
+    }
+}
+
+fn save_scene(game_state: &GameState, path: &Path) {
+    match game_state.scene_desc.save(path) {
+        Ok(()) => println!("saved scene to {}", path.display()),
+        Err(e) => println!("failed to save scene to {}: {}", path.display(), e),
+    }
 }
 
 fn main_thread(mut window: PistonWindow,
     user_input_tx: Sender<UserInput>,
     renderer_framebuffer_rx: Receiver<RgbaImageU8Vec>,
-    game_state: ArcSwap<GameState>) {
+    game_conf: GameConf,
+    game_state: SharedArcSwap<GameState>,
+    renderer_settings: SharedArcSwap<RendererSettings>) {
 
     let mut running = true;
     let mut texture_context = TextureContext {
@@ -191,21 +383,39 @@ fn main_thread(mut window: PistonWindow,
         encoder: window.factory.create_command_buffer().into()
     };
 
+    let mut cursor_position = [0.0, 0.0];
+
     while let Some(e) = window.next() {
         if !running  {
             break
         }
 
-        let user_input = generate_user_input(&e);
+        if let Some(position) = e.mouse_cursor_args() {
+            cursor_position = position;
+        }
+
+        let user_input = generate_user_input(&e, cursor_position);
+
+        // the render thread restarts the progressive render when a new state is stored
+        if let Some(new_game_state) = update_game_state(&user_input, game_state.load().as_ref()) {
+            game_state.store(std::sync::Arc::new(new_game_state));
+        }
+
+        if let Some(new_settings) = update_renderer_settings(&user_input, renderer_settings.load().as_ref()) {
+            println!("renderer settings: {}", new_settings);
+            renderer_settings.store(std::sync::Arc::new(new_settings));
+        }
 
-        let previous_game_state = game_state.load();
-        let new_game_state = update_game_state(&user_input, previous_game_state.as_ref()); 
-        game_state.store(std::sync::Arc::new(new_game_state));
+        if user_input.pressed_keys.contains(&Key::K) {
+            save_scene(game_state.load().as_ref(), &game_conf.scene_path);
+        }
 
         if user_input.exit_requested {
             println!("exiting from main thread");
             running = false;
-            user_input_tx.send(user_input);
+            let _ = user_input_tx.send(user_input);
+        } else if user_input.screenshot_requested || user_input.stats_requested {
+            let _ = user_input_tx.send(user_input);
         }
 
         draw_window(&mut window, &e, &renderer_framebuffer_rx, &mut texture_context);
@@ -216,14 +426,28 @@ fn main_thread(mut window: PistonWindow,
 fn get_camera_size() -> Vector2i {
     let camera_width : u32 = 200;
     let camera_height: u32 = 120;
-    let camera_size = Vector2i::new(camera_width as i32, camera_height as i32);
-    camera_size
+    Vector2i::new(camera_width as i32, camera_height as i32)
 }
 
-fn make_initial_game_state(camera_size: Vector2i) -> GameState {
+fn make_initial_game_state(camera_size: Vector2i, scene_desc: SceneDesc) -> GameState {
+    let (scene, hittable_paths, errors) = scene_desc.build_with_paths();
+    print_scene_errors(&errors);
     GameState {
         camera: Camera::new(camera_size.x, camera_size.y),
-        hittables: make_default_hittables()
+        scene,
+        hittable_paths,
+        scene_desc,
+        selected: None
+    }
+}
+
+/// load the scene file given as first argument, the default scene if there is none
+fn load_scene_desc(scene_path: Option<&PathBuf>) -> SceneDesc {
+    match scene_path {
+        Some(path) => SceneDesc::load(path).unwrap_or_else(|e| {
+            panic!("failed to load scene {}: {}", path.display(), e)
+        }),
+        None => make_default_scene_desc()
     }
 }
 
@@ -235,107 +459,196 @@ fn main() {
         [camera_size.x as u32, camera_size.y as u32])
         .exit_on_esc(true).build().unwrap();
 
-    let game_conf = GameConf { camera_size };
+    let scene_path_arg = std::env::args().nth(1).map(PathBuf::from);
+    let scene_desc = load_scene_desc(scene_path_arg.as_ref());
+    let game_conf = GameConf {
+        _camera_size: camera_size,
+        scene_path: scene_path_arg.unwrap_or_else(|| PathBuf::from("scene.json"))
+    };
 
     let (renderer_framebuffer_tx, renderer_framebuffer_rx) = channel();
     let (user_input_tx, user_input_rx) = channel::<UserInput>();
 
-    let initial_game_state = make_initial_game_state(camera_size);
-    // let game_state = make_arc_mutex(initial_game_state);
-    let game_state = ArcSwap::new(std::sync::Arc::new(initial_game_state));
+    let initial_game_state = make_initial_game_state(camera_size, scene_desc);
+    let game_state = make_shared_arc_swap(initial_game_state);
+    let renderer_settings = make_shared_arc_swap(RendererSettings::default());
 
     let renderer_thread = start_render_thread(
         user_input_rx, renderer_framebuffer_tx,
-        game_conf, game_state.clone(),
+        game_conf.clone(), game_state.clone(), renderer_settings.clone(),
     );
 
     main_thread(window, user_input_tx, 
         renderer_framebuffer_rx, 
-        game_state);
+        game_conf, game_state, renderer_settings);
 
     renderer_thread.join().unwrap();
 }
 
 struct Game {
-    renderer: renderer::renderer::Renderer
+    renderer: renderer::renderer::Renderer,
+    accumulator: Accumulator,
+    /// state and settings of the accumulated passes
+    rendered_game_state: Option<Arc<GameState>>,
+    rendered_settings: Option<Arc<RendererSettings>>,
+    /// statistics of the last pass
+    stats: Option<RenderStats>,
 }
 
 
 #[derive(Clone)]
 struct GameState {
-    hittables: Vec<Box<dyn Hittable>>,
+    /// the scene as edited in the viewer, scene is rebuilt from it
+    scene_desc: SceneDesc,
+    scene: Scene,
+    /// path of the scene node of each hittable of scene
+    hittable_paths: Vec<String>,
+    /// path of the node picked in the viewer
+    selected: Option<String>,
     camera: Camera
 }
 
-impl GameState {
-    pub fn new(camera: Camera) -> GameState {
-        GameState {
-            hittables: Vec::new(),
-            camera: camera
-        }
+pub fn make_default_scene_desc() -> SceneDesc {
+    let material_ground = MaterialDesc::Lambertian {
+        albedo: [1.0, 1.0, 1.0],
+        texture: Some(TextureDesc::Checker { even: [0.8, 0.8, 0.0], odd: [0.2, 0.3, 0.1], scale: 1.0 })
+    };
+    let material_center = MaterialDesc::Lambertian { albedo: [0.7, 0.3, 0.3], texture: None };
+    let material_left   = MaterialDesc::Dielectric { reflective_index: 1.5 };
+    let material_right  = MaterialDesc::Metal { albedo: [0.8, 0.6, 0.2], fuzziness: 0.05 };
+
+    let ground = SceneNode::with_shape("ground",
+        ShapeDesc::Plane { point: [0.0, -0.5, 0.0], normal: [0.0, 1.0, 0.0] },
+        material_ground);
+    let sphere_center = SceneNode::with_shape("center",
+        ShapeDesc::Sphere { origin: [0.0, 0.0, 0.0], radius: 0.5 },
+        material_center);
+    let sphere_left = SceneNode::with_shape("left",
+        ShapeDesc::Sphere { origin: [-1.0, 0.0, 0.0], radius: 0.5 },
+        material_left);
+    let sphere_right = SceneNode::with_shape("right",
+        ShapeDesc::Sphere { origin: [1.0, 0.0, 0.0], radius: 0.5 },
+        material_right);
+
+    let mut spheres = SceneNode::new("spheres");
+    spheres.children = vec![sphere_left, sphere_right, sphere_center];
+
+    let mut world = SceneNode::new("world");
+    world.transform = Some(TransformDesc { translation: [0.0, 0.0, -1.0], ..TransformDesc::default() });
+    world.children = vec![ground, spheres];
+
+    SceneDesc {
+        nodes: vec![world],
+        fog: None,
+        lights: Vec::new(),
+        environment: None,
     }
 }
 
-pub fn make_default_hittables() -> Vec<Box<dyn Hittable>>  {
-        let material_ground = Lambertian::new(Vector3f::new(0.8, 0.8, 0.0));
-        let material_center = Lambertian::new(Vector3f::new(0.7, 0.3, 0.3));
-        // let material_left   = Metal::new(Vector3f::new(0.8, 0.8, 0.8), 0.3);
-        // let material_center = Dielectric::new(1.5f32);
-        let material_left   = Dielectric::new(1.5f32);
-        let material_right  = Metal::new(Vector3f::new(0.8, 0.6, 0.2), 0.05);
-
-        let sphere_ground = Sphere {
-            origin: Vector3f::new(0f32, -100.5f32, -1.0f32),
-            radius: 100f32,
-            material: Some(Box::new(material_ground))
-        };
-        let sphere_center = Sphere {
-            origin: Vector3f::new(0f32, 0f32, -1f32),
-            radius: 0.5f32,
-            material: Some(Box::new(material_center))
-        };
-        let sphere_left = Sphere {
-            origin: Vector3f::new(-1f32, 0f32, -1f32),
-            radius: 0.5f32,
-            material: Some(Box::new(material_left))
-        };
-        let sphere_right = Sphere {
-            origin: Vector3f::new(1f32, 0f32, -1f32),
-            radius: 0.5f32,
-            material: Some(Box::new(material_right))
-        };
-
-        let hittables: Vec<Box<dyn Hittable>> = vec![
-            Box::new(sphere_ground),
-            Box::new(sphere_left),
-            Box::new(sphere_right),
-            Box::new(sphere_center),
-        ];
-
-        hittables
-}
-
 
 impl Game {
 
     pub fn new() -> Game {
-        let renderer = renderer::renderer::Renderer::new();
+        let renderer = renderer::renderer::Renderer::default();
 
         Game {
-            renderer
+            renderer,
+            accumulator: Accumulator::new(),
+            rendered_game_state: None,
+            rendered_settings: None,
+            stats: None,
         }
     }
 
-    pub fn render(&self, game_state: &GameState) -> image::image::Image {
-        let hittables = &game_state.hittables;
+    /// render one more pass and return the average of the passes since the
+    /// last change of game state or settings
+    pub fn render(&mut self, game_state: Arc<GameState>, settings: Arc<RendererSettings>) -> image::image::Image {
+        let same_game_state = self.rendered_game_state.as_ref()
+            .is_some_and(|rendered| Arc::ptr_eq(rendered, &game_state));
+        let same_settings = self.rendered_settings.as_ref()
+            .is_some_and(|rendered| Arc::ptr_eq(rendered, &settings));
+        if !same_game_state || !same_settings {
+            self.accumulator.reset();
+        }
+        if self.renderer.settings() != settings.as_ref() {
+            self.renderer = renderer::renderer::Renderer::new(settings.as_ref().clone());
+        }
+
+        let scene = &game_state.scene;
         let camera = &game_state.camera;
-        let image = self.renderer.run(camera, hittables);
-        ppm::save_image_to_ppm(
-            image.data.as_slice(),
-            image.size.width(),
-            image.size.height(),
-            "output.ppm",
-        );
+        let (pass, stats) = self.renderer.render_pass(camera, scene);
+        self.stats = Some(stats);
+        self.accumulator.add_pass(&pass);
+        self.rendered_game_state = Some(game_state);
+        self.rendered_settings = Some(settings);
+
+        let mut image = self.accumulator.frame().unwrap().to_image();
+        if let Some(game_state) = &self.rendered_game_state {
+            if let Some(selected) = &game_state.selected {
+                highlight_selection(&mut image, game_state, selected);
+            }
+        }
         image
     }
+
+    /// print the statistics of the last pass
+    pub fn print_stats(&self) {
+        match &self.stats {
+            Some(stats) => println!("{}", stats),
+            None => println!("nothing rendered yet, no statistics"),
+        }
+    }
+
+    /// save the accumulated frame in the working directory
+    pub fn save_screenshot(&self) {
+        let (frame, game_state, settings) = match (self.accumulator.frame(),
+                &self.rendered_game_state, &self.rendered_settings) {
+            (Some(frame), Some(game_state), Some(settings)) => (frame, game_state, settings),
+            _ => {
+                println!("nothing rendered yet, screenshot skipped");
+                return;
+            }
+        };
+        let metadata = vec![
+            ("Software".to_string(), "ray_tracing_in_one_weekend_rust".to_string()),
+            ("Renderer settings".to_string(), settings.to_string()),
+            ("Accumulated passes".to_string(), self.accumulator.passes().to_string()),
+            ("Camera origin".to_string(), format!("{:?}", game_state.camera.origin)),
+        ];
+        match screenshot::save_screenshot(&frame, &metadata, std::path::Path::new(".")) {
+            Ok(files) => println!("saved screenshot {:?}", files),
+            Err(e) => println!("failed to save screenshot: {}", e),
+        }
+    }
+
+}
+
+/// draw an outline around the pixels whose camera ray hits the selected node or its descendants
+fn highlight_selection(image: &mut image::image::Image, game_state: &GameState, selected: &str) {
+    let is_selected_path = |path: &str| {
+        path == selected || (path.starts_with(selected) && path[selected.len()..].starts_with(graph::PATH_SEPARATOR))
+    };
+    let viewport = &game_state.camera.viewport;
+    let (width, height) = (viewport.image_width(), viewport.image_height());
+    let mask : Vec<bool> = (0..height).flat_map(|image_y| (0..width).map(move |image_x| (image_x, image_y)))
+        .map(|(image_x, image_y)| {
+            let ray = game_state.camera.get_ray_from_image_xy(Vector2i::new(image_x, image_y));
+            game_state.scene.closest_hit(&ray, 0.001, f32::INFINITY)
+                .is_some_and(|hit| is_selected_path(&game_state.hittable_paths[hit.index]))
+        }).collect();
+    let is_selected = |image_x: i32, image_y: i32| {
+        image_x >= 0 && image_x < width && image_y >= 0 && image_y < height
+            && mask[(image_y * width + image_x) as usize]
+    };
+
+    for image_y in 0..height {
+        for image_x in 0..width {
+            let on_border = is_selected(image_x, image_y) && (
+                !is_selected(image_x - 1, image_y) || !is_selected(image_x + 1, image_y) ||
+                !is_selected(image_x, image_y - 1) || !is_selected(image_x, image_y + 1));
+            if on_border {
+                image.set_pixel(Vector2i::new(image_x, image_y), Rgb8::new(255, 140, 0));
+            }
+        }
+    }
 }
@@ -0,0 +1,117 @@
+use crate::raycasting::ray::{HitPoint, Ray};
+use crate::types::Vector3f;
+use crate::material::material::{BsdfSample, Material};
+use crate::material::texture::Texture;
+
+/// opacities below it are cut out
+const OPACITY_THRESHOLD: f32 = 0.5;
+
+/// A base material cut out where the mean of the opacity texture is below one half,
+/// as the alpha of foliage and fences. It is the base material everywhere else
+#[derive(Clone, Debug)]
+pub struct Cutout {
+    base: Box<dyn Material>,
+    opacity: Box<dyn Texture>,
+}
+
+impl Cutout {
+    pub fn new(base: Box<dyn Material>, opacity: Box<dyn Texture>) -> Cutout {
+        Cutout{base, opacity}
+    }
+}
+
+impl Material for Cutout {
+
+    fn sample(&self, ray: &Ray, rec: &HitPoint) -> Option<BsdfSample> {
+        self.base.sample(ray, rec)
+    }
+
+    fn eval(&self, ray: &Ray, rec: &HitPoint, direction: &Vector3f) -> Option<Vector3f> {
+        self.base.eval(ray, rec, direction)
+    }
+
+    fn pdf(&self, ray: &Ray, rec: &HitPoint, direction: &Vector3f) -> f32 {
+        self.base.pdf(ray, rec, direction)
+    }
+
+    fn emitted(&self, ray: &Ray, rec: &HitPoint) -> Vector3f {
+        self.base.emitted(ray, rec)
+    }
+
+    fn is_cut_out(&self, rec: &HitPoint) -> bool {
+        self.opacity.value(&rec.uv, &rec.position).sum() / 3.0 < OPACITY_THRESHOLD || self.base.is_cut_out(rec)
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geom::hittable::Hittable;
+    use crate::geom::quad::Quad;
+    use crate::geom::sphere::Sphere;
+    use crate::geom::transformed::Transformed;
+    use crate::material::material::Lambertian;
+    use crate::material::mix::Mix;
+    use crate::material::texture::{Checker, SolidColor};
+    use crate::types::Vector2f;
+    use crate::scene::scene::Scene;
+    use nalgebra::Matrix4;
+    use std::sync::Arc;
+
+    #[test]
+    fn rays_and_shadow_rays_go_through_the_holes() {
+        let grey = || Box::new(Lambertian::new(Vector3f::repeat(0.5)));
+        // opaque on the squares of even uv, half a uv unit wide
+        let holes = || Box::new(Checker::new(Vector3f::repeat(1.0), Vector3f::zeros(), 2.0));
+        let fence = Quad::new(Vector3f::new(-1.0, -1.0, 0.0), Vector3f::new(2.0, 0.0, 0.0), Vector3f::new(0.0, 2.0, 0.0),
+            Some(Box::new(Cutout::new(grey(), holes()))));
+        let wall = Quad::new(Vector3f::new(-5.0, -5.0, -1.0), Vector3f::new(10.0, 0.0, 0.0), Vector3f::new(0.0, 10.0, 0.0),
+            Some(grey()));
+        // the same fence moved in front of it, without holes but for the material replacing its own
+        let plain = Arc::new(Quad::new(Vector3f::new(-1.0, -1.0, 0.0), Vector3f::new(2.0, 0.0, 0.0),
+            Vector3f::new(0.0, 2.0, 0.0), Some(grey())));
+        let moved = Transformed::with_material(plain, Matrix4::new_translation(&Vector3f::new(0.0, 0.0, 2.0)),
+            Box::new(Cutout::new(grey(), holes())));
+
+        let ray_at = |x: f32, y: f32| Ray { origin: Vector3f::new(x, y, 5.0), direction: Vector3f::new(0.0, 0.0, -1.0), time: 0.0 };
+        let scene = Scene::new(vec![Box::new(fence), Box::new(wall)]);
+        // uv (0.25, 0.25) is opaque, (0.75, 0.25) a hole
+        let hit = scene.closest_hit(&ray_at(-0.5, -0.5), 0.001, f32::INFINITY).unwrap();
+        assert_eq!((hit.index, hit.hitpoint.t), (0, 5.0));
+        let hit = scene.closest_hit(&ray_at(0.5, -0.5), 0.001, f32::INFINITY).unwrap();
+        assert_eq!((hit.index, hit.hitpoint.t), (1, 6.0));
+        assert_eq!(scene.transmittance_counted(&ray_at(-0.5, -0.5), 0.001, 5.5, &mut 0), 0.0);
+        assert_eq!(scene.transmittance_counted(&ray_at(0.5, -0.5), 0.001, 5.5, &mut 0), 1.0);
+
+        assert_eq!(moved.transmittance(&ray_at(-0.5, -0.5), 0.001, 5.5), 0.0);
+        assert_eq!(moved.transmittance(&ray_at(0.5, -0.5), 0.001, 5.5), 1.0);
+        assert!(moved.opaque_intersection(&ray_at(0.5, -0.5), 0.001, f32::INFINITY).is_none());
+
+        // a sphere cut out everywhere is crossed through both of its hits
+        let ghost = Sphere { origin: Vector3f::zeros(), radius: 1.0,
+            material: Some(Box::new(Cutout::new(grey(), Box::new(SolidColor::new(Vector3f::zeros()))))) };
+        assert!(ghost.opaque_intersection(&ray_at(0.0, 0.0), 0.001, f32::INFINITY).is_none());
+    }
+
+    #[test]
+    fn mixes_keep_the_holes_of_the_materials_they_show() {
+        let grey = || Box::new(Lambertian::new(Vector3f::repeat(0.5)));
+        let holed = || Box::new(Cutout::new(grey(), Box::new(SolidColor::new(Vector3f::zeros()))));
+        let rec = HitPoint {
+            t: 1.0,
+            position: Vector3f::zeros(),
+            normal: Vector3f::new(0.0, 1.0, 0.0),
+            front_face: true,
+            uv: Vector2f::zeros(),
+            dpdu: Vector3f::new(1.0, 0.0, 0.0),
+            dpdv: Vector3f::new(0.0, 0.0, -1.0),
+        };
+        assert!(Mix::new(holed(), holed(), 0.5).is_cut_out(&rec));
+        assert!(!Mix::new(holed(), grey(), 0.5).is_cut_out(&rec));
+        // a weight of 1 shows the second material only, 0 the first only
+        assert!(Mix::new(grey(), holed(), 1.0).is_cut_out(&rec));
+        assert!(!Mix::new(grey(), holed(), 0.0).is_cut_out(&rec));
+    }
+}
@@ -1,40 +1,174 @@
 use crate::raycasting::ray::HitPoint;
 use crate::raycasting::ray::Ray;
 use crate::types::{Vector3f};
-use crate::geom::rand_geom::random_in_unit_sphere;
-use dyn_clone::{clone_trait_object, DynClone};
+use crate::geom::rand_geom::{direction_around, random_unit_vector};
+use crate::material::texture::Texture;
+use dyn_clone::DynClone;
+use rand::Rng;
+use std::f32::consts::PI;
+use std::fmt::Debug;
 
-pub trait Material : Send + Sync + DynClone {
-    fn scatter  (&self,
-        ray: &Ray, rec: &HitPoint) -> Option<(Vector3f, Ray)>;
+/// Direction chosen by `Material::sample` for the path to continue
+#[derive(Clone, Debug)]
+pub struct BsdfSample {
+    /// unit vector
+    pub direction: Vector3f,
+    /// eval / pdf, what the light arriving along direction is multiplied by
+    pub weight: Vector3f,
+    /// solid angle density of the direction, meaningless for specular samples
+    pub pdf: f32,
+    /// chosen from a delta distribution, as a mirror, that eval and pdf leave out
+    pub specular: bool,
+}
+
+impl BsdfSample {
+    pub fn ray(&self, ray: &Ray, rec: &HitPoint) -> Ray {
+        Ray{origin: rec.position, direction: self.direction, time: ray.time}
+    }
+}
+
+pub trait Material : Send + Sync + DynClone + Debug {
+    /// direction the light scatters from, towards the origin of the ray. None if it is absorbed
+    fn sample(&self, ray: &Ray, rec: &HitPoint) -> Option<BsdfSample>;
+
+    /// BSDF times the cosine with the normal, for light coming from direction (a unit vector)
+    /// and leaving towards the origin of the ray. None for materials that can only scatter
+    /// by sampling, as mirrors: lights are not sampled at their hits
+    fn eval(&self, _ray: &Ray, _rec: &HitPoint, _direction: &Vector3f) -> Option<Vector3f> {
+        None
+    }
+
+    /// solid angle density of sample choosing direction, without the specular samples
+    fn pdf(&self, _ray: &Ray, _rec: &HitPoint, _direction: &Vector3f) -> f32 {
+        0.0
+    }
+
+    /// radiance emitted at the hit towards the origin of the ray
+    fn emitted(&self, _ray: &Ray, _rec: &HitPoint) -> Vector3f {
+        Vector3f::zeros()
+    }
+
+    /// whether the surface is cut away at the hit, as around the leaves of an alpha masked quad.
+    /// Scenes skip those hits, rays and shadow rays carry on past them
+    fn is_cut_out(&self, _rec: &HitPoint) -> bool {
+        false
+    }
 }
 
 
 dyn_clone::clone_trait_object!(Material);
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct Lambertian {
-    albedo : Vector3f
+    albedo : Vector3f,
+    /// multiplies the albedo when set
+    texture: Option<Box<dyn Texture>>,
 }
 
 impl Lambertian {
     pub fn new(albedo: Vector3f) -> Lambertian {
-        Lambertian{albedo}
+        Lambertian{albedo, texture: None}
+    }
+
+    pub fn textured(albedo: Vector3f, texture: Box<dyn Texture>) -> Lambertian {
+        Lambertian{albedo, texture: Some(texture)}
+    }
+
+    fn attenuation(&self, rec: &HitPoint) -> Vector3f {
+        match &self.texture {
+            Some(texture) => self.albedo.component_mul(&texture.value(&rec.uv, &rec.position)),
+            None => self.albedo
+        }
     }
 }
 
 impl Material for Lambertian {
 
-        fn scatter  (&self, ray: &Ray, rec: &HitPoint)-> Option<(Vector3f, Ray)> {
-            let scatter_direction = rec.normal + random_in_unit_sphere();
-            let scattered = Ray{origin: rec.position, direction: scatter_direction};
-            let attenuation = self.albedo;
-            Some((attenuation, scattered))
+        /// the normal plus a uniform unit vector follows the cosine distribution
+        fn sample(&self, ray: &Ray, rec: &HitPoint) -> Option<BsdfSample> {
+            let direction = (rec.normal + random_unit_vector()).try_normalize(1e-6)?;
+            Some(BsdfSample {
+                direction,
+                weight: self.attenuation(rec),
+                pdf: self.pdf(ray, rec, &direction),
+                specular: false,
+            })
+        }
+
+        fn eval(&self, ray: &Ray, rec: &HitPoint, direction: &Vector3f) -> Option<Vector3f> {
+            Some(self.attenuation(rec) * self.pdf(ray, rec, direction))
         }
 
+        fn pdf(&self, _ray: &Ray, rec: &HitPoint, direction: &Vector3f) -> f32 {
+            rec.normal.dot(direction).max(0.0) / PI
+        }
+
+}
+
+/// Rough diffuse surface, of V shaped Lambertian facets that shadow and light each other,
+/// as clay, concrete or cloth. Brighter than Lambertian when lit from behind the viewer and
+/// flatter overall. The roughness is the standard deviation of the facet angle, in radians,
+/// 0 is Lambertian
+#[derive(Clone, Debug)]
+pub struct OrenNayar {
+    albedo: Vector3f,
+    /// terms of the qualitative model
+    a: f32,
+    b: f32,
 }
 
-#[derive(Clone)]
+impl OrenNayar {
+    pub fn new(albedo: Vector3f, roughness: f32) -> OrenNayar {
+        let sigma2 = roughness.max(0.0).powi(2);
+        OrenNayar {
+            albedo,
+            a: 1.0 - 0.5 * sigma2 / (sigma2 + 0.33),
+            b: 0.45 * sigma2 / (sigma2 + 0.09),
+        }
+    }
+}
+
+impl Material for OrenNayar {
+
+    fn sample(&self, ray: &Ray, rec: &HitPoint) -> Option<BsdfSample> {
+        let direction = (rec.normal + random_unit_vector()).try_normalize(1e-6)?;
+        let pdf = self.pdf(ray, rec, &direction);
+        if pdf <= 0.0 {
+            return None;
+        }
+        let eval = self.eval(ray, rec, &direction)?;
+        Some(BsdfSample { direction, weight: eval / pdf, pdf, specular: false })
+    }
+
+    fn eval(&self, ray: &Ray, rec: &HitPoint, direction: &Vector3f) -> Option<Vector3f> {
+        let to_origin = -ray.direction.normalize();
+        let (cos_i, cos_o) = (rec.normal.dot(direction), rec.normal.dot(&to_origin));
+        if cos_i <= 0.0 || cos_o <= 0.0 {
+            return Some(Vector3f::zeros());
+        }
+        let sin_i = (1.0 - cos_i * cos_i).max(0.0).sqrt();
+        let sin_o = (1.0 - cos_o * cos_o).max(0.0).sqrt();
+        // cosine of the azimuth between the directions, around the normal
+        let cos_phi = if sin_i > 1e-4 && sin_o > 1e-4 {
+            ((direction - rec.normal * cos_i).dot(&(to_origin - rec.normal * cos_o)) / (sin_i * sin_o)).max(0.0)
+        } else {
+            0.0
+        };
+        // sine of the larger angle with the normal times the tangent of the smaller one
+        let sin_tan = if cos_i > cos_o { sin_o * sin_i / cos_i } else { sin_i * sin_o / cos_o };
+        Some(self.albedo * ((self.a + self.b * cos_phi * sin_tan) * cos_i / PI))
+    }
+
+    fn pdf(&self, _ray: &Ray, rec: &HitPoint, direction: &Vector3f) -> f32 {
+        rec.normal.dot(direction).max(0.0) / PI
+    }
+
+}
+
+/// Reflects around the mirror direction, in a lobe that widens with the fuzziness,
+/// the density grows as the cosine with the mirror direction to the power of 2 / fuzziness² - 2.
+/// The fuzziness is clamped to [0, 1], without it is a perfect mirror
+#[derive(Clone, Debug)]
 pub struct Metal {
     albedo : Vector3f,
     fuzziness: f32,
@@ -42,13 +176,20 @@ pub struct Metal {
 
 impl Metal {
     pub fn new(albedo: Vector3f, fuzziness: f32) -> Metal {
-        assert!(fuzziness >= 0f32 && fuzziness <= 1.0f32);
-        Metal{albedo, fuzziness} 
+        Metal{albedo, fuzziness: fuzziness.clamp(0.0, 1.0)}
+    }
+
+    fn is_mirror(&self) -> bool {
+        self.fuzziness < 1e-3
+    }
+
+    fn lobe_exponent(&self) -> f32 {
+        2.0 / (self.fuzziness * self.fuzziness) - 2.0
     }
 }
 
 fn reflect(v: &Vector3f, n: &Vector3f) -> Vector3f {
-    return v - 2f32*(v.dot(n)*n);
+    v - 2f32*(v.dot(n)*n)
 }
 
 
@@ -57,8 +198,7 @@ fn refract(uv: &Vector3f, n: &Vector3f, etai_over_etat: f32) -> Vector3f {
     let cos_theta = (-uv).dot(n);
     let r_out_perp: Vector3f = etai_over_etat * (uv + cos_theta * n);
     let r_out_parallel = -(1.0- r_out_perp.magnitude_squared()).abs().sqrt() * n;
-    let r_out = r_out_perp + r_out_parallel;
-    r_out
+    r_out_perp + r_out_parallel
 }
 
 // vec3 refract(const vec3& uv, const vec3& n, double etai_over_etat) {
@@ -70,22 +210,50 @@ fn refract(uv: &Vector3f, n: &Vector3f, etai_over_etat: f32) -> Vector3f {
 
 impl Material for Metal {
 
-    fn scatter  (&self, ray: &Ray, rec: &HitPoint)-> Option<(Vector3f, Ray)> {
+    fn sample(&self, ray: &Ray, rec: &HitPoint) -> Option<BsdfSample> {
         let reflected : Vector3f = reflect(&ray.direction.normalize(), &rec.normal);
-        let scattered_direction = reflected + self.fuzziness * random_in_unit_sphere(); 
-        let scattered = Ray{origin: rec.position, direction: scattered_direction};
-        let attenuation = self.albedo;
-        let scatter_same_normal_direction = scattered.direction.dot(&rec.normal) > 0f32;
+        if self.is_mirror() {
+            return Some(BsdfSample { direction: reflected, weight: self.albedo, pdf: 1.0, specular: true });
+        }
+        let mut rng = rand::thread_rng();
+        let cos_lobe = rng.gen::<f32>().powf(1.0 / (self.lobe_exponent() + 1.0));
+        let direction = direction_around(&reflected, cos_lobe, 2.0 * PI * rng.gen::<f32>());
+        let scatter_same_normal_direction = direction.dot(&rec.normal) > 0f32;
         if scatter_same_normal_direction {
-            Some((attenuation, scattered))
+            Some(BsdfSample { direction, weight: self.albedo, pdf: self.pdf(ray, rec, &direction), specular: false })
         } else {
             None
         }
     }
 
+    /// the lobe below the surface is absorbed, so eval / pdf is the albedo above it
+    fn eval(&self, ray: &Ray, rec: &HitPoint, direction: &Vector3f) -> Option<Vector3f> {
+        if self.is_mirror() {
+            return None;
+        }
+        if direction.dot(&rec.normal) <= 0.0 {
+            return Some(Vector3f::zeros());
+        }
+        Some(self.albedo * self.pdf(ray, rec, direction))
+    }
+
+    fn pdf(&self, ray: &Ray, rec: &HitPoint, direction: &Vector3f) -> f32 {
+        if self.is_mirror() {
+            return 0.0;
+        }
+        let reflected = reflect(&ray.direction.normalize(), &rec.normal);
+        let exponent = self.lobe_exponent();
+        // the lobe is the hemisphere around the mirror direction, even at an exponent of 0
+        let cos_lobe = reflected.dot(direction);
+        if cos_lobe <= 0.0 {
+            return 0.0;
+        }
+        (exponent + 1.0) / (2.0 * PI) * cos_lobe.powf(exponent)
+    }
+
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct Dielectric {
     reflective_index: f32
 }
@@ -98,12 +266,195 @@ impl Dielectric {
 
 impl Material for Dielectric {
 
-    fn scatter  (&self, ray: &Ray, rec: &HitPoint)-> Option<(Vector3f, Ray)> {
+    fn sample(&self, ray: &Ray, rec: &HitPoint) -> Option<BsdfSample> {
         let attenuation = Vector3f::new(1.0, 1.0, 1.0);
         let etai_over_etat = if rec.front_face { 1.0 / self.reflective_index } else {self.reflective_index};
         let unit_direction = ray.direction.normalize();
         let refracted = refract(&unit_direction, &rec.normal, etai_over_etat);
-        let scattered = Ray{origin: rec.position, direction: refracted};
-        Some((attenuation, scattered))
+        Some(BsdfSample { direction: refracted.normalize(), weight: attenuation, pdf: 1.0, specular: true })
+    }
+}
+
+/// Phase function of participating media, scatters the same in all directions
+#[derive(Clone, Debug)]
+pub struct Isotropic {
+    albedo: Vector3f,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Vector3f) -> Isotropic {
+        Isotropic{albedo}
+    }
+}
+
+impl Material for Isotropic {
+
+    fn sample(&self, ray: &Ray, rec: &HitPoint) -> Option<BsdfSample> {
+        let direction = random_unit_vector();
+        Some(BsdfSample { direction, weight: self.albedo, pdf: self.pdf(ray, rec, &direction), specular: false })
+    }
+
+    fn eval(&self, ray: &Ray, rec: &HitPoint, direction: &Vector3f) -> Option<Vector3f> {
+        Some(self.albedo * self.pdf(ray, rec, direction))
+    }
+
+    fn pdf(&self, _ray: &Ray, _rec: &HitPoint, _direction: &Vector3f) -> f32 {
+        1.0 / (4.0 * PI)
+    }
+}
+
+/// Henyey–Greenstein phase function of participating media. The anisotropy
+/// is the mean cosine of the scattering angle: positive scatters forward,
+/// negative backward, 0 is isotropic
+#[derive(Clone, Debug)]
+pub struct HenyeyGreenstein {
+    albedo: Vector3f,
+    anisotropy: f32,
+}
+
+impl HenyeyGreenstein {
+    /// the anisotropy is clamped to (-1, 1)
+    pub fn new(albedo: Vector3f, anisotropy: f32) -> HenyeyGreenstein {
+        HenyeyGreenstein{albedo, anisotropy: anisotropy.clamp(-0.99, 0.99)}
+    }
+
+    /// cosine of the angle between the incoming and the scattered direction, by inverting the CDF
+    pub fn sample_cos_theta(&self, xi: f32) -> f32 {
+        let g = self.anisotropy;
+        if g.abs() < 1e-3 {
+            return 1.0 - 2.0 * xi;
+        }
+        let ratio = (1.0 - g * g) / (1.0 - g + 2.0 * g * xi);
+        ((1.0 + g * g - ratio * ratio) / (2.0 * g)).clamp(-1.0, 1.0)
+    }
+
+    /// density over the sphere of scattering by the angle with this cosine
+    pub fn phase(&self, cos_theta: f32) -> f32 {
+        let g = self.anisotropy;
+        let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+        (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+    }
+}
+
+impl Material for HenyeyGreenstein {
+
+    fn sample(&self, ray: &Ray, rec: &HitPoint) -> Option<BsdfSample> {
+        let mut rng = rand::thread_rng();
+        let cos_theta = self.sample_cos_theta(rng.gen());
+        let direction = direction_around(&ray.direction.normalize(), cos_theta, 2.0 * PI * rng.gen::<f32>());
+        Some(BsdfSample { direction, weight: self.albedo, pdf: self.pdf(ray, rec, &direction), specular: false })
+    }
+
+    fn eval(&self, ray: &Ray, rec: &HitPoint, direction: &Vector3f) -> Option<Vector3f> {
+        Some(self.albedo * self.pdf(ray, rec, direction))
+    }
+
+    fn pdf(&self, ray: &Ray, _rec: &HitPoint, direction: &Vector3f) -> f32 {
+        self.phase(ray.direction.normalize().dot(direction))
+    }
+}
+
+/// Emits light from the front face and scatters none, the material of area lights
+#[derive(Clone, Debug)]
+pub struct DiffuseLight {
+    radiance: Vector3f,
+}
+
+impl DiffuseLight {
+    pub fn new(radiance: Vector3f) -> DiffuseLight {
+        DiffuseLight{radiance}
+    }
+}
+
+impl Material for DiffuseLight {
+
+    fn sample(&self, _ray: &Ray, _rec: &HitPoint) -> Option<BsdfSample> {
+        None
+    }
+
+    fn emitted(&self, _ray: &Ray, rec: &HitPoint) -> Vector3f {
+        if rec.front_face { self.radiance } else { Vector3f::zeros() }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Vector2f;
+
+    /// average of eval / pdf over directions sampled by the material, against the sample weights
+    fn check_sampling(material: &dyn Material, ray: &Ray, rec: &HitPoint) {
+        let samples = 20000;
+        let mut weights = Vector3f::zeros();
+        for _ in 0..samples {
+            if let Some(sample) = material.sample(ray, rec) {
+                let eval = material.eval(ray, rec, &sample.direction).unwrap();
+                let pdf = material.pdf(ray, rec, &sample.direction);
+                assert!((pdf - sample.pdf).abs() <= 1e-3 * pdf);
+                assert!((eval / pdf - sample.weight).norm() < 1e-3);
+                weights += sample.weight;
+            }
+        }
+        // the pdf integrates to 1 over the sphere, estimated with uniform directions
+        let integral : f32 = (0..samples).map(|_| material.pdf(ray, rec, &random_unit_vector())).sum::<f32>()
+            * 4.0 * PI / samples as f32;
+        assert!((integral - 1.0).abs() < 0.05, "pdf integral {}", integral);
+        assert!(weights.x / samples as f32 <= 1.0);
+    }
+
+    #[test]
+    fn samples_match_eval_and_pdf() {
+        let rec = HitPoint {
+            t: 1.0,
+            position: Vector3f::zeros(),
+            normal: Vector3f::new(0.0, 1.0, 0.0),
+            front_face: true,
+            uv: Vector2f::zeros(),
+            dpdu: Vector3f::new(1.0, 0.0, 0.0),
+            dpdv: Vector3f::new(0.0, 0.0, -1.0),
+        };
+        let ray = Ray { origin: Vector3f::new(-1.0, 1.0, 0.0), direction: Vector3f::new(1.0, -1.0, 0.0), time: 0.0 };
+        check_sampling(&Lambertian::new(Vector3f::repeat(0.5)), &ray, &rec);
+        check_sampling(&Metal::new(Vector3f::repeat(0.9), 0.5), &ray, &rec);
+        // out of range fuzziness is clamped
+        check_sampling(&Metal::new(Vector3f::repeat(0.9), 1.5), &ray, &rec);
+        check_sampling(&HenyeyGreenstein::new(Vector3f::repeat(0.9), 0.3), &ray, &rec);
+
+        let mirror = Metal::new(Vector3f::repeat(0.9), 0.0);
+        let sample = mirror.sample(&ray, &rec).unwrap();
+        assert!(sample.specular && mirror.eval(&ray, &rec, &sample.direction).is_none());
+        assert!((sample.direction - Vector3f::new(1.0, 1.0, 0.0).normalize()).norm() < 1e-6);
+    }
+
+    #[test]
+    fn oren_nayar_reduces_to_lambertian() {
+        let rec = HitPoint {
+            t: 1.0,
+            position: Vector3f::zeros(),
+            normal: Vector3f::new(0.0, 1.0, 0.0),
+            front_face: true,
+            uv: Vector2f::zeros(),
+            dpdu: Vector3f::new(1.0, 0.0, 0.0),
+            dpdv: Vector3f::new(0.0, 0.0, -1.0),
+        };
+        let ray = Ray { origin: Vector3f::new(-1.0, 1.0, 0.0), direction: Vector3f::new(1.0, -1.0, 0.0), time: 0.0 };
+        let albedo = Vector3f::new(0.8, 0.5, 0.2);
+        let smooth = OrenNayar::new(albedo, 0.0);
+        let lambertian = Lambertian::new(albedo);
+        for _ in 0..100 {
+            let direction = random_unit_vector();
+            let difference = smooth.eval(&ray, &rec, &direction).unwrap() - lambertian.eval(&ray, &rec, &direction).unwrap();
+            assert!(difference.norm() < 1e-6);
+            assert_eq!(smooth.pdf(&ray, &rec, &direction), lambertian.pdf(&ray, &rec, &direction));
+        }
+
+        // rough clay sends more light back towards a light behind the viewer than forward
+        let clay = OrenNayar::new(albedo, 0.5);
+        check_sampling(&clay, &ray, &rec);
+        let back = Vector3f::new(-1.0, 1.0, 0.0).normalize();
+        let forward = Vector3f::new(1.0, 1.0, 0.0).normalize();
+        assert!(clay.eval(&ray, &rec, &back).unwrap().x > clay.eval(&ray, &rec, &forward).unwrap().x);
+        assert!(clay.eval(&ray, &rec, &back).unwrap().x > lambertian.eval(&ray, &rec, &back).unwrap().x);
     }
 }
@@ -0,0 +1,528 @@
+use crate::raycasting::ray::{HitPoint, Ray};
+use crate::types::Vector3f;
+use crate::geom::rand_geom::orthonormal_basis;
+use crate::material::material::{BsdfSample, Material};
+use rand::Rng;
+use std::f32::consts::PI;
+
+/// Orthonormal frame around the normal at a hit, the normal is the z axis of the local coordinates
+#[derive(Clone, Debug)]
+pub struct ShadingFrame {
+    tangent: Vector3f,
+    bitangent: Vector3f,
+    normal: Vector3f,
+}
+
+impl ShadingFrame {
+
+    /// around the unit vector normal
+    pub fn new(normal: &Vector3f) -> ShadingFrame {
+        let (tangent, bitangent) = orthonormal_basis(normal);
+        ShadingFrame { tangent, bitangent, normal: *normal }
+    }
+
+    pub fn to_local(&self, v: &Vector3f) -> Vector3f {
+        Vector3f::new(v.dot(&self.tangent), v.dot(&self.bitangent), v.dot(&self.normal))
+    }
+
+    pub fn to_world(&self, v: &Vector3f) -> Vector3f {
+        self.tangent * v.x + self.bitangent * v.y + self.normal * v.z
+    }
+
+}
+
+/// Trowbridge–Reitz (GGX) distribution of the normals of the microfacets, isotropic,
+/// with the height correlated Smith masking. Directions are local, the surface normal is z
+#[derive(Clone, Copy, Debug)]
+pub struct Ggx {
+    alpha: f32,
+}
+
+impl Ggx {
+
+    /// the roughness in [0, 1] is squared into the width of the distribution,
+    /// so that the highlight widens about linearly with it
+    pub fn from_roughness(roughness: f32) -> Ggx {
+        Ggx { alpha: roughness.clamp(0.0, 1.0).powi(2) }
+    }
+
+    /// too smooth to be anything but a mirror, the distribution is a delta
+    pub fn is_smooth(&self) -> bool {
+        self.alpha < 1e-3
+    }
+
+    /// density of the microfacet normals over the solid angle, weighted by their cosine with the normal
+    pub fn d(&self, h: &Vector3f) -> f32 {
+        if h.z <= 0.0 {
+            return 0.0;
+        }
+        let alpha2 = self.alpha * self.alpha;
+        let t = h.z * h.z * (alpha2 - 1.0) + 1.0;
+        alpha2 / (PI * t * t)
+    }
+
+    /// Smith auxiliary function, the area of the microfacets hidden along w relative to the visible one
+    fn lambda(&self, w: &Vector3f) -> f32 {
+        let cos2 = w.z * w.z;
+        let tan2 = (1.0 - cos2).max(0.0) / cos2;
+        ((1.0 + self.alpha * self.alpha * tan2).sqrt() - 1.0) / 2.0
+    }
+
+    /// fraction of the microfacets visible along w
+    pub fn g1(&self, w: &Vector3f) -> f32 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// fraction of the microfacets visible along both directions
+    pub fn g2(&self, wo: &Vector3f, wi: &Vector3f) -> f32 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// microfacet normal among the ones visible from wo, above the surface, for uniform xi1 and xi2.
+    /// Heitz 2018, sampling the projection of a hemisphere in the stretched space where the
+    /// distribution is the one of a unit sphere
+    pub fn sample_visible_normal(&self, wo: &Vector3f, xi1: f32, xi2: f32) -> Vector3f {
+        let stretched = Vector3f::new(self.alpha * wo.x, self.alpha * wo.y, wo.z).normalize();
+        let length2 = stretched.x * stretched.x + stretched.y * stretched.y;
+        let t1 = if length2 > 0.0 {
+            Vector3f::new(-stretched.y, stretched.x, 0.0) / length2.sqrt()
+        } else {
+            Vector3f::new(1.0, 0.0, 0.0)
+        };
+        let t2 = stretched.cross(&t1);
+        // a point of the disk, squeezed onto the part of it that the hemisphere projects to
+        let r = xi1.sqrt();
+        let phi = 2.0 * PI * xi2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + stretched.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * r * phi.sin();
+        let normal = t1 * p1 + t2 * p2 + stretched * (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+        Vector3f::new(self.alpha * normal.x, self.alpha * normal.y, normal.z.max(0.0)).normalize()
+    }
+
+    /// solid angle density of sample_visible_normal choosing h
+    pub fn visible_normal_pdf(&self, wo: &Vector3f, h: &Vector3f) -> f32 {
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) * wo.dot(h).max(0.0) * self.d(h) / wo.z
+    }
+
+}
+
+fn reflect(wo: &Vector3f, h: &Vector3f) -> Vector3f {
+    2.0 * wo.dot(h) * h - wo
+}
+
+/// direction wo refracts to through the surface of normal h, on the side of wo, where eta is
+/// the index of the other side over the one of wo. None for a total internal reflection
+fn refract(wo: &Vector3f, h: &Vector3f, eta: f32) -> Option<Vector3f> {
+    let cos_i = wo.dot(h);
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-wo / eta + (cos_i / eta - cos_t) * h)
+}
+
+/// reflectance of the interface between two dielectrics, for unpolarized light arriving at this
+/// cosine with the normal, where eta is the index of the other side over the one of the light
+pub fn fresnel_dielectric(cos_i: f32, eta: f32) -> f32 {
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    (parallel * parallel + perpendicular * perpendicular) / 2.0
+}
+
+/// reflectance of a conductor of complex index eta + i k, for unpolarized light in air
+fn fresnel_conductor_channel(cos_i: f32, eta: f32, k: f32) -> f32 {
+    let cos2 = cos_i.clamp(0.0, 1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+    let t0 = eta * eta - k * k - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let t2 = 2.0 * cos_i * a;
+    let perpendicular = (t1 - t2) / (t1 + t2);
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let parallel = perpendicular * (t3 - t4) / (t3 + t4);
+    (perpendicular + parallel) / 2.0
+}
+
+/// Schlick approximation of the reflectance, from the one at normal incidence
+pub fn fresnel_schlick(normal_reflectance: &Vector3f, cos_i: f32) -> Vector3f {
+    normal_reflectance + (Vector3f::repeat(1.0) - normal_reflectance) * (1.0 - cos_i.clamp(0.0, 1.0)).powi(5)
+}
+
+/// the frame of the normal at the hit and the direction to the origin of the ray in it
+fn local_outgoing(ray: &Ray, rec: &HitPoint) -> (ShadingFrame, Vector3f) {
+    let frame = ShadingFrame::new(&rec.normal);
+    let wo = frame.to_local(&-ray.direction.normalize());
+    (frame, wo)
+}
+
+/// Reflection off rough conductors, the fresnel function gives the reflectance at the
+/// cosine of the angle with the microfacet normal. Samples only the visible microfacets
+pub(crate) fn sample_reflection(ggx: &Ggx, ray: &Ray, rec: &HitPoint, fresnel: impl Fn(f32) -> Vector3f) -> Option<BsdfSample> {
+    let (frame, wo) = local_outgoing(ray, rec);
+    if wo.z <= 0.0 {
+        return None;
+    }
+    if ggx.is_smooth() {
+        let direction = frame.to_world(&Vector3f::new(-wo.x, -wo.y, wo.z));
+        return Some(BsdfSample { direction, weight: fresnel(wo.z), pdf: 1.0, specular: true });
+    }
+    let mut rng = rand::thread_rng();
+    let h = ggx.sample_visible_normal(&wo, rng.gen(), rng.gen());
+    let wi = reflect(&wo, &h);
+    if wi.z <= 0.0 {
+        return None;
+    }
+    Some(BsdfSample {
+        direction: frame.to_world(&wi),
+        // D and the cosines cancel with the density of the visible normals
+        weight: fresnel(wo.dot(&h)) * (ggx.g2(&wo, &wi) / ggx.g1(&wo)),
+        pdf: local_reflection_pdf(ggx, &wo, &wi),
+        specular: false,
+    })
+}
+
+/// the reflection sampled by sample_reflection, times the cosine, None for mirrors
+pub(crate) fn eval_reflection(ggx: &Ggx, ray: &Ray, rec: &HitPoint, direction: &Vector3f,
+        fresnel: impl Fn(f32) -> Vector3f) -> Option<Vector3f> {
+    if ggx.is_smooth() {
+        return None;
+    }
+    let (frame, wo) = local_outgoing(ray, rec);
+    let wi = frame.to_local(direction);
+    if wo.z <= 0.0 || wi.z <= 0.0 {
+        return Some(Vector3f::zeros());
+    }
+    let h = (wo + wi).normalize();
+    Some(fresnel(wo.dot(&h)) * (ggx.d(&h) * ggx.g2(&wo, &wi) / (4.0 * wo.z)))
+}
+
+pub(crate) fn reflection_pdf(ggx: &Ggx, ray: &Ray, rec: &HitPoint, direction: &Vector3f) -> f32 {
+    if ggx.is_smooth() {
+        return 0.0;
+    }
+    let (frame, wo) = local_outgoing(ray, rec);
+    local_reflection_pdf(ggx, &wo, &frame.to_local(direction))
+}
+
+/// from the half vector of the directions rather than the sampled one, the same to the rounding
+/// that matters on the steep peak of smooth surfaces
+fn local_reflection_pdf(ggx: &Ggx, wo: &Vector3f, wi: &Vector3f) -> f32 {
+    if wo.z <= 0.0 || wi.z <= 0.0 {
+        return 0.0;
+    }
+    let h = (wo + wi).normalize();
+    ggx.visible_normal_pdf(wo, &h) / (4.0 * wo.dot(&h))
+}
+
+/// Rough metal reflecting as GGX microfacets, the albedo is its reflectance at normal
+/// incidence, the Schlick approximation brightens it towards grazing angles.
+/// The roughness is clamped to [0, 1], a roughness of 0 is a mirror
+#[derive(Clone, Debug)]
+pub struct RoughMetal {
+    albedo: Vector3f,
+    ggx: Ggx,
+}
+
+impl RoughMetal {
+
+    pub fn new(albedo: Vector3f, roughness: f32) -> RoughMetal {
+        RoughMetal { albedo, ggx: Ggx::from_roughness(roughness.clamp(0.0, 1.0)) }
+    }
+
+    fn fresnel(&self, cos_theta: f32) -> Vector3f {
+        fresnel_schlick(&self.albedo, cos_theta)
+    }
+
+}
+
+impl Material for RoughMetal {
+
+    fn sample(&self, ray: &Ray, rec: &HitPoint) -> Option<BsdfSample> {
+        sample_reflection(&self.ggx, ray, rec, |cos| self.fresnel(cos))
+    }
+
+    fn eval(&self, ray: &Ray, rec: &HitPoint, direction: &Vector3f) -> Option<Vector3f> {
+        eval_reflection(&self.ggx, ray, rec, direction, |cos| self.fresnel(cos))
+    }
+
+    fn pdf(&self, ray: &Ray, rec: &HitPoint, direction: &Vector3f) -> f32 {
+        reflection_pdf(&self.ggx, ray, rec, direction)
+    }
+
+}
+
+/// Complex index of refraction eta + i k of a conductor, for red, green and blue
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ComplexIor {
+    pub eta: Vector3f,
+    pub k: Vector3f,
+}
+
+impl ComplexIor {
+
+    pub fn gold() -> ComplexIor {
+        ComplexIor { eta: Vector3f::new(0.143, 0.374, 1.442), k: Vector3f::new(3.983, 2.385, 1.603) }
+    }
+
+    pub fn copper() -> ComplexIor {
+        ComplexIor { eta: Vector3f::new(0.200, 0.924, 1.102), k: Vector3f::new(3.912, 2.452, 2.142) }
+    }
+
+    pub fn aluminum() -> ComplexIor {
+        ComplexIor { eta: Vector3f::new(1.657, 0.880, 0.521), k: Vector3f::new(9.224, 6.270, 4.837) }
+    }
+
+    pub fn silver() -> ComplexIor {
+        ComplexIor { eta: Vector3f::new(0.155, 0.117, 0.138), k: Vector3f::new(4.828, 3.122, 2.147) }
+    }
+
+    /// reflectance at the cosine of the angle with the normal
+    pub fn fresnel(&self, cos_i: f32) -> Vector3f {
+        Vector3f::new(
+            fresnel_conductor_channel(cos_i, self.eta.x, self.k.x),
+            fresnel_conductor_channel(cos_i, self.eta.y, self.k.y),
+            fresnel_conductor_channel(cos_i, self.eta.z, self.k.z),
+        )
+    }
+
+}
+
+/// Rough metal of a measured complex index of refraction, the GGX microfacets reflect
+/// with the exact Fresnel reflectance of the metal
+#[derive(Clone, Debug)]
+pub struct Conductor {
+    ior: ComplexIor,
+    ggx: Ggx,
+}
+
+impl Conductor {
+    /// a roughness of 0 is a mirror
+    pub fn new(ior: ComplexIor, roughness: f32) -> Conductor {
+        Conductor { ior, ggx: Ggx::from_roughness(roughness) }
+    }
+}
+
+impl Material for Conductor {
+
+    fn sample(&self, ray: &Ray, rec: &HitPoint) -> Option<BsdfSample> {
+        sample_reflection(&self.ggx, ray, rec, |cos| self.ior.fresnel(cos))
+    }
+
+    fn eval(&self, ray: &Ray, rec: &HitPoint, direction: &Vector3f) -> Option<Vector3f> {
+        eval_reflection(&self.ggx, ray, rec, direction, |cos| self.ior.fresnel(cos))
+    }
+
+    fn pdf(&self, ray: &Ray, rec: &HitPoint, direction: &Vector3f) -> f32 {
+        reflection_pdf(&self.ggx, ray, rec, direction)
+    }
+
+}
+
+/// Rough glass, each GGX microfacet reflects or refracts the light in proportion to its
+/// Fresnel reflectance. As `Dielectric`, the radiance is not scaled by the squared ratio
+/// of the indices when crossing, the light entering and leaving a closed object balances
+#[derive(Clone, Debug)]
+pub struct RoughDielectric {
+    reflective_index: f32,
+    ggx: Ggx,
+}
+
+impl RoughDielectric {
+    /// a roughness of 0 is smooth glass, with Fresnel reflections
+    pub fn new(reflective_index: f32, roughness: f32) -> RoughDielectric {
+        RoughDielectric { reflective_index, ggx: Ggx::from_roughness(roughness) }
+    }
+
+    /// index of the side the ray does not come from over the one it comes from
+    fn eta(&self, rec: &HitPoint) -> f32 {
+        if rec.front_face { self.reflective_index } else { 1.0 / self.reflective_index }
+    }
+
+    /// microfacet normal that scatters wo to wi, by reflection when they are on the same side,
+    /// None if no microfacet facing wo does
+    fn half_vector(wo: &Vector3f, wi: &Vector3f, eta: f32) -> Option<Vector3f> {
+        let reflected = wi.z > 0.0;
+        let h = if reflected { wo + wi } else { wo + wi * eta };
+        let h = h.try_normalize(1e-6)?;
+        let h = if h.z < 0.0 { -h } else { h };
+        let facing = wo.dot(&h) > 0.0 && if reflected { wi.dot(&h) > 0.0 } else { wi.dot(&h) < 0.0 };
+        if facing { Some(h) } else { None }
+    }
+
+    /// density of choosing wi among the visible normals and between reflection and refraction,
+    /// with the Jacobian from the microfacet normal to wi
+    fn local_pdf(&self, wo: &Vector3f, wi: &Vector3f, eta: f32) -> f32 {
+        let h = match RoughDielectric::half_vector(wo, wi, eta) {
+            Some(h) => h,
+            None => return 0.0,
+        };
+        let (cos_oh, cos_ih) = (wo.dot(&h), wi.dot(&h));
+        let reflectance = fresnel_dielectric(cos_oh, eta);
+        let normal_pdf = self.ggx.visible_normal_pdf(wo, &h);
+        if wi.z > 0.0 {
+            normal_pdf * reflectance / (4.0 * cos_oh)
+        } else {
+            let denominator = (cos_ih + cos_oh / eta).powi(2);
+            normal_pdf * (1.0 - reflectance) * -cos_ih / denominator
+        }
+    }
+}
+
+impl Material for RoughDielectric {
+
+    fn sample(&self, ray: &Ray, rec: &HitPoint) -> Option<BsdfSample> {
+        let (frame, wo) = local_outgoing(ray, rec);
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let eta = self.eta(rec);
+        let mut rng = rand::thread_rng();
+        let h = if self.ggx.is_smooth() {
+            Vector3f::new(0.0, 0.0, 1.0)
+        } else {
+            self.ggx.sample_visible_normal(&wo, rng.gen(), rng.gen())
+        };
+        let wi = if rng.gen::<f32>() < fresnel_dielectric(wo.dot(&h), eta) {
+            reflect(&wo, &h)
+        } else {
+            // the reflectance is 1 for total internal reflections, this refraction exists
+            refract(&wo, &h, eta)?
+        };
+        if self.ggx.is_smooth() {
+            return Some(BsdfSample { direction: frame.to_world(&wi), weight: Vector3f::repeat(1.0), pdf: 1.0, specular: true });
+        }
+        // rough microfacets can scatter to the wrong side of the surface
+        if wi.z == 0.0 || (wi.z > 0.0) != (wi.dot(&h) > 0.0) {
+            return None;
+        }
+        Some(BsdfSample {
+            direction: frame.to_world(&wi),
+            // the Fresnel choice and the density of the visible normals leave the masking
+            weight: Vector3f::repeat(self.ggx.g2(&wo, &wi) / self.ggx.g1(&wo)),
+            pdf: self.local_pdf(&wo, &wi, eta),
+            specular: false,
+        })
+    }
+
+    fn eval(&self, ray: &Ray, rec: &HitPoint, direction: &Vector3f) -> Option<Vector3f> {
+        if self.ggx.is_smooth() {
+            return None;
+        }
+        let (frame, wo) = local_outgoing(ray, rec);
+        let wi = frame.to_local(direction);
+        let eta = self.eta(rec);
+        let h = match RoughDielectric::half_vector(&wo, &wi, eta) {
+            Some(h) => h,
+            None => return Some(Vector3f::zeros()),
+        };
+        let (cos_oh, cos_ih) = (wo.dot(&h), wi.dot(&h));
+        let reflectance = fresnel_dielectric(cos_oh, eta);
+        let masked = self.ggx.d(&h) * self.ggx.g2(&wo, &wi);
+        let value = if wi.z > 0.0 {
+            masked * reflectance / (4.0 * wo.z)
+        } else {
+            let denominator = (cos_ih + cos_oh / eta).powi(2);
+            masked * (1.0 - reflectance) * -cos_ih * cos_oh / (wo.z * denominator)
+        };
+        Some(Vector3f::repeat(value))
+    }
+
+    fn pdf(&self, ray: &Ray, rec: &HitPoint, direction: &Vector3f) -> f32 {
+        if self.ggx.is_smooth() {
+            return 0.0;
+        }
+        let (frame, wo) = local_outgoing(ray, rec);
+        self.local_pdf(&wo, &frame.to_local(direction), self.eta(rec))
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Vector2f;
+
+    /// mean weight of the sampled directions, the fraction of the light the material scatters
+    fn albedo(material: &dyn Material, ray: &Ray, rec: &HitPoint) -> f32 {
+        let samples = 50000;
+        let mut total = 0.0;
+        for _ in 0..samples {
+            if let Some(sample) = material.sample(ray, rec) {
+                total += sample.weight.x;
+                if sample.specular {
+                    continue;
+                }
+                let eval = material.eval(ray, rec, &sample.direction).unwrap();
+                let pdf = material.pdf(ray, rec, &sample.direction);
+                assert!((pdf - sample.pdf).abs() <= 1e-3 * pdf, "{} {}", pdf, sample.pdf);
+                assert!((eval / pdf - sample.weight).norm() < 1e-3 * sample.weight.norm().max(1.0));
+            }
+        }
+        total / samples as f32
+    }
+
+    #[test]
+    fn ggx_passes_the_white_furnace_tests() {
+        let ggx = Ggx::from_roughness(0.5);
+        let wo = Vector3f::new(0.6, 0.0, 0.8);
+        // the visible normals, turned into reflected directions, cover the whole sphere once:
+        // the integral of D G1 / (4 cos) over the directions is 1
+        let (thetas, phis) = (256, 512);
+        let mut integral = 0.0;
+        for i in 0..thetas {
+            let theta = (i as f32 + 0.5) / thetas as f32 * PI;
+            for j in 0..phis {
+                let phi = (j as f32 + 0.5) / phis as f32 * 2.0 * PI;
+                let wi = Vector3f::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos());
+                let h = (wo + wi).normalize();
+                let solid_angle = theta.sin() * (PI / thetas as f32) * (2.0 * PI / phis as f32);
+                integral += ggx.d(&h) * ggx.g1(&wo) / (4.0 * wo.z) * solid_angle;
+            }
+        }
+        assert!((integral - 1.0).abs() < 1e-2, "weak furnace {}", integral);
+
+        // a white conductor or glass scatters all the light, but the light that the microfacets
+        // mask or reflect below the surface, that would bounce between them. At a roughness
+        // of 1 more than half of it is lost
+        let hit = |front_face| HitPoint {
+            t: 1.0,
+            position: Vector3f::zeros(),
+            normal: Vector3f::new(0.0, 1.0, 0.0),
+            front_face,
+            uv: Vector2f::zeros(),
+            dpdu: Vector3f::new(1.0, 0.0, 0.0),
+            dpdv: Vector3f::new(0.0, 0.0, -1.0),
+        };
+        let rec = hit(true);
+        let ray = Ray { origin: Vector3f::new(-1.0, 1.0, 0.0), direction: Vector3f::new(1.0, -1.0, 0.0), time: 0.0 };
+        let smooth = albedo(&RoughMetal::new(Vector3f::repeat(1.0), 0.2), &ray, &rec);
+        let rough = albedo(&RoughMetal::new(Vector3f::repeat(1.0), 1.0), &ray, &rec);
+        assert!(smooth > 0.98 && smooth <= 1.0, "{}", smooth);
+        assert!(rough > 0.35 && rough < smooth - 0.5, "{}", rough);
+        for front_face in [true, false] {
+            let glass = albedo(&RoughDielectric::new(1.5, 0.2), &ray, &hit(front_face));
+            assert!(glass > 0.97 && glass <= 1.0, "{}", glass);
+        }
+        assert!((albedo(&RoughDielectric::new(1.5, 0.0), &ray, &rec) - 1.0).abs() < 1e-6);
+
+        // gold reflects red more than blue, every metal reflects everything at grazing angles
+        let gold = ComplexIor::gold().fresnel(1.0);
+        assert!(gold.x > 0.9 && gold.z < 0.4);
+        assert!(ComplexIor::aluminum().fresnel(1.0).min() > 0.85);
+        assert!(ComplexIor::copper().fresnel(0.0).min() > 0.999);
+    }
+}
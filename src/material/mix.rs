@@ -0,0 +1,236 @@
+use crate::raycasting::ray::{HitPoint, Ray};
+use crate::types::Vector3f;
+use crate::material::material::{BsdfSample, Material};
+use crate::material::microfacet::{eval_reflection, fresnel_dielectric, reflection_pdf, sample_reflection, Ggx};
+use crate::material::texture::Texture;
+use rand::Rng;
+
+/// Sample of one of the parts of a material made of several, chosen with this probability,
+/// turned into a sample of the whole: the direction is weighted by the whole eval and pdf.
+/// Specular samples are left out of them, factor weights the part for those
+pub(crate) fn mixture_sample(whole: &dyn Material, ray: &Ray, rec: &HitPoint, sample: BsdfSample,
+        factor: Vector3f, probability: f32) -> Option<BsdfSample> {
+    if sample.specular {
+        return Some(BsdfSample {
+            weight: sample.weight.component_mul(&factor) / probability,
+            pdf: sample.pdf * probability,
+            ..sample
+        });
+    }
+    let pdf = whole.pdf(ray, rec, &sample.direction);
+    if pdf <= 0.0 {
+        return None;
+    }
+    let eval = whole.eval(ray, rec, &sample.direction)?;
+    Some(BsdfSample { weight: eval / pdf, pdf, ..sample })
+}
+
+/// the sum of the evals of the parts that are not specular only, None if they all are
+pub(crate) fn sum_evals(evals: impl Iterator<Item = Option<Vector3f>>) -> Option<Vector3f> {
+    evals.flatten().reduce(|total, eval| total + eval)
+}
+
+/// Chooses between two materials at each hit, the second one in proportion to the weight,
+/// times the mean of the mask texture when there is one
+#[derive(Clone, Debug)]
+pub struct Mix {
+    first: Box<dyn Material>,
+    second: Box<dyn Material>,
+    weight: f32,
+    mask: Option<Box<dyn Texture>>,
+}
+
+impl Mix {
+    pub fn new(first: Box<dyn Material>, second: Box<dyn Material>, weight: f32) -> Mix {
+        Mix{first, second, weight, mask: None}
+    }
+
+    pub fn masked(first: Box<dyn Material>, second: Box<dyn Material>, weight: f32, mask: Box<dyn Texture>) -> Mix {
+        Mix{first, second, weight, mask: Some(mask)}
+    }
+
+    /// share of the second material at the hit
+    fn amount(&self, rec: &HitPoint) -> f32 {
+        let mask = match &self.mask {
+            Some(mask) => mask.value(&rec.uv, &rec.position).sum() / 3.0,
+            None => 1.0,
+        };
+        (self.weight * mask).clamp(0.0, 1.0)
+    }
+
+    /// both materials with their share
+    fn parts(&self, rec: &HitPoint) -> [(&dyn Material, f32); 2] {
+        let amount = self.amount(rec);
+        [(self.first.as_ref(), 1.0 - amount), (self.second.as_ref(), amount)]
+    }
+}
+
+impl Material for Mix {
+
+    fn sample(&self, ray: &Ray, rec: &HitPoint) -> Option<BsdfSample> {
+        let [first, second] = self.parts(rec);
+        let (material, share) = if rand::thread_rng().gen::<f32>() < second.1 { second } else { first };
+        let sample = material.sample(ray, rec)?;
+        // the share weights the material and is the chance to sample it
+        mixture_sample(self, ray, rec, sample, Vector3f::repeat(share), share)
+    }
+
+    fn eval(&self, ray: &Ray, rec: &HitPoint, direction: &Vector3f) -> Option<Vector3f> {
+        sum_evals(self.parts(rec).iter()
+            .map(|(material, share)| material.eval(ray, rec, direction).map(|eval| eval * *share)))
+    }
+
+    fn pdf(&self, ray: &Ray, rec: &HitPoint, direction: &Vector3f) -> f32 {
+        self.parts(rec).iter().map(|(material, share)| share * material.pdf(ray, rec, direction)).sum()
+    }
+
+    fn emitted(&self, ray: &Ray, rec: &HitPoint) -> Vector3f {
+        self.parts(rec).iter().map(|(material, share)| material.emitted(ray, rec) * *share).sum()
+    }
+
+    /// a hole where all the materials it shows are cut out, so that the mask picks the holes of either
+    fn is_cut_out(&self, rec: &HitPoint) -> bool {
+        self.parts(rec).iter().all(|(material, share)| *share <= 0.0 || material.is_cut_out(rec))
+    }
+
+}
+
+/// Clear varnish over a base material, as car paint or varnished wood. The varnish reflects
+/// as a GGX dielectric, the light it lets in and back out reaches the base.
+/// The light bouncing between the base and the varnish is left out
+#[derive(Clone, Debug)]
+pub struct Coated {
+    base: Box<dyn Material>,
+    reflective_index: f32,
+    ggx: Ggx,
+}
+
+impl Coated {
+    /// a roughness of 0 is a smooth varnish
+    pub fn new(base: Box<dyn Material>, reflective_index: f32, roughness: f32) -> Coated {
+        Coated{base, reflective_index: reflective_index.max(1.0), ggx: Ggx::from_roughness(roughness)}
+    }
+
+    /// reflectance of the varnish at the cosine with the normal
+    fn fresnel(&self, cos: f32) -> f32 {
+        fresnel_dielectric(cos.abs(), self.reflective_index)
+    }
+
+    /// fraction of the light going through the varnish to and from the base
+    fn transmittance(&self, ray: &Ray, rec: &HitPoint, direction: &Vector3f) -> f32 {
+        let cos_o = -ray.direction.normalize().dot(&rec.normal);
+        (1.0 - self.fresnel(cos_o)) * (1.0 - self.fresnel(direction.dot(&rec.normal)))
+    }
+
+    /// chance to sample the varnish rather than the base, its reflectance towards the ray
+    fn coat_probability(&self, ray: &Ray, rec: &HitPoint) -> f32 {
+        self.fresnel(ray.direction.normalize().dot(&rec.normal)).clamp(0.1, 0.9)
+    }
+
+    fn coat_fresnel(&self) -> impl Fn(f32) -> Vector3f + '_ {
+        move |cos| Vector3f::repeat(self.fresnel(cos))
+    }
+}
+
+impl Material for Coated {
+
+    fn sample(&self, ray: &Ray, rec: &HitPoint) -> Option<BsdfSample> {
+        let coat_probability = self.coat_probability(ray, rec);
+        if rand::thread_rng().gen::<f32>() < coat_probability {
+            let sample = sample_reflection(&self.ggx, ray, rec, self.coat_fresnel())?;
+            mixture_sample(self, ray, rec, sample, Vector3f::repeat(1.0), coat_probability)
+        } else {
+            let sample = self.base.sample(ray, rec)?;
+            let transmittance = self.transmittance(ray, rec, &sample.direction);
+            mixture_sample(self, ray, rec, sample, Vector3f::repeat(transmittance), 1.0 - coat_probability)
+        }
+    }
+
+    fn eval(&self, ray: &Ray, rec: &HitPoint, direction: &Vector3f) -> Option<Vector3f> {
+        let base = self.base.eval(ray, rec, direction)
+            .map(|eval| eval * self.transmittance(ray, rec, direction));
+        sum_evals([eval_reflection(&self.ggx, ray, rec, direction, self.coat_fresnel()), base].iter().copied())
+    }
+
+    fn pdf(&self, ray: &Ray, rec: &HitPoint, direction: &Vector3f) -> f32 {
+        let coat_probability = self.coat_probability(ray, rec);
+        coat_probability * reflection_pdf(&self.ggx, ray, rec, direction)
+            + (1.0 - coat_probability) * self.base.pdf(ray, rec, direction)
+    }
+
+    fn emitted(&self, ray: &Ray, rec: &HitPoint) -> Vector3f {
+        self.base.emitted(ray, rec) * (1.0 - self.fresnel(ray.direction.normalize().dot(&rec.normal)))
+    }
+
+    fn is_cut_out(&self, rec: &HitPoint) -> bool {
+        self.base.is_cut_out(rec)
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::material::{Dielectric, Lambertian, Metal};
+    use crate::material::texture::Checker;
+    use crate::types::Vector2f;
+
+    /// mean weight of the samples, checking that the non specular ones are eval / pdf
+    fn check_sampling(material: &dyn Material, ray: &Ray, rec: &HitPoint) -> Vector3f {
+        let samples = 20000;
+        let mut total = Vector3f::zeros();
+        for _ in 0..samples {
+            if let Some(sample) = material.sample(ray, rec) {
+                total += sample.weight;
+                if sample.specular {
+                    continue;
+                }
+                let eval = material.eval(ray, rec, &sample.direction).unwrap();
+                let pdf = material.pdf(ray, rec, &sample.direction);
+                assert!((pdf - sample.pdf).abs() <= 1e-3 * pdf, "{} {}", pdf, sample.pdf);
+                assert!((eval / pdf - sample.weight).norm() < 1e-3 * sample.weight.norm().max(1.0));
+            }
+        }
+        total / samples as f32
+    }
+
+    #[test]
+    fn mixes_and_coats_weigh_their_parts() {
+        let rec = HitPoint {
+            t: 1.0,
+            position: Vector3f::zeros(),
+            normal: Vector3f::new(0.0, 1.0, 0.0),
+            front_face: true,
+            uv: Vector2f::new(0.25, 0.25),
+            dpdu: Vector3f::new(1.0, 0.0, 0.0),
+            dpdv: Vector3f::new(0.0, 0.0, -1.0),
+        };
+        let ray = Ray { origin: Vector3f::new(-1.0, 2.0, 0.0), direction: Vector3f::new(1.0, -2.0, 0.0), time: 0.0 };
+        let red = || Box::new(Lambertian::new(Vector3f::new(0.8, 0.0, 0.0)));
+        let blue = || Box::new(Lambertian::new(Vector3f::new(0.0, 0.0, 0.8)));
+
+        // the blend of two diffuse materials is the blend of their albedos
+        let mix = Mix::new(red(), blue(), 0.25);
+        let albedo = check_sampling(&mix, &ray, &rec);
+        assert!((albedo - Vector3f::new(0.6, 0.0, 0.2)).norm() < 1e-3, "{:?}", albedo);
+        // the mask is 1 at this uv, 0 on the next square
+        let masked = Mix::masked(red(), blue(), 1.0, Box::new(Checker::new(Vector3f::repeat(1.0), Vector3f::zeros(), 1.0)));
+        assert!((check_sampling(&masked, &ray, &rec) - Vector3f::new(0.0, 0.0, 0.8)).norm() < 1e-3);
+        // mirrors and glass keep their specular samples, the glass keeps all the light and the mirror its albedo
+        let glass_metal = Mix::new(Box::new(Dielectric::new(1.5)), Box::new(Metal::new(Vector3f::repeat(0.5), 0.0)), 0.5);
+        assert!(glass_metal.eval(&ray, &rec, &rec.normal).is_none());
+        assert!((check_sampling(&glass_metal, &ray, &rec).x - 0.75).abs() < 0.02);
+
+        // a varnish over white loses nothing but the light reflected between the layers:
+        // what it reflects is more than it keeps from the base
+        let white = || Box::new(Lambertian::new(Vector3f::repeat(1.0)));
+        for roughness in [0.0, 0.3] {
+            let varnished = check_sampling(&Coated::new(white(), 1.5, roughness), &ray, &rec);
+            assert!(varnished.x > 0.85 && varnished.x <= 1.0, "{:?}", varnished);
+        }
+        let coated = Coated::new(white(), 1.5, 0.0);
+        let grazing = Ray { origin: Vector3f::new(-1.0, 0.1, 0.0), direction: Vector3f::new(1.0, -0.1, 0.0), time: 0.0 };
+        assert!(coated.coat_probability(&grazing, &rec) > coated.coat_probability(&ray, &rec));
+    }
+}
@@ -1 +1,7 @@
-pub mod material;
\ No newline at end of file
+pub mod cutout;
+pub mod material;
+pub mod microfacet;
+pub mod mix;
+pub mod normal_map;
+pub mod principled;
+pub mod texture;
\ No newline at end of file
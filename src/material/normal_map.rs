@@ -0,0 +1,174 @@
+use crate::raycasting::ray::{HitPoint, Ray};
+use crate::types::{Vector2f, Vector3f};
+use crate::geom::rand_geom::orthonormal_basis;
+use crate::material::material::{BsdfSample, Material};
+use crate::material::texture::Texture;
+
+/// step in uv of the finite differences of bump maps
+const BUMP_DELTA: f32 = 1e-3;
+
+/// How the shading normal is bent away from the geometric one
+#[derive(Clone, Debug)]
+enum Perturbation {
+    /// rgb of the texture mapped from [0, 1] to [-1, 1] in the tangent frame: x along u,
+    /// y along v and z along the normal, so that (0.5, 0.5, 1) keeps the normal
+    NormalMap(Box<dyn Texture>),
+    /// the surface moved along the normal by scale times the mean of the texture
+    Bump { height: Box<dyn Texture>, scale: f32 },
+}
+
+/// A base material shaded with the normal of a normal or bump map. Only the shading
+/// normal changes: the hit keeps the geometric one, rays leave from the geometric surface
+/// and directions the two normals do not agree on, above one and below the other, are black
+#[derive(Clone, Debug)]
+pub struct NormalMapped {
+    base: Box<dyn Material>,
+    perturbation: Perturbation,
+}
+
+impl NormalMapped {
+
+    /// tangent space normal map, as baked for meshes
+    pub fn normal_map(base: Box<dyn Material>, normals: Box<dyn Texture>) -> NormalMapped {
+        NormalMapped{base, perturbation: Perturbation::NormalMap(normals)}
+    }
+
+    /// grayscale height map, scale is the height in scene units of a texture value of 1
+    pub fn bump_map(base: Box<dyn Material>, height: Box<dyn Texture>, scale: f32) -> NormalMapped {
+        NormalMapped{base, perturbation: Perturbation::Bump{height, scale}}
+    }
+
+    /// the perturbed normal on the side of the normal of the hit, None if it is degenerate
+    fn shading_normal(&self, rec: &HitPoint) -> Option<Vector3f> {
+        let normal = rec.normal;
+        let shading_normal = match &self.perturbation {
+            Perturbation::NormalMap(normals) => {
+                let tangent = (rec.dpdu - normal * normal.dot(&rec.dpdu)).try_normalize(1e-9)
+                    .unwrap_or_else(|| orthonormal_basis(&normal).0);
+                // v may run either way around the normal, as on back faces
+                let bitangent = normal.cross(&tangent);
+                let bitangent = if bitangent.dot(&rec.dpdv) < 0.0 { -bitangent } else { bitangent };
+                let local = normals.value(&rec.uv, &rec.position) * 2.0 - Vector3f::repeat(1.0);
+                tangent * local.x + bitangent * local.y + normal * local.z
+            },
+            Perturbation::Bump{height, scale} => {
+                let height_at = |du: f32, dv: f32| {
+                    let uv = rec.uv + Vector2f::new(du, dv);
+                    let position = rec.position + rec.dpdu * du + rec.dpdv * dv;
+                    height.value(&uv, &position).sum() / 3.0
+                };
+                let center = height_at(0.0, 0.0);
+                let dhdu = (height_at(BUMP_DELTA, 0.0) - center) / BUMP_DELTA;
+                let dhdv = (height_at(0.0, BUMP_DELTA) - center) / BUMP_DELTA;
+                // derivatives of the displaced surface, leaving out how the normal turns
+                let dpdu = rec.dpdu + normal * (scale * dhdu);
+                let dpdv = rec.dpdv + normal * (scale * dhdv);
+                let cross = dpdu.cross(&dpdv);
+                if rec.dpdu.cross(&rec.dpdv).dot(&normal) < 0.0 { -cross } else { cross }
+            },
+        };
+        shading_normal.try_normalize(1e-9).filter(|shading_normal| shading_normal.dot(&normal) > 0.0)
+    }
+
+    /// the hit with the shading normal, or as it is when the ray would see the back of the shading normal
+    fn shaded(&self, ray: &Ray, rec: &HitPoint) -> HitPoint {
+        let normal = match self.shading_normal(rec) {
+            Some(shading_normal) if shading_normal.dot(&ray.direction) < 0.0 => shading_normal,
+            _ => rec.normal,
+        };
+        HitPoint {
+            t: rec.t,
+            position: rec.position,
+            normal,
+            front_face: rec.front_face,
+            uv: rec.uv,
+            dpdu: rec.dpdu,
+            dpdv: rec.dpdv,
+        }
+    }
+
+}
+
+/// whether the direction is on the same side of both normals
+fn same_side(rec: &HitPoint, shaded: &HitPoint, direction: &Vector3f) -> bool {
+    (rec.normal.dot(direction) > 0.0) == (shaded.normal.dot(direction) > 0.0)
+}
+
+impl Material for NormalMapped {
+
+    fn sample(&self, ray: &Ray, rec: &HitPoint) -> Option<BsdfSample> {
+        let shaded = self.shaded(ray, rec);
+        let sample = self.base.sample(ray, &shaded)?;
+        if same_side(rec, &shaded, &sample.direction) { Some(sample) } else { None }
+    }
+
+    fn eval(&self, ray: &Ray, rec: &HitPoint, direction: &Vector3f) -> Option<Vector3f> {
+        let shaded = self.shaded(ray, rec);
+        let eval = self.base.eval(ray, &shaded, direction)?;
+        Some(if same_side(rec, &shaded, direction) { eval } else { Vector3f::zeros() })
+    }
+
+    /// the density of the base, the samples on the wrong side are dropped, not redrawn
+    fn pdf(&self, ray: &Ray, rec: &HitPoint, direction: &Vector3f) -> f32 {
+        self.base.pdf(ray, &self.shaded(ray, rec), direction)
+    }
+
+    fn emitted(&self, ray: &Ray, rec: &HitPoint) -> Vector3f {
+        self.base.emitted(ray, &self.shaded(ray, rec))
+    }
+
+    fn is_cut_out(&self, rec: &HitPoint) -> bool {
+        self.base.is_cut_out(rec)
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::material::Lambertian;
+    use crate::material::texture::{ImageTexture, SolidColor};
+
+    #[test]
+    fn maps_tilt_the_shading_normal_only() {
+        let rec = HitPoint {
+            t: 1.0,
+            position: Vector3f::zeros(),
+            normal: Vector3f::new(0.0, 1.0, 0.0),
+            front_face: true,
+            uv: Vector2f::new(0.5, 0.5),
+            dpdu: Vector3f::new(2.0, 0.0, 0.0),
+            dpdv: Vector3f::new(0.0, 0.0, -2.0),
+        };
+        let ray = Ray { origin: Vector3f::new(0.0, 1.0, 0.0), direction: Vector3f::new(0.0, -1.0, 0.0), time: 0.0 };
+        let white = || Box::new(Lambertian::new(Vector3f::repeat(1.0)));
+
+        // a flat normal map changes nothing
+        let flat = NormalMapped::normal_map(white(), Box::new(SolidColor::new(Vector3f::new(0.5, 0.5, 1.0))));
+        assert!((flat.shading_normal(&rec).unwrap() - rec.normal).norm() < 1e-6);
+        // x of the map leans the normal along u, y along v
+        let leaning = Vector3f::new(0.5 + 0.5 * 0.6, 0.5 + 0.5 * 0.0, 0.5 + 0.5 * 0.8);
+        let tilted = NormalMapped::normal_map(white(), Box::new(SolidColor::new(leaning)));
+        assert!((tilted.shading_normal(&rec).unwrap() - Vector3f::new(0.6, 0.8, 0.0)).norm() < 1e-5);
+        let shaded = tilted.shaded(&ray, &rec);
+        assert_eq!(shaded.position, rec.position);
+        // the cosine is with the shading normal, directions below the surface are black
+        let up = Vector3f::new(0.0, 1.0, 0.0);
+        assert!((tilted.eval(&ray, &rec, &up).unwrap().x - 0.8 / std::f32::consts::PI).abs() < 1e-5);
+        let under = Vector3f::new(0.8, -0.2, 0.0).normalize();
+        assert_eq!(tilted.eval(&ray, &rec, &under), Some(Vector3f::zeros()));
+        for _ in 0..1000 {
+            if let Some(sample) = tilted.sample(&ray, &rec) {
+                assert!(sample.direction.dot(&rec.normal) > 0.0);
+            }
+        }
+
+        // a height ramp going up along u leans the normal back towards -u: the height
+        // grows by 1 per uv unit, scaled by 2 over a u edge of 2 it is a 45 degrees slope
+        let ramp = (0..16).map(|x| Vector3f::repeat(x as f32 / 16.0)).collect();
+        let bumps = NormalMapped::bump_map(white(), Box::new(ImageTexture::new(16, 1, ramp)), 2.0);
+        let normal = bumps.shading_normal(&rec).unwrap();
+        assert!((normal - Vector3f::new(-1.0, 1.0, 0.0).normalize()).norm() < 1e-2, "{:?}", normal);
+    }
+}
@@ -0,0 +1,283 @@
+use crate::raycasting::ray::{HitPoint, Ray};
+use crate::types::{luminance, Vector3f};
+use crate::geom::rand_geom::random_unit_vector;
+use crate::material::material::{BsdfSample, Material};
+use crate::material::microfacet::{RoughDielectric, RoughMetal};
+use crate::material::mix::{mixture_sample, sum_evals};
+use rand::Rng;
+use std::f32::consts::PI;
+
+/// Parameters of the principled material, all in [0, 1] but the index of refraction
+#[derive(Clone, Debug, PartialEq)]
+pub struct PrincipledParameters {
+    /// diffuse albedo, or reflectance of metals, or tint of the transmitted light
+    pub base_color: Vector3f,
+    /// blend from a dielectric to a metal
+    pub metallic: f32,
+    /// of the specular reflection and the transmission
+    pub roughness: f32,
+    /// reflectance of dielectrics, 0.5 is the 4% of most of them
+    pub specular: f32,
+    /// strength of a smooth white varnish over the rest
+    pub clearcoat: f32,
+    pub clearcoat_roughness: f32,
+    /// strength of the grazing reflection of cloth
+    pub sheen: f32,
+    /// blend of the sheen from white to the base color
+    pub sheen_tint: f32,
+    /// blend from opaque to glass, for dielectrics
+    pub transmission: f32,
+    /// index of refraction of the transmission
+    pub ior: f32,
+}
+
+impl Default for PrincipledParameters {
+    /// a grey rough plastic
+    fn default() -> Self {
+        PrincipledParameters {
+            base_color: Vector3f::repeat(0.8),
+            metallic: 0.0,
+            roughness: 0.5,
+            specular: 0.5,
+            clearcoat: 0.0,
+            clearcoat_roughness: 0.1,
+            sheen: 0.0,
+            sheen_tint: 0.5,
+            transmission: 0.0,
+            ior: 1.5,
+        }
+    }
+}
+
+/// cosine of the angle between direction and the normal at the hit, and between the
+/// direction and the half vector with the one to the origin of the ray
+fn diffuse_cosines(ray: &Ray, rec: &HitPoint, direction: &Vector3f) -> Option<(f32, f32, f32)> {
+    let to_origin = -ray.direction.normalize();
+    let (cos_i, cos_o) = (rec.normal.dot(direction), rec.normal.dot(&to_origin));
+    if cos_i <= 0.0 || cos_o <= 0.0 {
+        return None;
+    }
+    let half = (direction + to_origin).normalize();
+    Some((cos_i, cos_o, direction.dot(&half)))
+}
+
+/// the normal plus a uniform unit vector follows the cosine distribution, as for `Lambertian`
+fn sample_cosine(material: &dyn Material, ray: &Ray, rec: &HitPoint) -> Option<BsdfSample> {
+    let direction = (rec.normal + random_unit_vector()).try_normalize(1e-6)?;
+    let pdf = material.pdf(ray, rec, &direction);
+    if pdf <= 0.0 {
+        return None;
+    }
+    let eval = material.eval(ray, rec, &direction)?;
+    Some(BsdfSample { direction, weight: eval / pdf, pdf, specular: false })
+}
+
+fn cosine_pdf(rec: &HitPoint, direction: &Vector3f) -> f32 {
+    rec.normal.dot(direction).max(0.0) / PI
+}
+
+/// Burley diffuse, Lambertian but darker at grazing angles for smooth surfaces and brighter
+/// for rough ones, as the retroreflection of rough surfaces
+#[derive(Clone, Debug)]
+pub struct BurleyDiffuse {
+    pub albedo: Vector3f,
+    pub roughness: f32,
+}
+
+impl Material for BurleyDiffuse {
+
+    fn sample(&self, ray: &Ray, rec: &HitPoint) -> Option<BsdfSample> {
+        sample_cosine(self, ray, rec)
+    }
+
+    fn eval(&self, ray: &Ray, rec: &HitPoint, direction: &Vector3f) -> Option<Vector3f> {
+        let (cos_i, cos_o, cos_d) = match diffuse_cosines(ray, rec, direction) {
+            Some(cosines) => cosines,
+            None => return Some(Vector3f::zeros()),
+        };
+        let grazing = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+        let fresnel = |cos: f32| 1.0 + (grazing - 1.0) * (1.0 - cos).powi(5);
+        Some(self.albedo * (fresnel(cos_i) * fresnel(cos_o) * cos_i / PI))
+    }
+
+    fn pdf(&self, _ray: &Ray, rec: &HitPoint, direction: &Vector3f) -> f32 {
+        cosine_pdf(rec, direction)
+    }
+
+}
+
+/// Grazing reflection of the fibers of cloth
+#[derive(Clone, Debug)]
+pub struct Sheen {
+    pub color: Vector3f,
+}
+
+impl Material for Sheen {
+
+    fn sample(&self, ray: &Ray, rec: &HitPoint) -> Option<BsdfSample> {
+        sample_cosine(self, ray, rec)
+    }
+
+    fn eval(&self, ray: &Ray, rec: &HitPoint, direction: &Vector3f) -> Option<Vector3f> {
+        match diffuse_cosines(ray, rec, direction) {
+            Some((cos_i, _, cos_d)) => Some(self.color * ((1.0 - cos_d).powi(5) * cos_i)),
+            None => Some(Vector3f::zeros()),
+        }
+    }
+
+    fn pdf(&self, _ray: &Ray, rec: &HitPoint, direction: &Vector3f) -> f32 {
+        cosine_pdf(rec, direction)
+    }
+
+}
+
+/// One of the BSDFs the principled material adds up
+#[derive(Clone, Debug)]
+struct Lobe {
+    material: Box<dyn Material>,
+    /// multiplies the BSDF of the material
+    weight: Vector3f,
+    /// chance of sampling the lobe
+    probability: f32,
+}
+
+/// Disney style uber material, the sum of a diffuse, a sheen, a specular reflection, a
+/// clearcoat and a transmission lobe weighted by the parameters. A lobe is sampled in
+/// proportion to a guess of the light it reflects, the other ones weight the sample
+#[derive(Clone, Debug)]
+pub struct Principled {
+    parameters: PrincipledParameters,
+    lobes: Vec<Lobe>,
+}
+
+impl Principled {
+
+    pub fn new(parameters: PrincipledParameters) -> Principled {
+        let p = &parameters;
+        let base_color = p.base_color;
+        let white = Vector3f::repeat(1.0);
+        let dielectric = (1.0 - p.metallic) * (1.0 - p.transmission);
+        let glass = (1.0 - p.metallic) * p.transmission;
+        let sheen_color = white.lerp(&base_color, p.sheen_tint) * p.sheen;
+        // specular 0.5 reflects 4% at normal incidence, metals reflect their color
+        let specular_color = (white * (0.08 * p.specular)).lerp(&base_color, p.metallic);
+
+        let candidates : Vec<(Box<dyn Material>, Vector3f, f32)> = vec![
+            (Box::new(BurleyDiffuse { albedo: base_color, roughness: p.roughness }),
+                Vector3f::repeat(dielectric), dielectric * luminance(&base_color)),
+            (Box::new(Sheen { color: sheen_color }),
+                Vector3f::repeat(dielectric), dielectric * 0.1 * luminance(&sheen_color)),
+            (Box::new(RoughMetal::new(specular_color, p.roughness)),
+                Vector3f::repeat(1.0 - glass), (1.0 - glass) * (0.2 + 0.8 * luminance(&specular_color))),
+            (Box::new(RoughMetal::new(Vector3f::repeat(0.04), p.clearcoat_roughness)),
+                Vector3f::repeat(p.clearcoat), 0.25 * p.clearcoat),
+            (Box::new(RoughDielectric::new(p.ior.max(1.0), p.roughness)), base_color * glass, glass),
+        ];
+        let mut lobes : Vec<Lobe> = candidates.into_iter()
+            .filter(|(_, weight, probability)| weight.max() > 0.0 && *probability > 0.0)
+            .map(|(material, weight, probability)| Lobe { material, weight, probability })
+            .collect();
+        let total : f32 = lobes.iter().map(|lobe| lobe.probability).sum();
+        for lobe in &mut lobes {
+            lobe.probability /= total;
+        }
+        Principled { parameters, lobes }
+    }
+
+    pub fn parameters(&self) -> &PrincipledParameters {
+        &self.parameters
+    }
+
+}
+
+impl Default for Principled {
+    fn default() -> Self {
+        Principled::new(PrincipledParameters::default())
+    }
+}
+
+impl Material for Principled {
+
+    fn sample(&self, ray: &Ray, rec: &HitPoint) -> Option<BsdfSample> {
+        let mut choice = rand::thread_rng().gen::<f32>();
+        let lobe = self.lobes.iter()
+            .find(|lobe| { choice -= lobe.probability; choice < 0.0 })
+            .or_else(|| self.lobes.last())?;
+        let sample = lobe.material.sample(ray, rec)?;
+        mixture_sample(self, ray, rec, sample, lobe.weight, lobe.probability)
+    }
+
+    fn eval(&self, ray: &Ray, rec: &HitPoint, direction: &Vector3f) -> Option<Vector3f> {
+        sum_evals(self.lobes.iter()
+            .map(|lobe| lobe.material.eval(ray, rec, direction).map(|eval| eval.component_mul(&lobe.weight))))
+    }
+
+    fn pdf(&self, ray: &Ray, rec: &HitPoint, direction: &Vector3f) -> f32 {
+        self.lobes.iter().map(|lobe| lobe.probability * lobe.material.pdf(ray, rec, direction)).sum()
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Vector2f;
+
+    /// mean weight of the samples, checking that the non specular ones are eval / pdf
+    fn check_sampling(material: &dyn Material, ray: &Ray, rec: &HitPoint) -> Vector3f {
+        let samples = 20000;
+        let mut total = Vector3f::zeros();
+        for _ in 0..samples {
+            if let Some(sample) = material.sample(ray, rec) {
+                total += sample.weight;
+                if sample.specular {
+                    continue;
+                }
+                let eval = material.eval(ray, rec, &sample.direction).unwrap();
+                let pdf = material.pdf(ray, rec, &sample.direction);
+                assert!((pdf - sample.pdf).abs() <= 1e-3 * pdf, "{} {}", pdf, sample.pdf);
+                assert!((eval / pdf - sample.weight).norm() < 1e-3 * sample.weight.norm().max(1.0));
+            }
+        }
+        total / samples as f32
+    }
+
+    #[test]
+    fn lobes_sample_their_eval_and_add_up() {
+        let rec = HitPoint {
+            t: 1.0,
+            position: Vector3f::zeros(),
+            normal: Vector3f::new(0.0, 1.0, 0.0),
+            front_face: true,
+            uv: Vector2f::zeros(),
+            dpdu: Vector3f::new(1.0, 0.0, 0.0),
+            dpdv: Vector3f::new(0.0, 0.0, -1.0),
+        };
+        let ray = Ray { origin: Vector3f::new(-1.0, 2.0, 0.0), direction: Vector3f::new(1.0, -2.0, 0.0), time: 0.0 };
+        let everything = Principled::new(PrincipledParameters {
+            clearcoat: 0.5, sheen: 0.5, transmission: 0.3, metallic: 0.2, ..PrincipledParameters::default()
+        });
+        assert_eq!(everything.lobes.len(), 5);
+        for lobe in &everything.lobes {
+            let albedo = check_sampling(lobe.material.as_ref(), &ray, &rec);
+            assert!(albedo.max() < 1.05, "{:?} {:?}", lobe.material, albedo);
+        }
+        check_sampling(&everything, &ray, &rec);
+
+        // a white metal is the metal
+        let metal = Principled::new(PrincipledParameters {
+            base_color: Vector3f::repeat(1.0), metallic: 1.0, roughness: 0.2, ..PrincipledParameters::default()
+        });
+        assert_eq!(metal.lobes.len(), 1);
+        let albedo = check_sampling(&metal, &ray, &rec);
+        assert!(albedo.min() > 0.97 && albedo.max() <= 1.0, "{:?}", albedo);
+
+        // the default plastic is mostly diffuse, a smooth clearcoat adds a mirror
+        let plastic = check_sampling(&Principled::default(), &ray, &rec);
+        assert!(plastic.x > 0.75 && plastic.x < 0.95, "{:?}", plastic);
+        let coated = Principled::new(PrincipledParameters { clearcoat: 1.0, clearcoat_roughness: 0.0, ..PrincipledParameters::default() });
+        assert!(coated.eval(&ray, &rec, &rec.normal).is_some());
+        assert!(check_sampling(&coated, &ray, &rec).x > plastic.x);
+    }
+}
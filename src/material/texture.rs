@@ -0,0 +1,119 @@
+use crate::types::{Vector2f, Vector3f};
+use dyn_clone::DynClone;
+use std::fmt::Debug;
+use std::io;
+use std::path::Path;
+
+/// Color varying over a surface, looked up by the uv of the hit
+pub trait Texture : Send + Sync + DynClone + Debug {
+    fn value(&self, uv: &Vector2f, position: &Vector3f) -> Vector3f;
+}
+
+dyn_clone::clone_trait_object!(Texture);
+
+#[derive(Clone, Debug)]
+pub struct SolidColor {
+    color: Vector3f
+}
+
+impl SolidColor {
+    pub fn new(color: Vector3f) -> SolidColor {
+        SolidColor{color}
+    }
+}
+
+impl Texture for SolidColor {
+
+    fn value(&self, _uv: &Vector2f, _position: &Vector3f) -> Vector3f {
+        self.color
+    }
+
+}
+
+/// Squares of two colors alternating in uv, scale squares per uv unit
+#[derive(Clone, Debug)]
+pub struct Checker {
+    even: Vector3f,
+    odd: Vector3f,
+    scale: f32,
+}
+
+impl Checker {
+    pub fn new(even: Vector3f, odd: Vector3f, scale: f32) -> Checker {
+        Checker{even, odd, scale}
+    }
+}
+
+impl Texture for Checker {
+
+    fn value(&self, uv: &Vector2f, _position: &Vector3f) -> Vector3f {
+        let square = (uv.x * self.scale).floor() as i64 + (uv.y * self.scale).floor() as i64;
+        if square.rem_euclid(2) == 0 { self.even } else { self.odd }
+    }
+
+}
+
+/// Image file repeated over the uv plane, v going up from the bottom row.
+/// Colors are decoded with the gamma 2 the renders are saved with, data such as
+/// normal and bump maps are read as linear
+#[derive(Clone, Debug)]
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    /// rows from the top
+    pixels: Vec<Vector3f>,
+}
+
+impl ImageTexture {
+
+    /// rgb pixels, rows from the top
+    pub fn new(width: usize, height: usize, pixels: Vec<Vector3f>) -> ImageTexture {
+        assert!(width > 0 && height > 0 && pixels.len() == width * height, "ImageTexture needs a pixel per width x height");
+        ImageTexture{width, height, pixels}
+    }
+
+    /// load a png or jpeg image, in [0, 1]
+    pub fn load(path: &Path, linear: bool) -> io::Result<ImageTexture> {
+        let decode = |channel: u8| {
+            let value = channel as f32 / 255.0;
+            if linear { value } else { value * value }
+        };
+        ImageTexture::load_pixels(path, |rgba| Vector3f::new(decode(rgba[0]), decode(rgba[1]), decode(rgba[2])))
+    }
+
+    /// the alpha channel of a png image as a grey, opaque where the image has none
+    pub fn load_alpha(path: &Path) -> io::Result<ImageTexture> {
+        ImageTexture::load_pixels(path, |rgba| Vector3f::repeat(rgba[3] as f32 / 255.0))
+    }
+
+    fn load_pixels(path: &Path, pixel: impl Fn(&[u8]) -> Vector3f) -> io::Result<ImageTexture> {
+        let image = image_codecs::open(path)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+            .to_rgba();
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        if width == 0 || height == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "empty texture image"));
+        }
+        let pixels = image.pixels().map(|rgba| pixel(&rgba.0)).collect();
+        Ok(ImageTexture::new(width, height, pixels))
+    }
+
+}
+
+impl Texture for ImageTexture {
+
+    /// bilinear between the pixel centers, so that bump maps have no steps
+    fn value(&self, uv: &Vector2f, _position: &Vector3f) -> Vector3f {
+        let x = uv.x * self.width as f32 - 0.5;
+        let y = (1.0 - uv.y) * self.height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let pixel = |dx: i64, dy: i64| {
+            let column = (x0 as i64 + dx).rem_euclid(self.width as i64) as usize;
+            let row = (y0 as i64 + dy).rem_euclid(self.height as i64) as usize;
+            self.pixels[column + row * self.width]
+        };
+        pixel(0, 0).lerp(&pixel(1, 0), fx).lerp(&pixel(0, 1).lerp(&pixel(1, 1), fx), fy)
+    }
+
+}
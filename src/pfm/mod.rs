@@ -0,0 +1,108 @@
+use std::io::prelude::*;
+use std::fs::File;
+use std::path::Path;
+
+/// save a linear rgb image as a color PFM file.
+/// buffer is interleaved rgb with origin at the upper left corner,
+/// PFM stores rows bottom to top. A negative scale marks little endian data
+pub fn save_image_to_pfm(buffer: &[f32], width: i32, height: i32, path: &Path) -> std::io::Result<()> {
+    let mut file = File::create(path)?;
+    write!(file, "PF\n{} {}\n-1.0\n", width, height)?;
+    let mut data = Vec::with_capacity(buffer.len() * 4);
+    for y in (0..height).rev() {
+        let row_start = (y*width*3) as usize;
+        let row_end = row_start + (width*3) as usize;
+        for value in &buffer[row_start..row_end] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    file.write_all(&data)
+}
+
+fn invalid_data(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// read a PFM file as a linear rgb image, in the layout save_image_to_pfm takes:
+/// interleaved rgb, origin at the upper left corner. Grayscale files are expanded to rgb.
+/// Returns the buffer, the width and the height
+pub fn read_image_from_pfm(path: &Path) -> std::io::Result<(Vec<f32>, i32, i32)> {
+    let data = std::fs::read(path)?;
+    // three whitespace separated header lines: the type, the size and the scale
+    let mut fields = Vec::new();
+    let mut position = 0;
+    while fields.len() < 4 {
+        while position < data.len() && data[position].is_ascii_whitespace() {
+            position += 1;
+        }
+        let start = position;
+        while position < data.len() && !data[position].is_ascii_whitespace() {
+            position += 1;
+        }
+        if start == position {
+            return Err(invalid_data("truncated PFM header"));
+        }
+        fields.push(std::str::from_utf8(&data[start..position]).map_err(|_| invalid_data("invalid PFM header"))?);
+    }
+    // a single whitespace ends the header
+    position += 1;
+    let channels = match fields[0] {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => return Err(invalid_data("not a PFM file")),
+    };
+    let width : i32 = fields[1].parse().map_err(|_| invalid_data("invalid PFM width"))?;
+    let height : i32 = fields[2].parse().map_err(|_| invalid_data("invalid PFM height"))?;
+    let scale : f32 = fields[3].parse().map_err(|_| invalid_data("invalid PFM scale"))?;
+    if width <= 0 || height <= 0 {
+        return Err(invalid_data("invalid PFM size"));
+    }
+    let value_count = (width * height) as usize * channels;
+    let values = data.get(position..position + value_count * 4).ok_or_else(|| invalid_data("truncated PFM data"))?;
+    let values : Vec<f32> = values.chunks_exact(4)
+        .map(|bytes| {
+            let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+            if scale < 0.0 { f32::from_le_bytes(bytes) } else { f32::from_be_bytes(bytes) }
+        })
+        .collect();
+
+    let row_length = width as usize * channels;
+    let mut buffer = Vec::with_capacity((width * height * 3) as usize);
+    for row in values.chunks_exact(row_length).rev() {
+        for pixel in row.chunks_exact(channels) {
+            buffer.extend((0..3).map(|channel| pixel[channel % channels]));
+        }
+    }
+    Ok((buffer, width, height))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_save_works() {
+        let bufvec = vec![
+            1.0, 0.0, 0.0,   0.0, 2.0, 0.0,
+            0.0, 0.0, 0.5,   0.25, 0.25, 0.25,
+        ];
+        let path = std::env::temp_dir().join("output.pfm");
+
+        save_image_to_pfm(bufvec.as_slice(), 2, 2, &path).unwrap();
+        let written_data = std::fs::read(&path).unwrap();
+        let header = b"PF\n2 2\n-1.0\n";
+        assert_eq!(&written_data[..header.len()], header);
+
+        let values : Vec<f32> = written_data[header.len()..].chunks(4)
+            .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect();
+        // bottom row first
+        assert_eq!(values, vec![
+            0.0, 0.0, 0.5,   0.25, 0.25, 0.25,
+            1.0, 0.0, 0.0,   0.0, 2.0, 0.0,
+        ]);
+
+        assert_eq!(read_image_from_pfm(&path).unwrap(), (bufvec, 2, 2));
+    }
+}
@@ -8,11 +8,11 @@ pub fn save_image_to_ppm(buffer: &[u8], width: i32, height: i32, fname: &str) {
     for y in 0..height {
         for x in 0..width {
             let i : usize = (y*width+x) as usize;
-            let r : u8 = buffer[0*image_size+i];
-            let g : u8 = buffer[1*image_size+i];
+            let r : u8 = buffer[i];
+            let g : u8 = buffer[image_size+i];
             let b : u8 = buffer[2*image_size+i];
 
-            write!(file, "{} {} {}\n", r, g, b).unwrap();
+            writeln!(file, "{} {} {}", r, g, b).unwrap();
         }
     }
 }
@@ -1,4 +1,5 @@
 use nalgebra::Vector3;
+use crate::types::Vector2f;
 
 type Vector3f = Vector3<f32>;
 
@@ -6,6 +7,8 @@ type Vector3f = Vector3<f32>;
 pub struct Ray {
     pub origin: Vector3f,
     pub direction: Vector3f,
+    /// instant at which the ray exists, within the camera shutter interval
+    pub time: f32,
 }
 
 impl Ray {
@@ -14,9 +17,88 @@ impl Ray {
     }
 }
 
+#[derive(Debug)]
 pub struct HitPoint {
+    /// ray parameter of the hit, position == ray.at(t)
+    pub t: f32,
     pub position: Vector3f,
     /// normalized vector
     pub normal: Vector3f,
-    pub front_face: bool
+    pub front_face: bool,
+    /// surface coordinates of the hit, in [0, 1] for bounded shapes
+    pub uv: Vector2f,
+    /// derivatives of the position with u and v, tangent to the surface and not normalized.
+    /// Zero where the uv are degenerate, as at the poles of a sphere
+    pub dpdu: Vector3f,
+    pub dpdv: Vector3f,
+}
+
+/// A point where a ray crosses the surface of a solid.
+/// Unbounded solids have crossings at infinite t, with a zero normal
+#[derive(Clone, Debug)]
+pub struct SurfaceCrossing {
+    pub t: f32,
+    pub position: Vector3f,
+    /// normalized, pointing out of the solid
+    pub outward_normal: Vector3f,
+    pub uv: Vector2f,
+    pub dpdu: Vector3f,
+    pub dpdv: Vector3f,
+}
+
+/// The part of a ray inside a solid, from where it enters to where it exits
+#[derive(Clone, Debug)]
+pub struct RayInterval {
+    pub enter: SurfaceCrossing,
+    pub exit: SurfaceCrossing,
+}
+
+impl SurfaceCrossing {
+
+    pub fn at_infinity(t: f32) -> SurfaceCrossing {
+        SurfaceCrossing {
+            t,
+            position: Vector3f::repeat(t),
+            outward_normal: Vector3f::zeros(),
+            uv: Vector2f::zeros(),
+            dpdu: Vector3f::zeros(),
+            dpdv: Vector3f::zeros(),
+        }
+    }
+
+    /// the hit with the ray that crossed the surface, normal facing the ray
+    pub fn to_hitpoint(&self, ray: &Ray) -> HitPoint {
+        let is_front_face = self.outward_normal.dot(&ray.direction) < 0.0;
+        HitPoint {
+            t: self.t,
+            position: self.position,
+            normal: if is_front_face { self.outward_normal } else { -self.outward_normal },
+            front_face: is_front_face,
+            uv: self.uv,
+            dpdu: self.dpdu,
+            dpdv: self.dpdv,
+        }
+    }
+
+    /// the same crossing on the surface of the complement of the solid
+    pub fn flipped(&self) -> SurfaceCrossing {
+        SurfaceCrossing { outward_normal: -self.outward_normal, ..self.clone() }
+    }
+
+}
+
+impl HitPoint {
+
+    /// the crossing of the surface, normal pointing out of the solid
+    pub fn to_crossing(&self) -> SurfaceCrossing {
+        SurfaceCrossing {
+            t: self.t,
+            position: self.position,
+            outward_normal: if self.front_face { self.normal } else { -self.normal },
+            uv: self.uv,
+            dpdu: self.dpdu,
+            dpdv: self.dpdv,
+        }
+    }
+
 }
@@ -0,0 +1,50 @@
+use crate::image::float_image::FloatImage;
+
+/// Progressive refinement: averages the passes rendered while the scene does not change
+pub struct Accumulator {
+    sum: Option<FloatImage>,
+    passes: u32,
+}
+
+impl Default for Accumulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Accumulator {
+
+    pub fn new() -> Accumulator {
+        Accumulator {
+            sum: None,
+            passes: 0
+        }
+    }
+
+    /// drop the accumulated passes, e.g. after the camera moved
+    pub fn reset(&mut self) {
+        self.sum = None;
+        self.passes = 0;
+    }
+
+    pub fn add_pass(&mut self, pass: &FloatImage) {
+        match &mut self.sum {
+            Some(sum) if sum.size == pass.size => sum.add(pass),
+            _ => {
+                self.sum = Some(pass.clone());
+                self.passes = 0;
+            }
+        }
+        self.passes += 1;
+    }
+
+    pub fn passes(&self) -> u32 {
+        self.passes
+    }
+
+    /// the average of the accumulated passes, None if no pass was added yet
+    pub fn frame(&self) -> Option<FloatImage> {
+        self.sum.as_ref().map(|sum| sum.scaled(1.0 / self.passes as f32))
+    }
+
+}
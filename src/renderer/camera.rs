@@ -8,6 +8,10 @@ use rand::Rng;
 pub struct Camera {
     pub origin: Vector3f,
     pub viewport: Viewport,
+    /// rays are sampled at times between shutter_open and shutter_close,
+    /// objects moving in between are blurred
+    pub shutter_open: f32,
+    pub shutter_close: f32,
 }
 
 impl Camera {
@@ -18,7 +22,9 @@ impl Camera {
         let viewport = Viewport::new_by_width(aspect_ratio, width);
         Camera {
             origin: Vector3f::new(0.0f32, 0.0f32, 0f32),
-            viewport: viewport,
+            viewport,
+            shutter_open: 0.0,
+            shutter_close: 1.0,
         }
     }
 
@@ -30,10 +36,23 @@ impl Camera {
         self.get_ray_from_image_yx(xy.y as f32, xy.x as f32)
     }
 
+    /// ray through the pixel center at a random time of the shutter interval
+    pub fn get_timed_ray_from_image_xy(&self, xy: Vector2i) -> Ray {
+        let mut ray = self.get_ray_from_image_xy(xy);
+        ray.time = self.sample_time();
+        ray
+    }
+
     pub fn get_random_ray_from_image_xy(&self, xy: Vector2i) -> Ray {
         let mut rng = rand::thread_rng();
-        self.get_ray_from_image_yx(xy.y as f32 + rng.gen::<f32>() - 0.5f32,
-                                   xy.x as f32 + rng.gen::<f32>() - 0.5f32)
+        let mut ray = self.get_ray_from_image_yx(xy.y as f32 + rng.gen::<f32>() - 0.5f32,
+                                                 xy.x as f32 + rng.gen::<f32>() - 0.5f32);
+        ray.time = self.sample_time();
+        ray
+    }
+
+    fn sample_time(&self) -> f32 {
+        self.shutter_open + rand::thread_rng().gen::<f32>() * (self.shutter_close - self.shutter_open)
     }
 
     /// get a ray from a pixel in the image (between (0,0) and (image_y, image_x))
@@ -41,6 +60,7 @@ impl Camera {
     /// y from image (0, image_y) is mapped from  to (viewport_height/2, -viewport_height/2)
     /// origin at top left
     /// ray exit at camera -z
+    /// the ray is at the opening of the shutter
     pub fn get_ray_from_image_yx(&self, image_y: f32, image_x: f32) -> Ray {
         let image_v = self.viewport.to_image_v(image_y);
         let image_u = self.viewport.to_image_u(image_x);
@@ -49,7 +69,8 @@ impl Camera {
             + self.front() * self.focal_length();
         Ray {
             origin: self.origin,
-            direction: direction,
+            direction,
+            time: self.shutter_open,
         }
     }
 
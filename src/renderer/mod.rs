@@ -1,3 +1,6 @@
 pub mod renderer;
 pub mod viewport;
-pub mod camera;
\ No newline at end of file
+pub mod camera;
+pub mod stats;
+pub mod settings;
+pub mod accumulator;
\ No newline at end of file
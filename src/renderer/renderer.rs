@@ -1,117 +1,280 @@
-use crate::geom::hittable::Hittable;
+use crate::image::float_image::FloatImage;
 use crate::image::image::Image;
-use crate::raycasting::ray::Ray;
+use crate::light::light::Light;
+use crate::material::material::Material;
+use crate::raycasting::ray::{HitPoint, Ray};
 use crate::renderer::camera::Camera;
-use crate::types::{normal3f_to_rgb8, vector3f_to_rgb8, rgb8_to_vector3f};
-use crate::types::{Rgb8, Size2i, Vector2i, Vector3f, scale_rgb8, PointwiseSqrtExt };
-use rand::Rng;
-use std::boxed::Box;
+use crate::scene::scene::Scene;
+use crate::renderer::settings::{Integrator, RendererSettings};
+use crate::renderer::stats::{PathEnd, RenderStats};
+use crate::types::{Size2i, Vector2i, Vector3f};
+use std::thread;
+use std::time::Instant;
 use std::vec::Vec;
 
-pub struct RendererSettings {
-    antialiasing_on: bool,
-    antialiasing_samples: u32,
-    max_depth: u32
-}
-
 pub struct Renderer {
     settings: RendererSettings,
 }
 
+/// How the emission of the surfaces of lights and of the sampled environment counts when a ray reaches them
+#[derive(Copy, Clone, Debug)]
+enum LightSurfaces {
+    /// in full, the lights were not sampled at the previous hit
+    Counted,
+    /// not at all, sampling the lights at the previous hit already gathered it
+    Skipped,
+    /// weighted against sampling the lights at the previous hit, where the material
+    /// chose the ray with this density
+    Weighted { bsdf_pdf: f32 },
+}
+
+impl LightSurfaces {
+
+    /// weight of the light reached by the ray, if it is the one of a light
+    fn weight(self, light: Option<&dyn Light>, r: &Ray) -> f32 {
+        match (light, self) {
+            (None, _) | (_, LightSurfaces::Counted) => 1.0,
+            (_, LightSurfaces::Skipped) => 0.0,
+            (Some(light), LightSurfaces::Weighted { bsdf_pdf }) =>
+                power_heuristic(bsdf_pdf, light.pdf(&r.origin, &r.direction.normalize())),
+        }
+    }
+
+}
+
+/// multiple importance sampling weight of the strategy sampling with pdf, against the other one
+fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let (pdf2, other_pdf2) = (pdf * pdf, other_pdf * other_pdf);
+    if pdf2 + other_pdf2 > 0.0 { pdf2 / (pdf2 + other_pdf2) } else { 0.0 }
+}
+
+impl Default for Renderer {
+    fn default() -> Self {
+        Self::new(RendererSettings::default())
+    }
+}
+
 impl Renderer {
-    pub fn new() -> Renderer {
+    pub fn new(settings: RendererSettings) -> Renderer {
         Renderer {
-            settings: RendererSettings {
-                antialiasing_on: true,
-                antialiasing_samples: 50,
-                max_depth: 50
-            },
+            settings
         }
     }
 
-    fn eval_background_color(&self, r: &Ray) -> Vector3f {
-        let unit_direction = r.direction.normalize();
-        let t = 0.5 * (unit_direction.y + 1.0);
-        let color =  (1.0 - t)
-             * Vector3f::new(1.0, 1.0, 1.0) +
-             t * Vector3f::new(0.5, 0.7, 1.0) ;
-        color
+    pub fn settings(&self) -> &RendererSettings {
+        &self.settings
     }
 
-    fn eval_ray_color(&self, r: &Ray, hittables: &Vec<Box<dyn Hittable>>, remaining_depth: u32) -> Vector3f {
-        let mut color = self.eval_background_color(r);
+    fn eval_background_color(&self, r: &Ray, scene: &Scene) -> Vector3f {
+        scene.environment().radiance(&r.direction.normalize())
+    }
 
+    /// light arriving at the hit straight from the lights, through a shadow ray towards a
+    /// sampled point of each. None if the material cannot be evaluated for a given direction.
+    /// With weighted, area lights are weighted against the material sampling their direction
+    fn eval_direct_light(&self, r: &Ray, scene: &Scene, hitpoint: &HitPoint, material: &dyn Material,
+            weighted: bool, stats: &mut RenderStats) -> Option<Vector3f> {
+        material.eval(r, hitpoint, &hitpoint.normal)?;
+        let mut direct = Vector3f::zeros();
+        for light in scene.sampled_lights() {
+            let sample = match light.sample(&hitpoint.position) {
+                Some(sample) if sample.pdf > 0.0 => sample,
+                _ => continue,
+            };
+            let bsdf = material.eval(r, hitpoint, &sample.direction).unwrap_or_else(Vector3f::zeros);
+            if bsdf == Vector3f::zeros() {
+                continue;
+            }
+            // stop short of the light, area lights are hittables themselves
+            let t_max = (sample.distance - 0.001).min(10000.0);
+            let shadow_ray = Ray { origin: hitpoint.position, direction: sample.direction, time: r.time };
+            stats.shadow_rays += 1;
+            let mut transmittance = scene.transmittance_counted(&shadow_ray, 0.001, t_max, &mut stats.intersection_tests);
+            if let Some(fog) = scene.fog() {
+                transmittance *= fog.transmittance(&shadow_ray, sample.distance - 0.001);
+            }
+            if transmittance > 0.0 {
+                let weight = if weighted && !light.is_delta() {
+                    power_heuristic(sample.pdf, material.pdf(r, hitpoint, &sample.direction))
+                } else {
+                    1.0
+                };
+                direct += bsdf.component_mul(&sample.radiance) * (weight * transmittance / sample.pdf);
+            }
+        }
+        Some(direct)
+    }
+
+    /// light_surfaces tells how the lights sampled at the previous hit count when the ray
+    /// reaches their surface, so that their light is not added twice
+    fn eval_ray_color(&self, r: &Ray, scene: &Scene, remaining_depth: u32, light_surfaces: LightSurfaces,
+            stats: &mut RenderStats) -> Vector3f {
+        let depth = self.settings.max_depth() - remaining_depth;
         if remaining_depth == 0 {
-            color = Vector3f::zeros();
-            // color =  (hitpoint.normal + Vector3f::new(1f32, 1f32, 1f32)) * 0.5f32
-        } else {
-            for hittable in hittables {
-                let sphere_hitpoint = hittable.ray_intersaction(r, 0.001, 10000.0);
-                match sphere_hitpoint {
-                    Some(hitpoint) => {
-                        let material = hittable.material().as_ref().unwrap();
-                        match material.scatter(r, &hitpoint)  {
-                            Some((attenuation, scattered)) => {
-                                let next_color = self.eval_ray_color(&scattered, hittables, remaining_depth-1);
-                                color = attenuation.component_mul(&next_color);
-                            },
-                            None => {
-                                color = Vector3f::zeros()
-                            }
-                        }
+            stats.record_path_end(depth, PathEnd::MaxDepth);
+            return Vector3f::zeros();
+        }
+
+        let closest_hit = scene.closest_hit_counted(r, 0.001, 10000.0, &mut stats.intersection_tests);
+        // the fog scatters the ray if its free flight ends before the hit
+        if let Some(fog) = scene.fog() {
+            let t_max = closest_hit.as_ref().map_or(f32::INFINITY, |hit| hit.hitpoint.t);
+            if let Some(t) = fog.sample_scattering(r, t_max) {
+                let (attenuation, scattered) = fog.scatter(r, t);
+                stats.secondary_rays += 1;
+                let next_color = self.eval_ray_color(&scattered, scene, remaining_depth-1, LightSurfaces::Counted, stats);
+                return attenuation.component_mul(&next_color);
+            }
+        }
+
+        match closest_hit {
+            Some(hit) => {
+                let hittable = scene.hittable(hit.index);
+                let material = hittable.material().as_ref().unwrap();
+                let surface_weight = light_surfaces.weight(scene.light_of_surface(hit.index), r);
+                let emitted = hittable.emitted(r, &hit.hitpoint) + material.emitted(r, &hit.hitpoint) * surface_weight;
+                let integrator = self.settings.integrator();
+                let direct = match integrator {
+                    Integrator::NextEventEstimation | Integrator::MultipleImportanceSampling => {
+                        let weighted = integrator == Integrator::MultipleImportanceSampling;
+                        self.eval_direct_light(r, scene, &hit.hitpoint, material.as_ref(), weighted, stats)
                     },
-                    None=> ()
+                    _ => None,
+                };
+                match material.sample(r, &hit.hitpoint)  {
+                    Some(sample) => {
+                        stats.secondary_rays += 1;
+                        let next_light_surfaces = match (integrator, &direct) {
+                            (_, None) => LightSurfaces::Counted,
+                            _ if sample.specular => LightSurfaces::Counted,
+                            (Integrator::MultipleImportanceSampling, _) => LightSurfaces::Weighted { bsdf_pdf: sample.pdf },
+                            _ => LightSurfaces::Skipped,
+                        };
+                        let scattered = sample.ray(r, &hit.hitpoint);
+                        let next_color = self.eval_ray_color(&scattered, scene, remaining_depth-1,
+                            next_light_surfaces, stats);
+                        emitted + direct.unwrap_or_else(Vector3f::zeros) + sample.weight.component_mul(&next_color)
+                    },
+                    None => {
+                        stats.record_path_end(depth, PathEnd::Absorbed);
+                        emitted + direct.unwrap_or_else(Vector3f::zeros)
+                    }
                 }
+            },
+            None => {
+                stats.record_path_end(depth, PathEnd::Escaped);
+                self.eval_background_color(r, scene) * light_surfaces.weight(scene.environment_light(), r)
             }
         }
-        color
     }
 
+    /// debug integrator, maps the normal of the first hit to a color
+    fn eval_ray_normal(&self, r: &Ray, scene: &Scene, stats: &mut RenderStats) -> Vector3f {
+        match scene.closest_hit_counted(r, 0.001, 10000.0, &mut stats.intersection_tests) {
+            Some(hit) => {
+                stats.record_path_end(0, PathEnd::Absorbed);
+                (hit.hitpoint.normal + Vector3f::new(1f32, 1f32, 1f32)) * 0.5f32
+            },
+            None => {
+                stats.record_path_end(0, PathEnd::Escaped);
+                self.eval_background_color(r, scene)
+            }
+        }
+    }
+
+    fn eval_camera_ray(&self, r: &Ray, scene: &Scene, stats: &mut RenderStats) -> Vector3f {
+        stats.primary_rays += 1;
+        match self.settings.integrator() {
+            Integrator::PathTracer | Integrator::NextEventEstimation | Integrator::MultipleImportanceSampling =>
+                self.eval_ray_color(r, scene, self.settings.max_depth(), LightSurfaces::Counted, stats),
+            Integrator::Normals => self.eval_ray_normal(r, scene, stats),
+        }
+    }
+
+    /// evaluate the linear color of a pixel
     pub fn eval_pixel_color(
         &self,
         camera: &Camera,
         pixel_position: Vector2i,
-        hittables: &Vec<Box<dyn Hittable>>,
-    ) -> Rgb8 {
-        let color_vector : Vector3f = if self.settings.antialiasing_on {
+        scene: &Scene,
+        stats: &mut RenderStats,
+    ) -> Vector3f {
+        if self.settings.antialiasing_on() {
             let mut pixel_color_vector = Vector3f::zeros();
-            for _i in 0..self.settings.antialiasing_samples {
+            for _i in 0..self.settings.antialiasing_samples() {
                 let ray = camera.get_random_ray_from_image_xy(pixel_position);
-                let sample_color = self.eval_ray_color(&ray, &hittables, self.settings.max_depth);
-                pixel_color_vector += sample_color;
+                pixel_color_vector += self.eval_camera_ray(&ray, scene, stats);
             }
-            let color_vector : Vector3f = pixel_color_vector / self.settings.antialiasing_samples as f32;
-
-            // apply gamma 2 correction
-            let corrected_color_vector = color_vector.pointwise_sqrt();
-
-            // println!("{}", color_vector);
-            corrected_color_vector
+            pixel_color_vector / self.settings.antialiasing_samples() as f32
         } else {
-            let ray = camera.get_ray_from_image_xy(pixel_position);
-            let color_vector = self.eval_ray_color(&ray, &hittables, self.settings.max_depth);
-            // println!("{}", color_vector);
-            color_vector
-        };
-        vector3f_to_rgb8(color_vector)
+            let ray = camera.get_timed_ray_from_image_xy(pixel_position);
+            self.eval_camera_ray(&ray, scene, stats)
+        }
     }
 
-    pub fn run(&self, camera: &Camera, hittables: &Vec<Box<dyn Hittable>>) -> Image {
+    /// trace the rows image_y = first_row, first_row + row_step, ...
+    fn trace_rows(&self, camera: &Camera, scene: &Scene,
+            first_row: i32, row_step: usize) -> (Vec<(i32, Vec<Vector3f>)>, RenderStats) {
+        let start = Instant::now();
+        let mut stats = RenderStats::new();
+        stats.threads = 1;
+        let mut rows = Vec::new();
+        for image_y in (first_row..camera.viewport.image_height()).step_by(row_step) {
+            let row = (0..camera.viewport.image_width()).map(|image_x| {
+                let pixel_position = Vector2i::new(image_x, image_y);
+                self.eval_pixel_color(camera, pixel_position, scene, &mut stats)
+            }).collect();
+            rows.push((image_y, row));
+        }
+        stats.trace_thread_time = start.elapsed();
+        (rows, stats)
+    }
+
+    /// render one pass of the image, without tonemapping.
+    /// successive passes can be averaged to refine the image
+    pub fn render_pass(&self, camera: &Camera, scene: &Scene) -> (FloatImage, RenderStats) {
+        let run_start = Instant::now();
         let image_size = Size2i::new(
             camera.viewport.image_height(),
             camera.viewport.image_width(),
         );
-        let mut image = Image::new(image_size);
+        let mut image = FloatImage::new(image_size);
+        let mut stats = RenderStats::new();
+
+        // every thread renders an interleaved set of rows, so that expensive
+        // parts of the image are spread between threads
+        let threads_count = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        let trace_start = Instant::now();
+        let traced_rows : Vec<_> = thread::scope(|scope| {
+            let handles : Vec<_> = (0..threads_count).map(|thread_index| {
+                scope.spawn(move || self.trace_rows(camera, scene, thread_index as i32, threads_count))
+            }).collect();
+            handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+        });
+        stats.trace_time = trace_start.elapsed();
 
         // render from upper left corner
-        for image_y in 0..camera.viewport.image_height() {
-            for image_x in 0..camera.viewport.image_width() {
-                let pixel_position = Vector2i::new(image_x, image_y);
-                let pixel_color = self.eval_pixel_color(camera, pixel_position, &hittables);
-                image.set_pixel(pixel_position, pixel_color);
+        for (rows, thread_stats) in traced_rows {
+            stats.merge(&thread_stats);
+            for (image_y, row) in rows {
+                for (image_x, color_vector) in row.into_iter().enumerate() {
+                    image.set_pixel(Vector2i::new(image_x as i32, image_y), color_vector);
+                }
             }
         }
+        stats.total_time = run_start.elapsed();
+
+        (image, stats)
+    }
+
+    pub fn run(&self, camera: &Camera, scene: &Scene) -> (Image, RenderStats) {
+        let (float_image, mut stats) = self.render_pass(camera, scene);
+
+        let tonemap_start = Instant::now();
+        let image = float_image.to_image();
+        stats.tonemap_time = tonemap_start.elapsed();
+        stats.total_time += stats.tonemap_time;
 
-        image
+        (image, stats)
     }
 }
@@ -0,0 +1,160 @@
+use std::fmt;
+
+/// Which algorithm the renderer uses to compute the color of a camera ray
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Integrator {
+    /// unidirectional path tracer, bouncing until the ray escapes or max_depth is reached
+    PathTracer,
+    /// path tracer casting a shadow ray towards every light at each diffuse hit
+    NextEventEstimation,
+    /// next event estimation and the rays the materials sample both reach the lights,
+    /// weighted by the power heuristic
+    MultipleImportanceSampling,
+    /// debug view, shades the first hit with its normal
+    Normals,
+}
+
+impl Integrator {
+
+    pub const ALL: [Integrator; 4] = [
+        Integrator::PathTracer,
+        Integrator::NextEventEstimation,
+        Integrator::MultipleImportanceSampling,
+        Integrator::Normals,
+    ];
+
+    /// the integrator after this one, wrapping around
+    pub fn next(self) -> Integrator {
+        let index = Integrator::ALL.iter().position(|i| *i == self).unwrap();
+        Integrator::ALL[(index + 1) % Integrator::ALL.len()]
+    }
+
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RendererSettings {
+    antialiasing_on: bool,
+    antialiasing_samples: u32,
+    max_depth: u32,
+    integrator: Integrator,
+}
+
+impl Default for RendererSettings {
+    fn default() -> Self {
+        RendererSettings {
+            antialiasing_on: true,
+            antialiasing_samples: 50,
+            max_depth: 50,
+            integrator: Integrator::PathTracer,
+        }
+    }
+}
+
+impl RendererSettings {
+
+    pub fn builder() -> RendererSettingsBuilder {
+        RendererSettingsBuilder {
+            settings: RendererSettings::default()
+        }
+    }
+
+    /// start a builder from these settings, to change only some of them
+    pub fn to_builder(&self) -> RendererSettingsBuilder {
+        RendererSettingsBuilder {
+            settings: self.clone()
+        }
+    }
+
+    pub fn antialiasing_on(&self) -> bool {
+        self.antialiasing_on
+    }
+
+    pub fn antialiasing_samples(&self) -> u32 {
+        self.antialiasing_samples
+    }
+
+    pub fn max_depth(&self) -> u32 {
+        self.max_depth
+    }
+
+    pub fn integrator(&self) -> Integrator {
+        self.integrator
+    }
+
+}
+
+impl fmt::Display for RendererSettings {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "integrator {:?}, antialiasing {} ({} samples), max depth {}",
+            self.integrator,
+            if self.antialiasing_on { "on" } else { "off" },
+            self.antialiasing_samples,
+            self.max_depth)
+    }
+
+}
+
+/// Builds `RendererSettings`, fields not set keep their default value.
+/// Sample count and depth are clamped to at least 1
+pub struct RendererSettingsBuilder {
+    settings: RendererSettings
+}
+
+impl RendererSettingsBuilder {
+
+    pub fn antialiasing_on(mut self, antialiasing_on: bool) -> Self {
+        self.settings.antialiasing_on = antialiasing_on;
+        self
+    }
+
+    pub fn antialiasing_samples(mut self, antialiasing_samples: u32) -> Self {
+        self.settings.antialiasing_samples = antialiasing_samples.max(1);
+        self
+    }
+
+    pub fn max_depth(mut self, max_depth: u32) -> Self {
+        self.settings.max_depth = max_depth.max(1);
+        self
+    }
+
+    pub fn integrator(mut self, integrator: Integrator) -> Self {
+        self.settings.integrator = integrator;
+        self
+    }
+
+    pub fn build(self) -> RendererSettings {
+        self.settings
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builder_sets_and_clamps_the_settings() {
+        let settings = RendererSettings::builder()
+            .antialiasing_on(false)
+            .antialiasing_samples(8)
+            .max_depth(3)
+            .integrator(Integrator::Normals)
+            .build();
+        assert!(!settings.antialiasing_on());
+        assert_eq!(settings.antialiasing_samples(), 8);
+        assert_eq!(settings.max_depth(), 3);
+        assert_eq!(settings.integrator(), Integrator::Normals);
+
+        // fields not set keep their default, or the value of the settings the builder started from
+        assert_eq!(RendererSettings::builder().build(), RendererSettings::default());
+        let changed = settings.to_builder().max_depth(0).antialiasing_samples(0).build();
+        assert_eq!(changed.max_depth(), 1);
+        assert_eq!(changed.antialiasing_samples(), 1);
+        assert_eq!(changed.integrator(), Integrator::Normals);
+
+        // the last integrator wraps around to the first
+        assert_eq!(Integrator::ALL[0].next(), Integrator::ALL[1]);
+        assert_eq!(Integrator::ALL[Integrator::ALL.len() - 1].next(), Integrator::ALL[0]);
+    }
+}
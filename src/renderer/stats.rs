@@ -0,0 +1,161 @@
+use std::fmt;
+use std::time::Duration;
+
+/// How a traced path ended
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PathEnd {
+    /// the ray left the scene and picked up the background
+    Escaped,
+    /// the material did not scatter the ray
+    Absorbed,
+    /// the path was cut at `max_depth` bounces
+    MaxDepth,
+}
+
+/// Counters collected while rendering a frame.
+/// Every render thread fills its own copy, the copies are merged at the end of `Renderer::run`
+#[derive(Clone, Debug, Default)]
+pub struct RenderStats {
+    pub threads: u32,
+    pub primary_rays: u64,
+    pub secondary_rays: u64,
+    /// rays towards sampled light points, not counted in total_rays
+    pub shadow_rays: u64,
+    pub intersection_tests: u64,
+    pub paths: u64,
+    pub total_path_depth: u64,
+    pub paths_escaped: u64,
+    pub paths_absorbed: u64,
+    pub paths_terminated_by_max_depth: u64,
+    /// wall time spent tracing the image
+    pub trace_time: Duration,
+    /// sum of the time spent tracing by every thread
+    pub trace_thread_time: Duration,
+    /// wall time spent converting the traced colors to the output image
+    pub tonemap_time: Duration,
+    pub total_time: Duration,
+}
+
+impl RenderStats {
+
+    pub fn new() -> RenderStats {
+        RenderStats::default()
+    }
+
+    pub fn record_path_end(&mut self, depth: u32, end: PathEnd) {
+        self.paths += 1;
+        self.total_path_depth += depth as u64;
+        match end {
+            PathEnd::Escaped => self.paths_escaped += 1,
+            PathEnd::Absorbed => self.paths_absorbed += 1,
+            PathEnd::MaxDepth => self.paths_terminated_by_max_depth += 1,
+        }
+    }
+
+    /// add the counters of another thread to these ones.
+    /// wall times are not summed, they are measured once by the caller
+    pub fn merge(&mut self, other: &RenderStats) {
+        self.threads += other.threads;
+        self.primary_rays += other.primary_rays;
+        self.secondary_rays += other.secondary_rays;
+        self.shadow_rays += other.shadow_rays;
+        self.intersection_tests += other.intersection_tests;
+        self.paths += other.paths;
+        self.total_path_depth += other.total_path_depth;
+        self.paths_escaped += other.paths_escaped;
+        self.paths_absorbed += other.paths_absorbed;
+        self.paths_terminated_by_max_depth += other.paths_terminated_by_max_depth;
+        self.trace_thread_time += other.trace_thread_time;
+    }
+
+    pub fn total_rays(&self) -> u64 {
+        self.primary_rays + self.secondary_rays
+    }
+
+    pub fn average_path_depth(&self) -> f64 {
+        if self.paths == 0 {
+            0.0
+        } else {
+            self.total_path_depth as f64 / self.paths as f64
+        }
+    }
+
+    pub fn rays_per_second(&self) -> f64 {
+        let seconds = self.trace_time.as_secs_f64();
+        if seconds > 0.0 {
+            self.total_rays() as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let value = serde_json::json!({
+            "threads": self.threads,
+            "primary_rays": self.primary_rays,
+            "secondary_rays": self.secondary_rays,
+            "shadow_rays": self.shadow_rays,
+            "intersection_tests": self.intersection_tests,
+            "paths": self.paths,
+            "average_path_depth": self.average_path_depth(),
+            "paths_escaped": self.paths_escaped,
+            "paths_absorbed": self.paths_absorbed,
+            "paths_terminated_by_max_depth": self.paths_terminated_by_max_depth,
+            "rays_per_second": self.rays_per_second(),
+            "phases": {
+                "trace_seconds": self.trace_time.as_secs_f64(),
+                "trace_thread_seconds": self.trace_thread_time.as_secs_f64(),
+                "tonemap_seconds": self.tonemap_time.as_secs_f64(),
+                "total_seconds": self.total_time.as_secs_f64(),
+            }
+        });
+        serde_json::to_string_pretty(&value).unwrap()
+    }
+}
+
+impl fmt::Display for RenderStats {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "render stats ({} threads)", self.threads)?;
+        writeln!(f, "  rays: {} primary, {} secondary ({:.0} rays/s), {} shadow",
+            self.primary_rays, self.secondary_rays, self.rays_per_second(), self.shadow_rays)?;
+        writeln!(f, "  intersection tests: {}", self.intersection_tests)?;
+        writeln!(f, "  paths: {}, average depth {:.2}", self.paths, self.average_path_depth())?;
+        writeln!(f, "    escaped: {}, absorbed: {}, terminated by max depth: {}",
+            self.paths_escaped, self.paths_absorbed, self.paths_terminated_by_max_depth)?;
+        write!(f, "  time: trace {:.3}s (threads {:.3}s), tonemap {:.3}s, total {:.3}s",
+            self.trace_time.as_secs_f64(), self.trace_thread_time.as_secs_f64(),
+            self.tonemap_time.as_secs_f64(), self.total_time.as_secs_f64())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_sums_thread_counters() {
+        let mut a = RenderStats::new();
+        a.threads = 1;
+        a.primary_rays = 10;
+        a.record_path_end(2, PathEnd::Escaped);
+        a.record_path_end(4, PathEnd::Absorbed);
+
+        let mut b = RenderStats::new();
+        b.threads = 1;
+        b.primary_rays = 5;
+        b.secondary_rays = 3;
+        b.record_path_end(6, PathEnd::MaxDepth);
+
+        a.merge(&b);
+        assert_eq!(a.threads, 2);
+        assert_eq!(a.total_rays(), 18);
+        assert_eq!(a.paths, 3);
+        assert_eq!(a.paths_terminated_by_max_depth, 1);
+        assert!((a.average_path_depth() - 4.0).abs() < 1e-9);
+
+        let json: serde_json::Value = serde_json::from_str(&a.to_json()).unwrap();
+        assert_eq!(json["paths_absorbed"], 1);
+        assert_eq!(json["average_path_depth"], 4.0);
+    }
+}
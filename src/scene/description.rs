@@ -0,0 +1,894 @@
+use crate::geom::aabb::Aabb;
+use crate::geom::hittable::Hittable;
+use crate::geom::constant_medium::{ConstantMedium, Fog};
+use crate::geom::csg::{Csg, CsgOperation};
+use crate::geom::cuboid::Cuboid;
+use crate::geom::cylinder::{Cone, Cylinder};
+use crate::geom::moving::{Keyframe, MovingSphere, TransformMotion};
+use crate::geom::plane::{Disk, Plane};
+use crate::geom::quad::Quad;
+use crate::geom::rect::{AxisAlignedRect, RectPlane};
+use crate::geom::sdf::{SdfExpr, SdfHittable};
+use crate::geom::sphere::Sphere;
+use crate::geom::torus::Torus;
+use crate::geom::transformed::Transformed;
+use crate::geom::voxel_medium::VoxelMedium;
+use crate::light::environment::{ConstantEnvironment, Environment, EnvironmentMap, GradientEnvironment};
+use crate::light::sky::PreethamSky;
+use crate::light::light::{DirectionalLight, Light, PointLight, QuadLight, SphereLight, SpotLight};
+use crate::material::cutout::Cutout;
+use crate::material::material::{Dielectric, DiffuseLight, HenyeyGreenstein, Isotropic, Lambertian, Material, Metal, OrenNayar};
+use crate::material::microfacet::{ComplexIor, Conductor, RoughDielectric, RoughMetal};
+use crate::material::mix::{Coated, Mix};
+use crate::material::normal_map::NormalMapped;
+use crate::material::principled::{Principled, PrincipledParameters};
+use crate::material::texture::{Checker, ImageTexture, SolidColor, Texture};
+use crate::scene::graph::{self, SceneNode};
+use crate::scene::scene::Scene;
+use crate::types::{to_vector3f, Vector2f, Vector3f};
+use crate::voxel;
+use nalgebra::Matrix4;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Serializable description of a scene, the format of scene files.
+/// The scene is a graph of named nodes, addressed by their path.
+/// The viewer edits the description and rebuilds the `Scene` from it
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SceneDesc {
+    pub nodes: Vec<SceneNode>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fog: Option<FogDesc>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lights: Vec<LightDesc>,
+    /// what rays leaving the scene find, the gradient sky of the book without one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment: Option<EnvironmentDesc>,
+}
+
+/// homogeneous fog. With `bounds`, a `[min, max]` pair of corners, it fills that
+/// box, rays escaping to the sky included. Without them it only fills the space
+/// between the shapes: rays escaping to the sky cross it unscattered, since an
+/// unbounded fog would let no light in
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FogDesc {
+    pub density: f32,
+    pub albedo: [f32; 3],
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bounds: Option<[[f32; 3]; 2]>,
+}
+
+/// light sampled at every diffuse hit. Sphere and quad lights are also hittables,
+/// colors are radiance for them, intensity for point and spot lights
+/// and irradiance for directional ones
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LightDesc {
+    Point { position: [f32; 3], intensity: [f32; 3] },
+    /// angles in degrees from the direction, the light fades out between them
+    Spot { position: [f32; 3], direction: [f32; 3], inner_degrees: f32, outer_degrees: f32, intensity: [f32; 3] },
+    /// direction is where the light travels to
+    Directional { direction: [f32; 3], irradiance: [f32; 3] },
+    Sphere { center: [f32; 3], radius: f32, radiance: [f32; 3] },
+    /// emits from the side u × v points to
+    Quad { corner: [f32; 3], u: [f32; 3], v: [f32; 3], radiance: [f32; 3] },
+}
+
+impl LightDesc {
+
+    pub fn build(&self) -> Box<dyn Light> {
+        match self {
+            LightDesc::Point { position, intensity } =>
+                Box::new(PointLight { position: to_vector3f(*position), intensity: to_vector3f(*intensity) }),
+            LightDesc::Spot { position, direction, inner_degrees, outer_degrees, intensity } =>
+                Box::new(SpotLight::new(to_vector3f(*position), to_vector3f(*direction), *inner_degrees, *outer_degrees,
+                    to_vector3f(*intensity))),
+            LightDesc::Directional { direction, irradiance } =>
+                Box::new(DirectionalLight { direction: to_vector3f(*direction).normalize(), irradiance: to_vector3f(*irradiance) }),
+            LightDesc::Sphere { center, radius, radiance } =>
+                Box::new(SphereLight { center: to_vector3f(*center), radius: *radius, radiance: to_vector3f(*radiance) }),
+            LightDesc::Quad { corner, u, v, radiance } =>
+                Box::new(QuadLight { corner: to_vector3f(*corner), u: to_vector3f(*u), v: to_vector3f(*v), radiance: to_vector3f(*radiance) }),
+        }
+    }
+
+}
+
+/// light arriving from far away in every direction
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EnvironmentDesc {
+    /// from bottom, looking down, to top, looking up
+    Gradient { bottom: [f32; 3], top: [f32; 3] },
+    Constant { radiance: [f32; 3] },
+    /// clear sky lit by the sun, towards sun_direction. The turbidity goes from 2, very clear,
+    /// to 10, hazy, the ground below the horizon has the ground_albedo
+    Sky {
+        sun_direction: [f32; 3],
+        #[serde(default = "default_turbidity")]
+        turbidity: f32,
+        #[serde(default = "default_ground_albedo")]
+        ground_albedo: [f32; 3],
+    },
+    /// equirectangular .hdr or .pfm image file, rotated around y and scaled by intensity
+    Map {
+        file: String,
+        #[serde(default)]
+        rotation_degrees: f32,
+        #[serde(default = "default_intensity")]
+        intensity: f32,
+    },
+}
+
+fn default_intensity() -> f32 {
+    1.0
+}
+
+fn default_turbidity() -> f32 {
+    3.0
+}
+
+fn default_ground_albedo() -> [f32; 3] {
+    [0.3, 0.3, 0.3]
+}
+
+impl EnvironmentDesc {
+
+    /// fails if the image of a map cannot be loaded
+    pub fn build(&self) -> io::Result<Arc<dyn Environment>> {
+        Ok(match self {
+            EnvironmentDesc::Gradient { bottom, top } =>
+                Arc::new(GradientEnvironment { bottom: to_vector3f(*bottom), top: to_vector3f(*top) }),
+            EnvironmentDesc::Constant { radiance } => Arc::new(ConstantEnvironment { radiance: to_vector3f(*radiance) }),
+            EnvironmentDesc::Sky { sun_direction, turbidity, ground_albedo } =>
+                Arc::new(PreethamSky::new(to_vector3f(*sun_direction), *turbidity, to_vector3f(*ground_albedo))),
+            EnvironmentDesc::Map { file, rotation_degrees, intensity } =>
+                Arc::new(EnvironmentMap::load(Path::new(file), *rotation_degrees, *intensity)?),
+        })
+    }
+
+}
+
+/// scale, then rotation around rotation_axis, then translation
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TransformDesc {
+    pub translation: [f32; 3],
+    pub rotation_axis: [f32; 3],
+    pub rotation_degrees: f32,
+    pub scale: [f32; 3],
+}
+
+impl Default for TransformDesc {
+    fn default() -> Self {
+        TransformDesc {
+            translation: [0.0, 0.0, 0.0],
+            rotation_axis: [0.0, 1.0, 0.0],
+            rotation_degrees: 0.0,
+            scale: [1.0, 1.0, 1.0],
+        }
+    }
+}
+
+/// Motion of a node, from its own transform at time0 to this transform at time1.
+/// The node stays still before and after
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MotionDesc {
+    pub transform: TransformDesc,
+    #[serde(default)]
+    pub time0: f32,
+    #[serde(default = "default_time1")]
+    pub time1: f32,
+}
+
+fn default_time1() -> f32 {
+    1.0
+}
+
+fn default_density_scale() -> f32 {
+    1.0
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ShapeDesc {
+    Sphere { origin: [f32; 3], radius: f32 },
+    /// sphere moving from origin0 at time0 to origin1 at time1
+    MovingSphere {
+        origin0: [f32; 3],
+        origin1: [f32; 3],
+        radius: f32,
+        #[serde(default)]
+        time0: f32,
+        #[serde(default = "default_time1")]
+        time1: f32,
+    },
+    /// axis aligned rectangle at k along the axis normal to the plane,
+    /// min and max are the corners in the plane coordinates
+    Rect { plane: RectPlane, min: [f32; 2], max: [f32; 2], k: f32 },
+    /// parallelogram with a corner and two edges
+    Quad { corner: [f32; 3], u: [f32; 3], v: [f32; 3] },
+    /// axis aligned box between two opposite corners
+    Cuboid { min: [f32; 3], max: [f32; 3] },
+    /// infinite plane through point
+    Plane { point: [f32; 3], normal: [f32; 3] },
+    Disk { center: [f32; 3], normal: [f32; 3], radius: f32 },
+    /// along the y axis from base up to height
+    Cylinder { base: [f32; 3], radius: f32, height: f32, #[serde(default = "default_capped")] capped: bool },
+    /// along the y axis, radius at base, apex at height
+    Cone { base: [f32; 3], radius: f32, height: f32, #[serde(default = "default_capped")] capped: bool },
+    /// around the y axis
+    Torus { center: [f32; 3], major_radius: f32, minor_radius: f32 },
+    /// boolean combination of two solids
+    Csg { operation: CsgOperation, left: Box<CsgOperandDesc>, right: Box<CsgOperandDesc> },
+    /// sphere traced distance function, marched within bounds, the min and max corners.
+    /// Unbounded SDFs, as repetitions, need them to be rendered efficiently
+    Sdf {
+        sdf: SdfExpr,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        bounds: Option<[[f32; 3]; 2]>,
+    },
+    /// volume of constant density inside a closed boundary,
+    /// its material is the phase function, usually isotropic
+    ConstantMedium { boundary: Box<ShapeDesc>, density: f32 },
+    /// medium with the density of a grid file stretched between the min and max corners,
+    /// scaled by density_scale. The emission channel of the grid, if any, glows with the emission color
+    VoxelMedium {
+        file: String,
+        min: [f32; 3],
+        max: [f32; 3],
+        #[serde(default = "default_density_scale")]
+        density_scale: f32,
+        #[serde(default)]
+        emission: [f32; 3],
+    },
+}
+
+/// operand of a CSG shape, placed by its own transform
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CsgOperandDesc {
+    #[serde(flatten)]
+    pub shape: ShapeDesc,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transform: Option<TransformDesc>,
+}
+
+fn default_capped() -> bool {
+    true
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MaterialDesc {
+    /// the texture, if any, multiplies the albedo
+    Lambertian {
+        albedo: [f32; 3],
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        texture: Option<TextureDesc>,
+    },
+    /// rough diffuse, the roughness is the standard deviation of the facet angle in radians, 0 is lambertian
+    OrenNayar { albedo: [f32; 3], roughness: f32 },
+    Metal { albedo: [f32; 3], fuzziness: f32 },
+    Dielectric { reflective_index: f32 },
+    /// GGX microfacets, the albedo is the reflectance at normal incidence, a roughness of 0 is a mirror
+    RoughMetal { albedo: [f32; 3], roughness: f32 },
+    /// metal of a measured complex index of refraction, a roughness of 0 is a mirror
+    Conductor { ior: ConductorIorDesc, roughness: f32 },
+    /// glass of GGX microfacets that reflect and refract, a roughness of 0 is smooth
+    RoughDielectric { reflective_index: f32, roughness: f32 },
+    /// one material for most surfaces, the fields left out take their default
+    Principled(PrincipledDesc),
+    /// the second material in proportion to the weight, times the mean of the mask texture if any
+    Mix {
+        first: Box<MaterialDesc>,
+        second: Box<MaterialDesc>,
+        weight: f32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mask: Option<TextureDesc>,
+    },
+    /// clear varnish over the base, smooth at a roughness of 0
+    Coated {
+        base: Box<MaterialDesc>,
+        #[serde(default = "default_coat_index")]
+        reflective_index: f32,
+        #[serde(default)]
+        roughness: f32,
+    },
+    /// the base shaded with the normals of a tangent space normal map
+    NormalMapped { base: Box<MaterialDesc>, normal_map: TextureDesc },
+    /// the base shaded as if moved along the normal by scale times the grayscale height
+    Bumped { base: Box<MaterialDesc>, height: TextureDesc, scale: f32 },
+    /// the base with holes where the mean of the opacity is below one half, rays go through them
+    Cutout { base: Box<MaterialDesc>, opacity: TextureDesc },
+    /// phase function of constant media, scatters in all directions
+    Isotropic { albedo: [f32; 3] },
+    /// phase function of media scattering forwards, with a positive anisotropy, or backwards
+    HenyeyGreenstein { albedo: [f32; 3], anisotropy: f32 },
+    /// emits the radiance from its front face, does not scatter. Light hitting it
+    /// is only found by bouncing rays, see `LightDesc` for lights sampled at every hit
+    DiffuseLight { radiance: [f32; 3] },
+}
+
+fn default_coat_index() -> f32 {
+    1.5
+}
+
+/// parameters of the principled material, in [0, 1] but the ior
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrincipledDesc {
+    pub base_color: [f32; 3],
+    pub metallic: f32,
+    pub roughness: f32,
+    pub specular: f32,
+    pub clearcoat: f32,
+    pub clearcoat_roughness: f32,
+    pub sheen: f32,
+    pub sheen_tint: f32,
+    pub transmission: f32,
+    pub ior: f32,
+}
+
+impl Default for PrincipledDesc {
+    fn default() -> Self {
+        let parameters = PrincipledParameters::default();
+        PrincipledDesc {
+            base_color: parameters.base_color.into(),
+            metallic: parameters.metallic,
+            roughness: parameters.roughness,
+            specular: parameters.specular,
+            clearcoat: parameters.clearcoat,
+            clearcoat_roughness: parameters.clearcoat_roughness,
+            sheen: parameters.sheen,
+            sheen_tint: parameters.sheen_tint,
+            transmission: parameters.transmission,
+            ior: parameters.ior,
+        }
+    }
+}
+
+impl PrincipledDesc {
+
+    pub fn build(&self) -> PrincipledParameters {
+        PrincipledParameters {
+            base_color: to_vector3f(self.base_color),
+            metallic: self.metallic,
+            roughness: self.roughness,
+            specular: self.specular,
+            clearcoat: self.clearcoat,
+            clearcoat_roughness: self.clearcoat_roughness,
+            sheen: self.sheen,
+            sheen_tint: self.sheen_tint,
+            transmission: self.transmission,
+            ior: self.ior,
+        }
+    }
+
+}
+
+/// named metals, or the index eta + i k for red, green and blue
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConductorIorDesc {
+    Gold,
+    Copper,
+    Aluminum,
+    Silver,
+    Custom { eta: [f32; 3], k: [f32; 3] },
+}
+
+impl ConductorIorDesc {
+
+    pub fn build(&self) -> ComplexIor {
+        match self {
+            ConductorIorDesc::Gold => ComplexIor::gold(),
+            ConductorIorDesc::Copper => ComplexIor::copper(),
+            ConductorIorDesc::Aluminum => ComplexIor::aluminum(),
+            ConductorIorDesc::Silver => ComplexIor::silver(),
+            ConductorIorDesc::Custom { eta, k } => ComplexIor { eta: to_vector3f(*eta), k: to_vector3f(*k) },
+        }
+    }
+
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TextureDesc {
+    /// scale squares per uv unit
+    Checker { even: [f32; 3], odd: [f32; 3], scale: f32 },
+    /// png or jpeg file repeated over the uv. Colors are decoded with gamma 2,
+    /// normal, bump and opacity maps are read as linear. With alpha, the alpha channel as a grey
+    Image {
+        file: String,
+        #[serde(default)]
+        alpha: bool,
+    },
+}
+
+impl SceneDesc {
+
+    pub fn build(&self) -> Scene {
+        self.build_with_paths().0
+    }
+
+    /// the scene and, for each of its hittables, the path of the node it comes from.
+    /// The surfaces of the lights come last, with the paths "lights/<index>".
+    /// An environment that cannot be built is left out, as the nodes that cannot,
+    /// and the problems are returned last, as the validation of load reports them
+    pub fn build_with_paths(&self) -> (Scene, Vec<String>, Vec<String>) {
+        let mut flat = graph::flatten(&self.nodes);
+        let fog = self.fog.as_ref().map(|fog| Fog {
+            density: fog.density,
+            albedo: to_vector3f(fog.albedo),
+            bounds: fog.bounds.map(|[min, max]| Aabb::new(to_vector3f(min), to_vector3f(max))),
+        });
+        let lights : Vec<_> = self.lights.iter().map(LightDesc::build).collect();
+        for (index, light) in lights.iter().enumerate() {
+            if light.hittable().is_some() {
+                flat.paths.push(format!("lights/{}", index));
+            }
+        }
+        let mut scene = Scene::new(flat.hittables).with_fog(fog).with_lights(lights);
+        match self.environment.as_ref().map(EnvironmentDesc::build) {
+            Some(Ok(environment)) => scene = scene.with_environment(environment),
+            Some(Err(error)) => flat.errors.push(format!("environment cannot be built: {}", error)),
+            None => (),
+        }
+        (scene, flat.paths, flat.errors)
+    }
+
+    pub fn node(&self, path: &str) -> Option<&SceneNode> {
+        graph::find_node(&self.nodes, path)
+    }
+
+    pub fn node_mut(&mut self, path: &str) -> Option<&mut SceneNode> {
+        graph::find_node_mut(&mut self.nodes, path)
+    }
+
+    /// path of the node itself or of its closest ancestor in the description,
+    /// hittables instanced from another node have paths that are not in it
+    pub fn closest_node_path<'a>(&self, path: &'a str) -> Option<&'a str> {
+        let mut path = Some(path);
+        while let Some(current) = path {
+            if self.node(current).is_some() {
+                return Some(current);
+            }
+            path = graph::parent_path(current);
+        }
+        None
+    }
+
+    /// the material of the node or the one it inherits
+    pub fn node_material(&self, path: &str) -> Option<MaterialDesc> {
+        graph::inherited_material(&self.nodes, path)
+    }
+
+    /// fails if node names clash, instances refer to missing nodes or to themselves
+    /// or the environment map or texture images cannot be loaded
+    pub fn load(path: &Path) -> io::Result<SceneDesc> {
+        let data = std::fs::read_to_string(path)?;
+        let scene : SceneDesc = serde_json::from_str(&data)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let mut errors = graph::validate(&scene.nodes);
+        if let Some(Err(error)) = scene.environment.as_ref().map(EnvironmentDesc::build) {
+            errors.push(format!("environment cannot be built: {}", error));
+        }
+        if !errors.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, errors.join(", ")));
+        }
+        Ok(scene)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let data = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        std::fs::write(path, data)
+    }
+
+}
+
+impl TransformDesc {
+
+    pub fn matrix(&self) -> Matrix4<f32> {
+        self.keyframe().matrix()
+    }
+
+    pub fn keyframe(&self) -> Keyframe {
+        Keyframe {
+            translation: to_vector3f(self.translation),
+            rotation_axis: to_vector3f(self.rotation_axis),
+            rotation_degrees: self.rotation_degrees,
+            scale: to_vector3f(self.scale),
+        }
+    }
+
+}
+
+impl MotionDesc {
+
+    /// the motion starting from the start transform
+    pub fn build(&self, start: &TransformDesc) -> TransformMotion {
+        TransformMotion {
+            start: start.keyframe(),
+            end: self.transform.keyframe(),
+            time0: self.time0,
+            time1: self.time1,
+        }
+    }
+
+}
+
+impl CsgOperandDesc {
+
+    /// fails if the operand cannot be built or its transform is singular
+    pub fn build(&self, material: Box<dyn Material>) -> io::Result<Box<dyn Hittable>> {
+        let hittable = self.shape.build(material)?;
+        Ok(match &self.transform {
+            Some(transform) => {
+                let matrix = transform.matrix();
+                if matrix.try_inverse().is_none() {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, "CSG operand with a singular transform"));
+                }
+                Box::new(Transformed::new(Arc::from(hittable), matrix))
+            },
+            None => hittable
+        })
+    }
+
+}
+
+impl ShapeDesc {
+
+    /// true for the closed shapes, the ones that can be CSG operands
+    pub fn is_solid(&self) -> bool {
+        match self {
+            ShapeDesc::Sphere { .. } | ShapeDesc::MovingSphere { .. } | ShapeDesc::Cuboid { .. } | ShapeDesc::Plane { .. }
+                | ShapeDesc::Torus { .. } => true,
+            ShapeDesc::Cylinder { capped, .. } | ShapeDesc::Cone { capped, .. } => *capped,
+            ShapeDesc::Csg { left, right, .. } => left.shape.is_solid() && right.shape.is_solid(),
+            ShapeDesc::Rect { .. } | ShapeDesc::Quad { .. } | ShapeDesc::Disk { .. } | ShapeDesc::Sdf { .. }
+                | ShapeDesc::ConstantMedium { .. } | ShapeDesc::VoxelMedium { .. } => false,
+        }
+    }
+
+    /// fails if a CSG operand cannot be built or a file the shape needs cannot be loaded
+    pub fn build(&self, material: Box<dyn Material>) -> io::Result<Box<dyn Hittable>> {
+        Ok(match self {
+            ShapeDesc::Sphere { origin, radius } => Box::new(Sphere {
+                origin: to_vector3f(*origin),
+                radius: *radius,
+                material: Some(material)
+            }),
+            ShapeDesc::MovingSphere { origin0, origin1, radius, time0, time1 } => Box::new(MovingSphere {
+                origin0: to_vector3f(*origin0),
+                origin1: to_vector3f(*origin1),
+                time0: *time0,
+                time1: *time1,
+                radius: *radius,
+                material: Some(material)
+            }),
+            ShapeDesc::Rect { plane, min, max, k } => Box::new(AxisAlignedRect {
+                plane: *plane,
+                min: Vector2f::new(min[0], min[1]),
+                max: Vector2f::new(max[0], max[1]),
+                k: *k,
+                material: Some(material)
+            }),
+            ShapeDesc::Quad { corner, u, v } => Box::new(
+                Quad::new(to_vector3f(*corner), to_vector3f(*u), to_vector3f(*v), Some(material))),
+            ShapeDesc::Cuboid { min, max } => Box::new(
+                Cuboid::new(to_vector3f(*min), to_vector3f(*max), Some(material))),
+            ShapeDesc::Plane { point, normal } => Box::new(
+                Plane::new(to_vector3f(*point), to_vector3f(*normal), Some(material))),
+            ShapeDesc::Disk { center, normal, radius } => Box::new(
+                Disk::new(to_vector3f(*center), to_vector3f(*normal), *radius, Some(material))),
+            ShapeDesc::Cylinder { base, radius, height, capped } => Box::new(Cylinder {
+                base: to_vector3f(*base),
+                radius: *radius,
+                height: *height,
+                capped: *capped,
+                material: Some(material)
+            }),
+            ShapeDesc::Cone { base, radius, height, capped } => Box::new(Cone {
+                base: to_vector3f(*base),
+                radius: *radius,
+                height: *height,
+                capped: *capped,
+                material: Some(material)
+            }),
+            ShapeDesc::Torus { center, major_radius, minor_radius } => Box::new(Torus {
+                center: to_vector3f(*center),
+                major_radius: *major_radius,
+                minor_radius: *minor_radius,
+                material: Some(material)
+            }),
+            ShapeDesc::Csg { operation, left, right } => Box::new(Csg {
+                operation: *operation,
+                left: left.build(material.clone())?,
+                right: right.build(material.clone())?,
+                material: Some(material)
+            }),
+            ShapeDesc::Sdf { sdf, bounds: None } => Box::new(SdfHittable::new(Box::new(sdf.clone()), Some(material))),
+            ShapeDesc::Sdf { sdf, bounds: Some([min, max]) } => Box::new(SdfHittable::with_bounds(
+                Box::new(sdf.clone()),
+                Aabb::new(to_vector3f(*min), to_vector3f(*max)),
+                Some(material)
+            )),
+            ShapeDesc::ConstantMedium { boundary, density } =>
+                Box::new(ConstantMedium::new(boundary.build(material.clone())?, *density, Some(material))),
+            ShapeDesc::VoxelMedium { file, min, max, density_scale, emission } => Box::new(VoxelMedium::new(
+                Arc::new(voxel::load_grid(Path::new(file))?),
+                Aabb::new(to_vector3f(*min), to_vector3f(*max)),
+                *density_scale,
+                to_vector3f(*emission),
+                Some(material)
+            )),
+        })
+    }
+
+}
+
+impl TextureDesc {
+
+    /// an image that cannot be loaded is replaced by a uniform grey, flat as a normal or bump map
+    pub fn build(&self) -> Box<dyn Texture> {
+        self.try_build(false).unwrap_or_else(|_| Box::new(SolidColor::new(Vector3f::repeat(0.5))))
+    }
+
+    /// as `build`, with images read as linear data rather than colors
+    pub fn build_data(&self) -> Box<dyn Texture> {
+        self.try_build(true).unwrap_or_else(|_| Box::new(SolidColor::new(Vector3f::repeat(0.5))))
+    }
+
+    /// fails if the image cannot be loaded
+    pub fn try_build(&self, linear: bool) -> io::Result<Box<dyn Texture>> {
+        Ok(match self {
+            TextureDesc::Checker { even, odd, scale } =>
+                Box::new(Checker::new(to_vector3f(*even), to_vector3f(*odd), *scale)),
+            TextureDesc::Image { file, alpha: false } => Box::new(ImageTexture::load(Path::new(file), linear)?),
+            TextureDesc::Image { file, alpha: true } => Box::new(ImageTexture::load_alpha(Path::new(file))?),
+        })
+    }
+
+}
+
+impl MaterialDesc {
+
+    /// the surface materials, the phase functions of media and the lights are left out
+    pub const TYPE_NAMES: [&'static str; 3] = ["lambertian", "metal", "dielectric"];
+
+    pub fn build(&self) -> Box<dyn Material> {
+        match self {
+            MaterialDesc::Lambertian { albedo, texture: None } => Box::new(Lambertian::new(to_vector3f(*albedo))),
+            MaterialDesc::Lambertian { albedo, texture: Some(texture) } =>
+                Box::new(Lambertian::textured(to_vector3f(*albedo), texture.build())),
+            MaterialDesc::OrenNayar { albedo, roughness } => Box::new(OrenNayar::new(to_vector3f(*albedo), *roughness)),
+            MaterialDesc::Metal { albedo, fuzziness } => Box::new(Metal::new(to_vector3f(*albedo), *fuzziness)),
+            MaterialDesc::Dielectric { reflective_index } => Box::new(Dielectric::new(*reflective_index)),
+            MaterialDesc::RoughMetal { albedo, roughness } => Box::new(RoughMetal::new(to_vector3f(*albedo), *roughness)),
+            MaterialDesc::Conductor { ior, roughness } => Box::new(Conductor::new(ior.build(), *roughness)),
+            MaterialDesc::RoughDielectric { reflective_index, roughness } =>
+                Box::new(RoughDielectric::new(*reflective_index, *roughness)),
+            MaterialDesc::Principled(principled) => Box::new(Principled::new(principled.build())),
+            MaterialDesc::Mix { first, second, weight, mask: None } => Box::new(Mix::new(first.build(), second.build(), *weight)),
+            MaterialDesc::Mix { first, second, weight, mask: Some(mask) } =>
+                Box::new(Mix::masked(first.build(), second.build(), *weight, mask.build())),
+            MaterialDesc::Coated { base, reflective_index, roughness } =>
+                Box::new(Coated::new(base.build(), *reflective_index, *roughness)),
+            MaterialDesc::NormalMapped { base, normal_map } =>
+                Box::new(NormalMapped::normal_map(base.build(), normal_map.build_data())),
+            MaterialDesc::Bumped { base, height, scale } =>
+                Box::new(NormalMapped::bump_map(base.build(), height.build_data(), *scale)),
+            MaterialDesc::Cutout { base, opacity } => Box::new(Cutout::new(base.build(), opacity.build_data())),
+            MaterialDesc::Isotropic { albedo } => Box::new(Isotropic::new(to_vector3f(*albedo))),
+            MaterialDesc::HenyeyGreenstein { albedo, anisotropy } =>
+                Box::new(HenyeyGreenstein::new(to_vector3f(*albedo), *anisotropy)),
+            MaterialDesc::DiffuseLight { radiance } => Box::new(DiffuseLight::new(to_vector3f(*radiance))),
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            MaterialDesc::Lambertian { .. } => "lambertian",
+            MaterialDesc::OrenNayar { .. } => "oren_nayar",
+            MaterialDesc::Metal { .. } => "metal",
+            MaterialDesc::Dielectric { .. } => "dielectric",
+            MaterialDesc::RoughMetal { .. } => "rough_metal",
+            MaterialDesc::Conductor { .. } => "conductor",
+            MaterialDesc::RoughDielectric { .. } => "rough_dielectric",
+            MaterialDesc::Principled(_) => "principled",
+            MaterialDesc::Mix { .. } => "mix",
+            MaterialDesc::Coated { .. } => "coated",
+            MaterialDesc::NormalMapped { .. } => "normal_mapped",
+            MaterialDesc::Bumped { .. } => "bumped",
+            MaterialDesc::Cutout { .. } => "cutout",
+            MaterialDesc::Isotropic { .. } => "isotropic",
+            MaterialDesc::HenyeyGreenstein { .. } => "henyey_greenstein",
+            MaterialDesc::DiffuseLight { .. } => "diffuse_light",
+        }
+    }
+
+    pub fn albedo(&self) -> Option<[f32; 3]> {
+        match self {
+            MaterialDesc::Lambertian { albedo, .. } | MaterialDesc::OrenNayar { albedo, .. } | MaterialDesc::Metal { albedo, .. }
+                | MaterialDesc::RoughMetal { albedo, .. }
+                | MaterialDesc::Isotropic { albedo } | MaterialDesc::HenyeyGreenstein { albedo, .. } => Some(*albedo),
+            MaterialDesc::Principled(principled) => Some(principled.base_color),
+            MaterialDesc::Coated { base, .. } | MaterialDesc::NormalMapped { base, .. }
+                | MaterialDesc::Bumped { base, .. } | MaterialDesc::Cutout { base, .. } => base.albedo(),
+            MaterialDesc::Mix { .. } => None,
+            MaterialDesc::Dielectric { .. } | MaterialDesc::Conductor { .. } | MaterialDesc::RoughDielectric { .. }
+                | MaterialDesc::DiffuseLight { .. } => None,
+        }
+    }
+
+    /// set the albedo, components are clamped to [0, 1]. Does nothing if the material has no albedo
+    pub fn set_albedo(&mut self, new_albedo: [f32; 3]) {
+        let clamped = new_albedo.map(|channel| channel.clamp(0.0, 1.0));
+        match self {
+            MaterialDesc::Lambertian { albedo, .. } | MaterialDesc::OrenNayar { albedo, .. } | MaterialDesc::Metal { albedo, .. }
+                | MaterialDesc::RoughMetal { albedo, .. }
+                | MaterialDesc::Isotropic { albedo } | MaterialDesc::HenyeyGreenstein { albedo, .. } => *albedo = clamped,
+            MaterialDesc::Principled(principled) => principled.base_color = clamped,
+            MaterialDesc::Coated { base, .. } | MaterialDesc::NormalMapped { base, .. }
+                | MaterialDesc::Bumped { base, .. } | MaterialDesc::Cutout { base, .. } => base.set_albedo(clamped),
+            MaterialDesc::Mix { .. } => (),
+            MaterialDesc::Dielectric { .. } | MaterialDesc::Conductor { .. } | MaterialDesc::RoughDielectric { .. }
+                | MaterialDesc::DiffuseLight { .. } => (),
+        }
+    }
+
+    /// name and value of the scalar parameter of the material, if any
+    pub fn scalar_parameter(&self) -> Option<(&'static str, f32)> {
+        match self {
+            MaterialDesc::Lambertian { .. } | MaterialDesc::Isotropic { .. } | MaterialDesc::DiffuseLight { .. } => None,
+            MaterialDesc::HenyeyGreenstein { anisotropy, .. } => Some(("anisotropy", *anisotropy)),
+            MaterialDesc::Metal { fuzziness, .. } => Some(("fuzziness", *fuzziness)),
+            MaterialDesc::Dielectric { reflective_index } => Some(("reflective_index", *reflective_index)),
+            MaterialDesc::OrenNayar { roughness, .. } | MaterialDesc::RoughMetal { roughness, .. }
+                | MaterialDesc::Conductor { roughness, .. } | MaterialDesc::RoughDielectric { roughness, .. } => Some(("roughness", *roughness)),
+            MaterialDesc::Principled(principled) => Some(("roughness", principled.roughness)),
+            MaterialDesc::Mix { weight, .. } => Some(("weight", *weight)),
+            MaterialDesc::Coated { roughness, .. } => Some(("roughness", *roughness)),
+            MaterialDesc::NormalMapped { base, .. } | MaterialDesc::Cutout { base, .. } => base.scalar_parameter(),
+            MaterialDesc::Bumped { scale, .. } => Some(("scale", *scale)),
+        }
+    }
+
+    /// set the scalar parameter, clamped to its valid range
+    pub fn set_scalar_parameter(&mut self, value: f32) {
+        match self {
+            MaterialDesc::Lambertian { .. } | MaterialDesc::Isotropic { .. } | MaterialDesc::DiffuseLight { .. } => (),
+            MaterialDesc::HenyeyGreenstein { anisotropy, .. } => *anisotropy = value.clamp(-0.99, 0.99),
+            MaterialDesc::Metal { fuzziness, .. } => *fuzziness = value.clamp(0.0, 1.0),
+            MaterialDesc::Dielectric { reflective_index } => *reflective_index = value.max(1.0),
+            MaterialDesc::OrenNayar { roughness, .. } | MaterialDesc::RoughMetal { roughness, .. }
+                | MaterialDesc::Conductor { roughness, .. } | MaterialDesc::RoughDielectric { roughness, .. } => *roughness = value.clamp(0.0, 1.0),
+            MaterialDesc::Principled(principled) => principled.roughness = value.clamp(0.0, 1.0),
+            MaterialDesc::Mix { weight, .. } => *weight = value.clamp(0.0, 1.0),
+            MaterialDesc::Coated { roughness, .. } => *roughness = value.clamp(0.0, 1.0),
+            MaterialDesc::NormalMapped { base, .. } | MaterialDesc::Cutout { base, .. } => base.set_scalar_parameter(value),
+            // negative scales dent rather than raise
+            MaterialDesc::Bumped { scale, .. } => *scale = value,
+        }
+    }
+
+    /// the textures of the material and of the materials it is made of
+    pub fn textures(&self) -> Vec<&TextureDesc> {
+        match self {
+            MaterialDesc::Lambertian { texture, .. } => texture.iter().collect(),
+            MaterialDesc::Mix { first, second, mask, .. } =>
+                first.textures().into_iter().chain(second.textures()).chain(mask.iter()).collect(),
+            MaterialDesc::Coated { base, .. } => base.textures(),
+            MaterialDesc::NormalMapped { base, normal_map } => base.textures().into_iter().chain(Some(normal_map)).collect(),
+            MaterialDesc::Bumped { base, height, .. } => base.textures().into_iter().chain(Some(height)).collect(),
+            MaterialDesc::Cutout { base, opacity } => base.textures().into_iter().chain(Some(opacity)).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// a material of another type, keeping the albedo when both have one
+    pub fn converted_to(&self, type_name: &str) -> Option<MaterialDesc> {
+        let albedo = self.albedo().unwrap_or([0.8, 0.8, 0.8]);
+        match type_name {
+            "lambertian" => Some(MaterialDesc::Lambertian { albedo, texture: None }),
+            "oren_nayar" => Some(MaterialDesc::OrenNayar { albedo, roughness: 0.3 }),
+            "metal" => Some(MaterialDesc::Metal { albedo, fuzziness: 0.0 }),
+            "dielectric" => Some(MaterialDesc::Dielectric { reflective_index: 1.5 }),
+            "rough_metal" => Some(MaterialDesc::RoughMetal { albedo, roughness: 0.2 }),
+            "conductor" => Some(MaterialDesc::Conductor { ior: ConductorIorDesc::Aluminum, roughness: 0.2 }),
+            "rough_dielectric" => Some(MaterialDesc::RoughDielectric { reflective_index: 1.5, roughness: 0.2 }),
+            "principled" => Some(MaterialDesc::Principled(PrincipledDesc { base_color: albedo, ..PrincipledDesc::default() })),
+            "isotropic" => Some(MaterialDesc::Isotropic { albedo }),
+            "henyey_greenstein" => Some(MaterialDesc::HenyeyGreenstein { albedo, anisotropy: 0.0 }),
+            "diffuse_light" => Some(MaterialDesc::DiffuseLight { radiance: albedo }),
+            _ => None
+        }
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scene_file_round_trip() {
+        let mut metal_sphere = SceneNode::with_shape("metal",
+            ShapeDesc::Sphere { origin: [1.0, 0.0, -1.0], radius: 0.5 },
+            MaterialDesc::Metal { albedo: [0.8, 0.6, 0.2], fuzziness: 0.05 });
+        metal_sphere.transform = Some(TransformDesc { scale: [1.0, 2.0, 1.0], ..TransformDesc::default() });
+        let mut spheres = SceneNode::new("spheres");
+        spheres.children = vec![metal_sphere];
+        let mut copy = SceneNode::new("copy");
+        copy.instance = Some("spheres".to_string());
+        copy.visible = false;
+        let scene = SceneDesc {
+            nodes: vec![
+                SceneNode::with_shape("ground",
+                    ShapeDesc::Sphere { origin: [0.0, -100.5, -1.0], radius: 100.0 },
+                    MaterialDesc::Lambertian { albedo: [0.8, 0.8, 0.0], texture: None }),
+                spheres,
+                copy,
+            ],
+            fog: Some(FogDesc { density: 0.1, albedo: [0.9, 0.9, 0.9], bounds: Some([[-5.0, -1.0, -5.0], [5.0, 3.0, 5.0]]) }),
+            lights: vec![
+                LightDesc::Point { position: [0.0, 5.0, 0.0], intensity: [10.0, 10.0, 10.0] },
+                LightDesc::Quad { corner: [0.0, 3.0, 0.0], u: [1.0, 0.0, 0.0], v: [0.0, 0.0, -1.0], radiance: [4.0, 4.0, 4.0] },
+            ],
+            environment: Some(EnvironmentDesc::Constant { radiance: [0.2, 0.3, 0.4] }),
+        };
+        let fname = Path::new("/tmp/scene_round_trip.json");
+        scene.save(fname).unwrap();
+        assert_eq!(SceneDesc::load(fname).unwrap(), scene);
+        let (built, paths, errors) = scene.build_with_paths();
+        assert!(errors.is_empty());
+        assert_eq!(built.hittables().len(), 3);
+        assert_eq!(paths, vec!["ground", "spheres/metal", "lights/1"]);
+        assert_eq!(built.lights().len(), 2);
+        assert!(built.light_of_surface(2).is_some() && built.light_of_surface(1).is_none());
+        assert_eq!(built.fog().unwrap().density, 0.1);
+        assert_eq!(built.environment().radiance(&Vector3f::new(0.0, 1.0, 0.0)), Vector3f::new(0.2, 0.3, 0.4));
+        assert_eq!(scene.closest_node_path("copy/metal"), Some("copy"));
+    }
+
+    #[test]
+    fn material_conversion_keeps_albedo() {
+        let metal = MaterialDesc::Metal { albedo: [0.1, 0.2, 0.3], fuzziness: 0.5 };
+        let lambertian = metal.converted_to("lambertian").unwrap();
+        assert_eq!(lambertian, MaterialDesc::Lambertian { albedo: [0.1, 0.2, 0.3], texture: None });
+        assert!(metal.converted_to("unknown").is_none());
+
+        let gold : MaterialDesc = serde_json::from_str(r#"{ "type": "conductor", "ior": "gold", "roughness": 0.1 }"#).unwrap();
+        assert_eq!(gold, MaterialDesc::Conductor { ior: ConductorIorDesc::Gold, roughness: 0.1 });
+        assert_eq!(gold.scalar_parameter(), Some(("roughness", 0.1)));
+        assert!(gold.albedo().is_none());
+        let rough_metal = metal.converted_to("rough_metal").unwrap();
+        assert_eq!(rough_metal, MaterialDesc::RoughMetal { albedo: [0.1, 0.2, 0.3], roughness: 0.2 });
+
+        let principled : MaterialDesc = serde_json::from_str(r#"{ "type": "principled", "metallic": 1.0 }"#).unwrap();
+        assert_eq!(principled, MaterialDesc::Principled(PrincipledDesc { metallic: 1.0, ..PrincipledDesc::default() }));
+        assert_eq!(principled.converted_to("lambertian").unwrap().albedo(), Some(PrincipledDesc::default().base_color));
+
+        let mut varnished : MaterialDesc = serde_json::from_str(
+            r#"{ "type": "coated", "base": { "type": "lambertian", "albedo": [0.5, 0.3, 0.1] } }"#).unwrap();
+        varnished.set_albedo([0.6, 0.3, 0.1]);
+        assert_eq!(varnished, MaterialDesc::Coated {
+            base: Box::new(MaterialDesc::Lambertian { albedo: [0.6, 0.3, 0.1], texture: None }),
+            reflective_index: 1.5,
+            roughness: 0.0,
+        });
+
+        // a missing image fails the validation of the scene and builds as a flat map
+        let bumped : MaterialDesc = serde_json::from_str(r#"{ "type": "bumped", "scale": 0.01,
+            "base": { "type": "oren_nayar", "albedo": [0.5, 0.5, 0.5], "roughness": 0.3 },
+            "height": { "type": "image", "file": "/tmp/missing_bump_map.png" } }"#).unwrap();
+        assert_eq!(bumped.albedo(), Some([0.5, 0.5, 0.5]));
+        assert_eq!(bumped.textures(), vec![&TextureDesc::Image { file: "/tmp/missing_bump_map.png".to_string(), alpha: false }]);
+        let node = SceneNode::with_shape("ball", ShapeDesc::Sphere { origin: [0.0, 0.0, 0.0], radius: 1.0 }, bumped.clone());
+        assert_eq!(graph::validate(&[node]).len(), 1);
+        bumped.build();
+    }
+}
@@ -0,0 +1,452 @@
+use crate::geom::hittable::Hittable;
+use crate::geom::moving::{MovingTransformed, TransformMotion};
+use crate::geom::transformed::Transformed;
+use crate::material::material::Material;
+use crate::scene::description::{MaterialDesc, MotionDesc, ShapeDesc, TransformDesc};
+use nalgebra::Matrix4;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// separates the node names in a node path, e.g. "table/leg_1"
+pub const PATH_SEPARATOR: char = '/';
+
+/// material of the shapes that neither have one nor inherit one
+const DEFAULT_MATERIAL: MaterialDesc = MaterialDesc::Lambertian { albedo: [0.5, 0.5, 0.5], texture: None };
+
+/// A named node of the scene graph.
+/// The transform is relative to the parent node, the material is inherited
+/// by the children that have none. A node can instance the contents
+/// (shape and children) of another node, given by its path.
+/// A node with a motion moves from its transform to the one of the motion,
+/// nodes cannot move inside a moving node
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SceneNode {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transform: Option<TransformDesc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub motion: Option<MotionDesc>,
+    #[serde(default = "default_visible", skip_serializing_if = "is_visible")]
+    pub visible: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shape: Option<ShapeDesc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub material: Option<MaterialDesc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<SceneNode>,
+}
+
+fn default_visible() -> bool {
+    true
+}
+
+fn is_visible(visible: &bool) -> bool {
+    *visible
+}
+
+impl SceneNode {
+
+    /// a visible node without transform, contents or children
+    pub fn new(name: &str) -> SceneNode {
+        SceneNode {
+            name: name.to_string(),
+            transform: None,
+            motion: None,
+            visible: true,
+            shape: None,
+            material: None,
+            instance: None,
+            children: Vec::new(),
+        }
+    }
+
+    pub fn with_shape(name: &str, shape: ShapeDesc, material: MaterialDesc) -> SceneNode {
+        SceneNode {
+            shape: Some(shape),
+            material: Some(material),
+            ..SceneNode::new(name)
+        }
+    }
+
+    /// node to parent space, at the start of the motion if the node moves
+    pub fn local_transform(&self) -> Matrix4<f32> {
+        self.transform.as_ref().map_or_else(Matrix4::identity, |transform| transform.matrix())
+    }
+
+    pub fn local_motion(&self) -> Option<TransformMotion> {
+        let start = self.transform.clone().unwrap_or_default();
+        self.motion.as_ref().map(|motion| motion.build(&start))
+    }
+
+}
+
+pub fn join_path(parent: &str, name: &str) -> String {
+    if parent.is_empty() {
+        name.to_string()
+    } else if name.is_empty() {
+        parent.to_string()
+    } else {
+        format!("{}{}{}", parent, PATH_SEPARATOR, name)
+    }
+}
+
+/// path of the parent node, None for top level nodes
+pub fn parent_path(path: &str) -> Option<&str> {
+    path.rfind(PATH_SEPARATOR).map(|separator| &path[..separator])
+}
+
+pub fn find_node<'a>(nodes: &'a [SceneNode], path: &str) -> Option<&'a SceneNode> {
+    let mut names = path.split(PATH_SEPARATOR);
+    let first_name = names.next();
+    let mut node = nodes.iter().find(|node| Some(node.name.as_str()) == first_name)?;
+    for name in names {
+        node = node.children.iter().find(|child| child.name == name)?;
+    }
+    Some(node)
+}
+
+pub fn find_node_mut<'a>(nodes: &'a mut [SceneNode], path: &str) -> Option<&'a mut SceneNode> {
+    let mut names = path.split(PATH_SEPARATOR);
+    let first_name = names.next();
+    let mut node = nodes.iter_mut().find(|node| Some(node.name.as_str()) == first_name)?;
+    for name in names {
+        node = node.children.iter_mut().find(|child| child.name == name)?;
+    }
+    Some(node)
+}
+
+/// the material of the node or the one it inherits from its closest ancestor
+pub fn inherited_material(nodes: &[SceneNode], path: &str) -> Option<MaterialDesc> {
+    let mut path = Some(path);
+    while let Some(current) = path {
+        if let Some(material) = find_node(nodes, current).and_then(|node| node.material.clone()) {
+            return Some(material);
+        }
+        path = parent_path(current);
+    }
+    None
+}
+
+/// The graph flattened into hittables, in depth first order.
+/// paths[i] is the path of the node that hittables[i] comes from; hittables
+/// instanced from another node get the path of the instancing node
+/// followed by their path relative to the instanced one
+pub struct FlatScene {
+    pub hittables: Vec<Box<dyn Hittable>>,
+    pub paths: Vec<String>,
+    /// problems found while flattening, the offending nodes are skipped
+    pub errors: Vec<String>,
+}
+
+/// The transform of a node to world space, before × motion(time) × transform
+/// when the node or one of its ancestors moves, otherwise just transform
+#[derive(Clone)]
+struct Placement {
+    /// the motion, with the transform of the space it happens in
+    motion: Option<(Matrix4<f32>, TransformMotion)>,
+    transform: Matrix4<f32>,
+}
+
+impl Placement {
+
+    fn still(transform: Matrix4<f32>) -> Placement {
+        Placement { motion: None, transform }
+    }
+
+    /// the placement of a node with this local transform relative to this one
+    fn then(&self, local: &Matrix4<f32>) -> Placement {
+        Placement { motion: self.motion, transform: self.transform * local }
+    }
+
+    /// the placement of a node moving relative to this one, None if this one already moves
+    fn then_moving(&self, motion: TransformMotion) -> Option<Placement> {
+        match self.motion {
+            Some(_) => None,
+            None => Some(Placement { motion: Some((self.transform, motion)), transform: Matrix4::identity() }),
+        }
+    }
+
+    /// the placement of a leaf of a prototype placed relative to this one, None if both move
+    fn then_placement(&self, other: &Placement) -> Option<Placement> {
+        match (&self.motion, &other.motion) {
+            (Some(_), Some(_)) => None,
+            (None, Some((before, motion))) => Some(Placement {
+                motion: Some((self.transform * before, *motion)),
+                transform: other.transform,
+            }),
+            _ => Some(self.then(&other.transform)),
+        }
+    }
+
+    fn is_singular(&self) -> bool {
+        match &self.motion {
+            None => self.transform.try_inverse().is_none(),
+            Some((before, motion)) => [motion.time0, motion.time1].iter()
+                .any(|time| (before * motion.matrix_at(*time) * self.transform).try_inverse().is_none()),
+        }
+    }
+
+}
+
+/// a shape with its placement in world space, before being wrapped into a hittable
+#[derive(Clone)]
+struct FlatLeaf {
+    path: String,
+    geometry: Arc<dyn Hittable>,
+    placement: Placement,
+    /// set when an instancing node overrides the material
+    material: Option<Box<dyn Material>>,
+}
+
+impl FlatLeaf {
+
+    fn into_hittable(self) -> Box<dyn Hittable> {
+        let transform = self.placement.transform;
+        match (self.placement.motion, self.material) {
+            (None, None) if transform == Matrix4::identity() => dyn_clone::clone_box(&*self.geometry),
+            (None, None) => Box::new(Transformed::new(self.geometry, transform)),
+            (None, Some(material)) => Box::new(Transformed::with_material(self.geometry, transform, material)),
+            (Some((before, motion)), None) => Box::new(MovingTransformed::new(self.geometry, before, motion, transform)),
+            (Some((before, motion)), Some(material)) =>
+                Box::new(MovingTransformed::with_material(self.geometry, before, motion, transform, material)),
+        }
+    }
+
+}
+
+struct Flattener<'a> {
+    nodes: &'a [SceneNode],
+    /// contents of the instanced nodes, relative to the instanced node,
+    /// so that all the instances share the geometry
+    prototypes: HashMap<String, Vec<FlatLeaf>>,
+    /// the nodes being instanced, to detect cycles
+    instancing: Vec<String>,
+    errors: Vec<String>,
+}
+
+impl<'a> Flattener<'a> {
+
+    fn flatten_node(&mut self, node: &'a SceneNode, parent: &str, parent_placement: &Placement,
+            inherited_material: Option<&MaterialDesc>, leaves: &mut Vec<FlatLeaf>) {
+        if !node.visible {
+            return;
+        }
+        let path = join_path(parent, &node.name);
+        let placement = self.node_placement(node, &path, parent_placement);
+        if placement.is_singular() {
+            self.errors.push(format!("node {} has a singular transform", path));
+            return;
+        }
+        self.flatten_contents(node, &path, &placement, inherited_material, leaves);
+    }
+
+    /// a node moving inside a moving node is reported and kept still
+    fn node_placement(&mut self, node: &SceneNode, path: &str, parent_placement: &Placement) -> Placement {
+        if let Some(motion) = node.local_motion() {
+            match parent_placement.then_moving(motion) {
+                Some(placement) => return placement,
+                None => self.errors.push(format!("node {} moves inside a moving node", path)),
+            }
+        }
+        parent_placement.then(&node.local_transform())
+    }
+
+    /// shape, instanced contents and children of the node, the node visibility and transform aside
+    fn flatten_contents(&mut self, node: &'a SceneNode, path: &str, placement: &Placement,
+            inherited_material: Option<&MaterialDesc>, leaves: &mut Vec<FlatLeaf>) {
+        let material = node.material.as_ref().or(inherited_material);
+        if let Some(shape) = node.shape.as_ref().filter(|shape| {
+            let is_valid = !matches!(shape, ShapeDesc::Csg { .. }) || shape.is_solid();
+            if !is_valid {
+                self.errors.push(format!("node {} combines shapes that are not solids", path));
+            }
+            is_valid
+        }) {
+            let material = material.unwrap_or(&DEFAULT_MATERIAL);
+            match shape.build(material.build()) {
+                Ok(geometry) => leaves.push(FlatLeaf {
+                    path: path.to_string(),
+                    geometry: Arc::from(geometry),
+                    placement: placement.clone(),
+                    material: None,
+                }),
+                Err(error) => self.errors.push(format!("node {} cannot be built: {}", path, error)),
+            }
+        }
+        if let Some(instanced_path) = &node.instance {
+            let material_override = node.material.as_ref().map(|material| material.build());
+            for leaf in self.prototype(instanced_path, path) {
+                let leaf_path = join_path(path, &leaf.path);
+                let leaf_placement = match placement.then_placement(&leaf.placement) {
+                    Some(leaf_placement) => leaf_placement,
+                    None => {
+                        self.errors.push(format!("node {} moves inside a moving node", leaf_path));
+                        continue;
+                    }
+                };
+                leaves.push(FlatLeaf {
+                    path: leaf_path,
+                    geometry: leaf.geometry,
+                    placement: leaf_placement,
+                    material: material_override.clone().or(leaf.material),
+                });
+            }
+        }
+        for child in &node.children {
+            self.flatten_node(child, path, placement, material, leaves);
+        }
+    }
+
+    /// the flattened contents of the node at path, in its own space.
+    /// The node is instanced even if it is hidden, so hidden nodes can be used as templates
+    fn prototype(&mut self, path: &str, instancing_path: &str) -> Vec<FlatLeaf> {
+        if let Some(prototype) = self.prototypes.get(path) {
+            return prototype.clone();
+        }
+        if self.instancing.iter().any(|instanced| instanced == path) {
+            self.errors.push(format!("node {} instances {} recursively", instancing_path, path));
+            return Vec::new();
+        }
+        let node = match find_node(self.nodes, path) {
+            Some(node) => node,
+            None => {
+                self.errors.push(format!("node {} instances missing node {}", instancing_path, path));
+                return Vec::new();
+            }
+        };
+
+        self.instancing.push(path.to_string());
+        let mut prototype = Vec::new();
+        let material = inherited_material(self.nodes, path);
+        self.flatten_contents(node, "", &Placement::still(Matrix4::identity()), material.as_ref(), &mut prototype);
+        self.instancing.pop();
+        self.prototypes.insert(path.to_string(), prototype.clone());
+        prototype
+    }
+
+}
+
+pub fn flatten(nodes: &[SceneNode]) -> FlatScene {
+    let mut flattener = Flattener {
+        nodes,
+        prototypes: HashMap::new(),
+        instancing: Vec::new(),
+        errors: Vec::new(),
+    };
+    let mut leaves = Vec::new();
+    for node in nodes {
+        flattener.flatten_node(node, "", &Placement::still(Matrix4::identity()), None, &mut leaves);
+    }
+    let paths = leaves.iter().map(|leaf| leaf.path.clone()).collect();
+    FlatScene {
+        hittables: leaves.into_iter().map(FlatLeaf::into_hittable).collect(),
+        paths,
+        errors: flattener.errors,
+    }
+}
+
+/// names must be non empty, without separators and unique among siblings
+fn check_names(nodes: &[SceneNode], parent: &str, errors: &mut Vec<String>) {
+    for (index, node) in nodes.iter().enumerate() {
+        let path = join_path(parent, &node.name);
+        if node.name.is_empty() || node.name.contains(PATH_SEPARATOR) {
+            errors.push(format!("invalid node name {:?} in {:?}", node.name, parent));
+        } else if nodes[..index].iter().any(|sibling| sibling.name == node.name) {
+            errors.push(format!("duplicate node {}", path));
+        }
+        check_names(&node.children, &path, errors);
+    }
+}
+
+/// the image textures of the materials must load
+fn check_textures(nodes: &[SceneNode], parent: &str, errors: &mut Vec<String>) {
+    for node in nodes {
+        let path = join_path(parent, &node.name);
+        for texture in node.material.iter().flat_map(MaterialDesc::textures) {
+            if let Err(error) = texture.try_build(false) {
+                errors.push(format!("texture of node {} cannot be loaded: {}", path, error));
+            }
+        }
+        check_textures(&node.children, &path, errors);
+    }
+}
+
+/// the problems of the graph, empty if it is valid
+pub fn validate(nodes: &[SceneNode]) -> Vec<String> {
+    let mut errors = Vec::new();
+    check_names(nodes, "", &mut errors);
+    check_textures(nodes, "", &mut errors);
+    errors.extend(flatten(nodes).errors);
+    errors
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_sphere(name: &str) -> SceneNode {
+        SceneNode::with_shape(name,
+            ShapeDesc::Sphere { origin: [0.0, 0.0, 0.0], radius: 1.0 },
+            MaterialDesc::Lambertian { albedo: [0.5, 0.5, 0.5], texture: None })
+    }
+
+    #[test]
+    fn flatten_paths_visibility_and_instances() {
+        let mut template = SceneNode::new("template");
+        template.visible = false;
+        template.children = vec![unit_sphere("a"), unit_sphere("b")];
+        template.children[1].visible = false;
+
+        let mut copy = SceneNode::new("copy");
+        copy.instance = Some("template".to_string());
+        copy.transform = Some(TransformDesc { translation: [0.0, 0.0, -5.0], ..TransformDesc::default() });
+
+        let mut group = SceneNode::new("group");
+        group.children = vec![unit_sphere("ball"), copy];
+
+        let nodes = vec![template, group];
+        let flat = flatten(&nodes);
+        assert!(flat.errors.is_empty());
+        assert_eq!(flat.paths, vec!["group/ball", "group/copy/a"]);
+        let aabb = flat.hittables[1].bounding_box().unwrap();
+        assert!((aabb.centroid().z + 5.0).abs() < 1e-5);
+
+        assert_eq!(find_node(&nodes, "group/copy").unwrap().name, "copy");
+        assert!(find_node(&nodes, "group/copy/a").is_none());
+        assert_eq!(parent_path("group/copy"), Some("group"));
+        assert!(validate(&nodes).is_empty());
+    }
+
+    #[test]
+    fn validate_reports_cycles_and_duplicates() {
+        let mut node = SceneNode::new("loop");
+        let mut child = SceneNode::new("child");
+        child.instance = Some("loop".to_string());
+        node.children = vec![child, SceneNode::new("child")];
+        let errors = validate(&[node]);
+        assert_eq!(errors.len(), 2);
+
+        let motion = MotionDesc { transform: TransformDesc { translation: [1.0, 0.0, 0.0], ..TransformDesc::default() }, time0: 0.0, time1: 1.0 };
+        let mut moving = unit_sphere("moving");
+        moving.motion = Some(motion.clone());
+        let mut inner = unit_sphere("inner");
+        inner.motion = Some(motion);
+        moving.children = vec![inner];
+        let nodes = vec![moving];
+        assert_eq!(validate(&nodes), vec!["node moving/inner moves inside a moving node"]);
+        // the bounding boxes cover the whole motion
+        let aabb = flatten(&nodes).hittables[0].bounding_box().unwrap();
+        assert!((aabb.max.x - 2.0).abs() < 1e-5);
+
+        // a CSG operand scaled to nothing is reported, not built
+        let flat_operand : ShapeDesc = serde_json::from_str(r#"{ "type": "csg", "operation": "union",
+            "left": { "type": "sphere", "origin": [0.0, 0.0, 0.0], "radius": 1.0, "transform": { "scale": [0.0, 1.0, 1.0] } },
+            "right": { "type": "sphere", "origin": [1.0, 0.0, 0.0], "radius": 1.0 } }"#).unwrap();
+        let node = SceneNode::with_shape("csg", flat_operand, MaterialDesc::Lambertian { albedo: [0.5, 0.5, 0.5], texture: None });
+        assert_eq!(validate(&[node]).len(), 1);
+    }
+}
@@ -0,0 +1,3 @@
+pub mod scene;
+pub mod description;
+pub mod graph;
\ No newline at end of file
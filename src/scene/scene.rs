@@ -0,0 +1,143 @@
+use crate::geom::bvh::Bvh;
+use crate::geom::constant_medium::Fog;
+use crate::geom::hittable::Hittable;
+use crate::light::environment::{Environment, EnvironmentLight, GradientEnvironment};
+use crate::light::light::Light;
+use crate::raycasting::ray::{HitPoint, Ray};
+use std::sync::Arc;
+
+/// The objects to render, with the queries the renderer and the viewer run on them
+#[derive(Clone, Debug)]
+pub struct Scene {
+    hittables: Vec<Box<dyn Hittable>>,
+    bvh: Bvh,
+    fog: Option<Fog>,
+    lights: Vec<Box<dyn Light>>,
+    /// the surfaces of the lights are the hittables from this index on
+    first_light_surface: usize,
+    /// index in lights of the light of each surface
+    surface_lights: Vec<usize>,
+    environment: Arc<dyn Environment>,
+    /// the environment, when it is sampled as the lights
+    environment_light: Option<EnvironmentLight>,
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Scene::new(Vec::new())
+    }
+}
+
+/// Result of a scene query: which hittable was hit and where
+#[derive(Debug)]
+pub struct SceneHit {
+    /// index of the hittable in `Scene::hittables`
+    pub index: usize,
+    pub hitpoint: HitPoint,
+}
+
+impl Scene {
+
+    pub fn new(hittables: Vec<Box<dyn Hittable>>) -> Scene {
+        let bvh = Bvh::new(&hittables);
+        let first_light_surface = hittables.len();
+        Scene {
+            hittables,
+            bvh,
+            fog: None,
+            lights: Vec::new(),
+            first_light_surface,
+            surface_lights: Vec::new(),
+            environment: Arc::new(GradientEnvironment::default()),
+            environment_light: None,
+        }
+    }
+
+    pub fn with_fog(mut self, fog: Option<Fog>) -> Scene {
+        self.fog = fog;
+        self
+    }
+
+    /// set the lights sampled at every hit, the surfaces of area lights are added to the hittables
+    pub fn with_lights(mut self, lights: Vec<Box<dyn Light>>) -> Scene {
+        self.hittables.truncate(self.first_light_surface);
+        self.surface_lights.clear();
+        for (index, light) in lights.iter().enumerate() {
+            if let Some(surface) = light.hittable() {
+                self.hittables.push(surface);
+                self.surface_lights.push(index);
+            }
+        }
+        self.bvh = Bvh::new(&self.hittables);
+        self.lights = lights;
+        self
+    }
+
+    pub fn lights(&self) -> &[Box<dyn Light>] {
+        &self.lights
+    }
+
+    /// set what rays leaving the scene find, the gradient sky by default
+    pub fn with_environment(mut self, environment: Arc<dyn Environment>) -> Scene {
+        self.environment_light = if environment.is_sampled() {
+            Some(EnvironmentLight { environment: environment.clone() })
+        } else {
+            None
+        };
+        self.environment = environment;
+        self
+    }
+
+    pub fn environment(&self) -> &dyn Environment {
+        self.environment.as_ref()
+    }
+
+    /// the light of the environment, if it is sampled. Its light is also found by escaping rays
+    pub fn environment_light(&self) -> Option<&dyn Light> {
+        self.environment_light.as_ref().map(|light| light as &dyn Light)
+    }
+
+    /// the lights and the environment, if it is sampled, for direct lighting
+    pub fn sampled_lights(&self) -> impl Iterator<Item = &dyn Light> {
+        self.lights.iter().map(|light| light.as_ref()).chain(self.environment_light())
+    }
+
+    /// the light whose surface is the hittable, if any. Its emission is also gathered by sampling the light
+    pub fn light_of_surface(&self, index: usize) -> Option<&dyn Light> {
+        let surface = index.checked_sub(self.first_light_surface)?;
+        self.surface_lights.get(surface).map(|light| self.lights[*light].as_ref())
+    }
+
+    /// fog filling the space between the hittables
+    pub fn fog(&self) -> Option<&Fog> {
+        self.fog.as_ref()
+    }
+
+    pub fn hittables(&self) -> &[Box<dyn Hittable>] {
+        &self.hittables
+    }
+
+    pub fn hittable(&self, index: usize) -> &dyn Hittable {
+        self.hittables[index].as_ref()
+    }
+
+    /// the closest hit along the ray with t in (t_min, t_max)
+    pub fn closest_hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<SceneHit> {
+        self.closest_hit_counted(ray, t_min, t_max, &mut 0)
+    }
+
+    /// same as closest_hit, adds the number of intersection tests run to intersection_tests
+    pub fn closest_hit_counted(&self, ray: &Ray, t_min: f32, t_max: f32,
+            intersection_tests: &mut u64) -> Option<SceneHit> {
+        self.bvh.closest_hit(&self.hittables, ray, t_min, t_max, intersection_tests)
+            .map(|(index, hitpoint)| SceneHit { index, hitpoint })
+    }
+
+    /// fraction of light crossing the scene along the ray with t in (t_min, t_max),
+    /// adds the number of intersection tests run to intersection_tests
+    pub fn transmittance_counted(&self, ray: &Ray, t_min: f32, t_max: f32,
+            intersection_tests: &mut u64) -> f32 {
+        self.bvh.transmittance(&self.hittables, ray, t_min, t_max, intersection_tests)
+    }
+
+}
@@ -0,0 +1,126 @@
+use crate::image::float_image::FloatImage;
+use image_codecs::png::PngEncoder;
+use image_codecs::ColorType;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Paths of the files written by `save_screenshot`
+#[derive(Debug)]
+pub struct ScreenshotFiles {
+    /// tonemapped 8 bit image, with the metadata in tEXt chunks
+    pub png: PathBuf,
+    /// linear float image
+    pub pfm: PathBuf,
+    /// the metadata as a JSON object, PFM files have no room for it
+    pub json: PathBuf,
+}
+
+/// save the frame in directory as screenshot_<utc timestamp>.png and .pfm,
+/// and the metadata next to them in .json
+pub fn save_screenshot(frame: &FloatImage, metadata: &[(String, String)],
+        directory: &Path) -> io::Result<ScreenshotFiles> {
+    let basename = format!("screenshot_{}", utc_timestamp(SystemTime::now()));
+    let files = ScreenshotFiles {
+        png: directory.join(format!("{}.png", basename)),
+        pfm: directory.join(format!("{}.pfm", basename)),
+        json: directory.join(format!("{}.json", basename)),
+    };
+
+    let image = frame.to_image();
+    let mut png_data = Vec::new();
+    PngEncoder::new(&mut png_data)
+        .encode(&image.data, image.size.width() as u32, image.size.height() as u32, ColorType::Rgb8)
+        .map_err(io::Error::other)?;
+    std::fs::write(&files.png, insert_png_text_chunks(&png_data, metadata))?;
+
+    let float_data : Vec<f32> = frame.data.iter().flat_map(|pixel| pixel.iter().copied()).collect();
+    crate::pfm::save_image_to_pfm(&float_data, frame.size.width(), frame.size.height(), &files.pfm)?;
+    std::fs::write(&files.json, metadata_to_json(metadata))?;
+
+    Ok(files)
+}
+
+/// add a tEXt chunk for every (keyword, text) pair right after the IHDR chunk
+fn insert_png_text_chunks(png_data: &[u8], metadata: &[(String, String)]) -> Vec<u8> {
+    // 8 bytes signature, then IHDR: length, type, 13 bytes of data, crc
+    let ihdr_end = 8 + 4 + 4 + 13 + 4;
+    let mut output = png_data[..ihdr_end].to_vec();
+    for (keyword, text) in metadata {
+        let mut chunk_data = keyword.as_bytes().to_vec();
+        chunk_data.push(0);
+        chunk_data.extend_from_slice(text.as_bytes());
+
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(b"tEXt");
+        hasher.update(&chunk_data);
+
+        output.extend_from_slice(&(chunk_data.len() as u32).to_be_bytes());
+        output.extend_from_slice(b"tEXt");
+        output.extend_from_slice(&chunk_data);
+        output.extend_from_slice(&hasher.finalize().to_be_bytes());
+    }
+    output.extend_from_slice(&png_data[ihdr_end..]);
+    output
+}
+
+/// metadata as a JSON object of the keywords and their text
+fn metadata_to_json(metadata: &[(String, String)]) -> String {
+    let object : serde_json::Map<String, serde_json::Value> = metadata.iter()
+        .map(|(keyword, text)| (keyword.clone(), serde_json::Value::String(text.clone())))
+        .collect();
+    serde_json::to_string_pretty(&object).unwrap()
+}
+
+/// format time as YYYYMMDD_HHMMSS_mmm in UTC
+fn utc_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap();
+    let seconds = since_epoch.as_secs() as i64;
+    let (days, seconds_of_day) = (seconds.div_euclid(86400), seconds.rem_euclid(86400));
+
+    // days since epoch to civil date, http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era/1460 + day_of_era/36524 - day_of_era/146096) / 365;
+    let day_of_year = day_of_era - (365*year_of_era + year_of_era/4 - year_of_era/100);
+    let mp = (5*day_of_year + 2) / 153;
+    let day = day_of_year - (153*mp + 2)/5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}{:02}{:02}_{:02}{:02}{:02}_{:03}",
+        year, month, day,
+        seconds_of_day / 3600, (seconds_of_day / 60) % 60, seconds_of_day % 60,
+        since_epoch.subsec_millis())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Size2i, Vector3f};
+    use std::time::Duration;
+
+    #[test]
+    fn utc_timestamp_works() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_603_113_845_042);
+        assert_eq!(utc_timestamp(time), "20201019_132405_042");
+        assert_eq!(utc_timestamp(UNIX_EPOCH), "19700101_000000_000");
+    }
+
+    #[test]
+    fn metadata_is_written_next_to_the_pfm() {
+        let directory = std::env::temp_dir().join("screenshot_metadata_test");
+        std::fs::create_dir_all(&directory).unwrap();
+        let mut frame = FloatImage::new(Size2i::new(1, 2));
+        frame.data[1] = Vector3f::new(0.5, 1.0, 2.0);
+        let metadata = vec![("Accumulated passes".to_string(), "3".to_string())];
+
+        let files = save_screenshot(&frame, &metadata, &directory).unwrap();
+        assert!(files.png.exists() && files.pfm.exists());
+        let json : serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&files.json).unwrap()).unwrap();
+        assert_eq!(json["Accumulated passes"], "3");
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -5,7 +5,7 @@ use std::fmt::*;
 
 
 pub type Vector2i = Vector2<i32>;
-// pub type Vector2f = Vector2<f32>;
+pub type Vector2f = Vector2<f32>;
 pub type Vector3f = Vector3<f32>;
 // pub type Vector3i = Vector3<f32>;
 // pub type Size2f = Size2<f32>;
@@ -33,9 +33,9 @@ impl PointwiseSqrtExt for Vector3f {
 }
 
 pub fn vector3f_to_rgb8(v: Vector3f) -> Rgb8 {
-    Rgb8::new( (v.x*255 as f32) as u8, 
-               (v.y*255 as f32) as u8,
-               (v.z*255 as f32) as u8)
+    Rgb8::new( (v.x*255.0) as u8, 
+               (v.y*255.0) as u8,
+               (v.z*255.0) as u8)
 }
 
 pub fn rgb8_to_vector3f(rgb: Rgb8) -> Vector3f {
@@ -46,13 +46,23 @@ pub fn rgb8_to_vector3f(rgb: Rgb8) -> Vector3f {
     )
 }
 
+/// luminance of a linear sRGB color
+pub fn luminance(color: &Vector3f) -> f32 {
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
+
+/// vector of the [x, y, z] arrays of the scene descriptions
+pub fn to_vector3f(v: [f32; 3]) -> Vector3f {
+    Vector3f::new(v[0], v[1], v[2])
+}
+
 pub fn normal3f_to_rgb8(v: Vector3f) -> Rgb8 {
-    Rgb8::new( ( (v.x/2.0+0.5)*255 as f32) as u8, 
-               ( (v.y/2.0+0.5)*255 as f32) as u8,
-               ( (v.z/2.0+0.5)*255 as f32) as u8)
+    Rgb8::new( ( (v.x/2.0+0.5)*255.0) as u8, 
+               ( (v.y/2.0+0.5)*255.0) as u8,
+               ( (v.z/2.0+0.5)*255.0) as u8)
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Size2<T>
 where
     T: 'static + std::cmp::PartialEq + std::clone::Clone + std::marker::Copy + std::fmt::Debug,
@@ -0,0 +1,150 @@
+use crate::types::Vector3f;
+use std::io::prelude::*;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+/// Dense grid of voxels with a density and, optionally, an emission value each.
+/// Values sit at the voxel centers, the grid spans [0, 1] on each axis
+#[derive(Clone, Debug, PartialEq)]
+pub struct VoxelGrid {
+    /// voxels along x, y and z
+    size: [usize; 3],
+    density: Vec<f32>,
+    emission: Option<Vec<f32>>,
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+impl VoxelGrid {
+
+    /// values are ordered x first, then y, then z. Panics if their count does not match the size
+    pub fn new(size: [usize; 3], density: Vec<f32>, emission: Option<Vec<f32>>) -> VoxelGrid {
+        let count = size[0] * size[1] * size[2];
+        assert!(density.len() == count && emission.as_ref().is_none_or(|emission| emission.len() == count),
+            "VoxelGrid needs a value per voxel");
+        VoxelGrid { size, density, emission }
+    }
+
+    pub fn size(&self) -> [usize; 3] {
+        self.size
+    }
+
+    pub fn max_density(&self) -> f32 {
+        self.density.iter().cloned().fold(0.0, f32::max)
+    }
+
+    pub fn has_emission(&self) -> bool {
+        self.emission.is_some()
+    }
+
+    /// trilinear interpolation of the density at a point of the unit cube
+    pub fn density_at(&self, point: &Vector3f) -> f32 {
+        self.interpolate(&self.density, point)
+    }
+
+    /// trilinear interpolation of the emission, 0 without an emission channel
+    pub fn emission_at(&self, point: &Vector3f) -> f32 {
+        self.emission.as_ref().map_or(0.0, |emission| self.interpolate(emission, point))
+    }
+
+    fn value(&self, values: &[f32], x: usize, y: usize, z: usize) -> f32 {
+        values[x + self.size[0] * (y + self.size[1] * z)]
+    }
+
+    fn interpolate(&self, values: &[f32], point: &Vector3f) -> f32 {
+        let mut lower = [0; 3];
+        let mut upper = [0; 3];
+        let mut fraction = [0.0; 3];
+        for axis in 0..3 {
+            // voxel centers are at (i + 0.5) / size, values are clamped at the borders
+            let coordinate = (point[axis] * self.size[axis] as f32 - 0.5).clamp(0.0, (self.size[axis] - 1) as f32);
+            lower[axis] = coordinate.floor() as usize;
+            upper[axis] = (lower[axis] + 1).min(self.size[axis] - 1);
+            fraction[axis] = coordinate - lower[axis] as f32;
+        }
+        let mut value = 0.0;
+        for corner in 0..8 {
+            let mut weight = 1.0;
+            let mut index = [0; 3];
+            for axis in 0..3 {
+                let is_upper = corner & (1 << axis) != 0;
+                index[axis] = if is_upper { upper[axis] } else { lower[axis] };
+                weight *= if is_upper { fraction[axis] } else { 1.0 - fraction[axis] };
+            }
+            value += weight * self.value(values, index[0], index[1], index[2]);
+        }
+        value
+    }
+
+}
+
+/// save the grid as a raw grid file: a "VOXG" line, a line with the size along x, y, z
+/// and the channel count (1 for density, 2 with emission), then little endian f32 values,
+/// the channels of a voxel together, x first, then y, then z
+pub fn save_grid(grid: &VoxelGrid, path: &Path) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    let channels = if grid.has_emission() { 2 } else { 1 };
+    write!(file, "VOXG\n{} {} {} {}\n", grid.size[0], grid.size[1], grid.size[2], channels)?;
+    let mut data = Vec::with_capacity(grid.density.len() * channels * 4);
+    for (index, density) in grid.density.iter().enumerate() {
+        data.extend_from_slice(&density.to_le_bytes());
+        if let Some(emission) = &grid.emission {
+            data.extend_from_slice(&emission[index].to_le_bytes());
+        }
+    }
+    file.write_all(&data)
+}
+
+pub fn load_grid(path: &Path) -> io::Result<VoxelGrid> {
+    let data = std::fs::read(path)?;
+    let mut lines = data.splitn(3, |byte| *byte == b'\n');
+    if lines.next() != Some(b"VOXG") {
+        return Err(invalid_data("not a VOXG grid file"));
+    }
+    let header = lines.next().and_then(|line| std::str::from_utf8(line).ok())
+        .ok_or_else(|| invalid_data("missing grid size"))?;
+    let numbers = header.split_whitespace().map(|number| number.parse::<usize>()).collect::<Result<Vec<_>, _>>()
+        .map_err(|_| invalid_data("invalid grid size"))?;
+    let (size, channels) = match numbers.as_slice() {
+        [x, y, z, channels @ (1 | 2)] if *x > 0 && *y > 0 && *z > 0 => ([*x, *y, *z], *channels),
+        _ => return Err(invalid_data("the grid size needs three sizes and 1 or 2 channels")),
+    };
+    let values : Vec<f32> = lines.next().unwrap_or(&[]).chunks_exact(4)
+        .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .collect();
+    if values.len() != size[0] * size[1] * size[2] * channels {
+        return Err(invalid_data("the grid file has not a value per voxel and channel"));
+    }
+    let density = values.iter().step_by(channels).cloned().collect();
+    let emission = if channels == 2 { Some(values.iter().skip(1).step_by(2).cloned().collect()) } else { None };
+    Ok(VoxelGrid::new(size, density, emission))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid_round_trip_and_interpolation() {
+        // density grows along x, emission only in the last voxel
+        let density = (0..8).map(|index| (index % 2) as f32).collect();
+        let emission = (0..8).map(|index| if index == 7 { 3.0 } else { 0.0 }).collect();
+        let grid = VoxelGrid::new([2, 2, 2], density, Some(emission));
+        let fname = Path::new("/tmp/grid_round_trip.voxg");
+        save_grid(&grid, fname).unwrap();
+        assert_eq!(load_grid(fname).unwrap(), grid);
+
+        assert_eq!(grid.density_at(&Vector3f::new(0.25, 0.5, 0.5)), 0.0);
+        assert_eq!(grid.density_at(&Vector3f::new(0.5, 0.1, 0.9)), 0.5);
+        assert_eq!(grid.density_at(&Vector3f::new(1.0, 0.5, 0.5)), 1.0);
+        assert_eq!(grid.emission_at(&Vector3f::new(0.9, 0.9, 0.9)), 3.0);
+        assert_eq!(grid.max_density(), 1.0);
+
+        std::fs::write(fname, b"VOXG\n2 2 2 1\n").unwrap();
+        assert!(load_grid(fname).is_err());
+    }
+}